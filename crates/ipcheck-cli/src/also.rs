@@ -0,0 +1,69 @@
+//! --also: 1 回の走査から --format 以外の形式も書き出す (FORMAT[:PATH][,OPTION=VALUE...])。
+//! OPTION はその形式が受け付けるオプション (OutputWriter::options) で、同名の --オプションや --format-option を上書きする
+
+use std::path::Path;
+
+use crate::output::{self, Metadata, OutputWriter};
+
+#[derive(Clone)]
+pub struct Also {
+    pub format: &'static dyn OutputWriter,
+    path: Option<String>,
+    options: Vec<(String, String)>,
+}
+
+impl std::str::FromStr for Also {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let head = parts.next().unwrap_or_default();
+        let (name, path) = match head.split_once(':') {
+            Some((name, path)) if !path.is_empty() => (name, Some(path.to_string())),
+            Some((name, _)) => (name, None),
+            None => (head, None),
+        };
+        let format = output::find(name).ok_or_else(|| format!("不明な出力形式です: {}", name))?;
+        let options = parts
+            .map(|option| {
+                let (key, value) = option.split_once('=').ok_or_else(|| format!("OPTION=VALUE の形式で指定してください: {}", option))?;
+                if !format.options().iter().any(|option| option.name == key) {
+                    let names: Vec<&str> = format.options().iter().map(|option| option.name).collect();
+                    return Err(format!("{} 形式のオプションではありません: {} (指定できるのは {})", name, key, names.join(", ")));
+                }
+                Ok((key.to_string(), value.to_string()))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Also { format, path, options })
+    }
+}
+
+impl Also {
+    /// 省略時は --output と同じ場所・名前で拡張子だけを変える
+    pub fn path(&self, output_path: &str) -> String {
+        match &self.path {
+            Some(path) => path.clone(),
+            None => Path::new(output_path).with_extension(self.format.extension()).display().to_string(),
+        }
+    }
+
+    pub fn metadata<'a>(&self, mut metadata: Metadata<'a>) -> Metadata<'a> {
+        metadata.options.extend(self.options.iter().cloned());
+        metadata
+    }
+}
+
+#[test]
+fn test_also_spec() {
+    let also: Also = "nft:/etc/nftables.d/foreign.nft,set-name=blocked".parse().unwrap();
+    assert_eq!(also.format.name(), "nft");
+    assert_eq!(also.path("out/list.json"), "/etc/nftables.d/foreign.nft");
+    let cli = <crate::Cli as clap::Parser>::parse_from(["ipcheck"]);
+    let metadata = also.metadata(crate::metadata(&cli, 0, &[]));
+    assert_eq!((metadata.option(also.format, "set-name"), metadata.option(also.format, "nft-table")), ("blocked", "inet filter"));
+
+    let also: Also = "text".parse().unwrap();
+    assert_eq!(also.path("out/list.json"), "out/list.txt");
+    assert!("nope:x".parse::<Also>().is_err());
+    assert!("nft:x,format=json".parse::<Also>().is_err());
+}
@@ -0,0 +1,43 @@
+use maxminddb::Reader;
+use serde::Deserialize;
+
+use crate::NetworkBlock;
+use crate::exceptions::networks_matching;
+
+/// GeoIP2 Anonymous-IP のレコード
+#[derive(Deserialize)]
+struct AnonymousRecord {
+    #[serde(default)]
+    is_anonymous_vpn: bool,
+    #[serde(default)]
+    is_hosting_provider: bool,
+    #[serde(default)]
+    is_tor_exit_node: bool,
+    #[serde(default)]
+    is_residential_proxy: bool,
+}
+
+/// 国に関係なくブロックする匿名化ネットワークの種類
+#[derive(Clone, Copy, Default)]
+pub struct AnonymousFlags {
+    pub vpn: bool,
+    pub hosting: bool,
+    pub tor: bool,
+    pub residential_proxy: bool,
+}
+
+impl AnonymousFlags {
+    pub fn any(&self) -> bool {
+        self.vpn || self.hosting || self.tor || self.residential_proxy
+    }
+}
+
+/// 指定した種類のいずれかに当たる IPv4 ネットワークを集める
+pub fn networks_flagged<S: AsRef<[u8]>>(reader: &Reader<S>, flags: AnonymousFlags) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    networks_matching(reader, |record: &AnonymousRecord| {
+        (flags.vpn && record.is_anonymous_vpn)
+            || (flags.hosting && record.is_hosting_provider)
+            || (flags.tor && record.is_tor_exit_node)
+            || (flags.residential_proxy && record.is_residential_proxy)
+    })
+}
@@ -0,0 +1,113 @@
+//! --apply: 生成したリストを nft / ipset / bpftool コマンドでそのままカーネルのセットやマップへ入れる
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use clap::ValueEnum;
+
+use crate::NetworkBlock;
+use crate::output::{self, Metadata, OutputWriter, bpf, ipset, nft};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Firewall {
+    /// nft -f で、セットの flush と追加を 1 トランザクションで適用する
+    Nft,
+    /// ipset restore で一時セットに入れてから swap で入れ替える
+    Ipset,
+    /// bpftool batch で XDP の LPM trie マップ (--bpf-map) を更新し、リストから外れたエントリを消す
+    Bpf,
+}
+
+/// ピン留めされたマップに今入っているエントリ
+fn bpf_entries(map: &str) -> Result<Vec<NetworkBlock>, String> {
+    let output = Command::new("bpftool")
+        .args(["-j", "map", "dump", "pinned", map])
+        .output()
+        .map_err(|e| format!("bpftool を実行できません: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("{} を読めません (XDP プログラムを読み込んでマップをピン留めしてください): {}", map, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let dump: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).map_err(|e| format!("bpftool の出力を解釈できません: {}", e))?;
+    dump.iter()
+        .map(|entry| {
+            let bytes: Option<Vec<u8>> = entry["key"]
+                .as_array()
+                .map(|key| key.iter().filter_map(|b| u8::from_str_radix(b.as_str()?.trim_start_matches("0x"), 16).ok()).collect());
+            bytes.as_deref().and_then(bpf::parse_key).ok_or_else(|| format!("{} のキーを解釈できません: {}", map, entry["key"]))
+        })
+        .collect()
+}
+
+impl Firewall {
+    /// 同じ名前の出力形式。セット名などのオプションとその既定値はこれに従う
+    fn writer(self) -> &'static dyn OutputWriter {
+        let name = match self {
+            Firewall::Nft => "nft",
+            Firewall::Ipset => "ipset",
+            Firewall::Bpf => "bpf",
+        };
+        output::find(name).expect("組み込みの形式です")
+    }
+
+    fn option<'a>(self, metadata: &'a Metadata, name: &str) -> &'a str {
+        metadata.option(self.writer(), name)
+    }
+
+    /// 実行するコマンドと、標準入力に渡すスクリプト
+    pub fn command(self, metadata: &Metadata, blocks: &[NetworkBlock]) -> Result<(&'static str, &'static [&'static str], String), String> {
+        Ok(match self {
+            Firewall::Nft => ("nft", &["-f", "-"], nft::full(self.option(metadata, "nft-table"), self.option(metadata, "set-name"), blocks)),
+            Firewall::Ipset => ("ipset", &["restore", "-exist"], ipset::swap(self.option(metadata, "set-name"), blocks)?),
+            Firewall::Bpf => ("bpftool", &["batch", "file", "-"], bpf::full(self.option(metadata, "bpf-map"), blocks)),
+        })
+    }
+
+    /// 適用先の名前 (表示用)
+    pub fn target<'a>(self, metadata: &'a Metadata) -> &'a str {
+        match self {
+            Firewall::Nft | Firewall::Ipset => self.option(metadata, "set-name"),
+            Firewall::Bpf => self.option(metadata, "bpf-map"),
+        }
+    }
+
+    pub fn apply(self, metadata: &Metadata, blocks: &[NetworkBlock]) -> Result<(), String> {
+        let (program, args, mut script) = self.command(metadata, blocks)?;
+        if let Firewall::Bpf = self {
+            // LPM trie は入れ替えられないので、全エントリを書き込んだ後にリストにないものを消す
+            let current: HashSet<&NetworkBlock> = blocks.iter().collect();
+            let map = self.option(metadata, "bpf-map");
+            let stale: Vec<NetworkBlock> = bpf_entries(map)?.into_iter().filter(|block| !current.contains(block)).collect();
+            script.push_str(&bpf::delta(map, &[], &stale));
+        }
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{} を実行できません: {}", program, e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(script.as_bytes()).map_err(|e| format!("{} への書き込みに失敗しました: {}", program, e))?;
+        }
+        let status = child.wait().map_err(|e| e.to_string())?;
+        if !status.success() {
+            return Err(format!("{} {} が失敗しました ({})", program, args.join(" "), status));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_apply_command() {
+    let blocks: Vec<NetworkBlock> = vec!["1.0.0.0/24".parse().unwrap()];
+    let cli = <crate::Cli as clap::Parser>::parse_from(["ipcheck", "--nft-table", "inet filter"]);
+    let metadata = crate::metadata(&cli, 0, &[]);
+    let (program, _, script) = Firewall::Ipset.command(&metadata, &blocks).unwrap();
+    assert_eq!(program, "ipset");
+    assert_eq!(
+        script.lines().filter(|l| !l.starts_with("create")).collect::<Vec<_>>(),
+        ["flush foreign-tmp", "add foreign-tmp 1.0.0.0/24", "swap foreign-tmp foreign", "destroy foreign-tmp"]
+    );
+    let (program, args, script) = Firewall::Nft.command(&metadata, &blocks).unwrap();
+    assert_eq!((program, args), ("nft", &["-f", "-"][..]));
+    assert!(script.ends_with("add element inet filter foreign { 1.0.0.0/24 }\n"));
+}
@@ -0,0 +1,15 @@
+use maxminddb::Reader;
+use serde::Deserialize;
+
+use crate::NetworkBlock;
+use crate::exceptions::networks_matching;
+
+#[derive(Deserialize)]
+struct AsnRecord {
+    autonomous_system_number: Option<u32>,
+}
+
+/// ASN データベースから、指定した AS 番号のいずれかに属する IPv4 ネットワークを集める
+pub fn networks_of<S: AsRef<[u8]>>(reader: &Reader<S>, asns: &[u32]) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    networks_matching(reader, |record: &AsnRecord| record.autonomous_system_number.is_some_and(|asn| asns.contains(&asn)))
+}
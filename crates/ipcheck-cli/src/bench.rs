@@ -0,0 +1,107 @@
+//! 集約方式の比較 (ipcheck bench)。既定の方式を選ぶためのデータを取る。
+//! メモリを数えるアロケータはすべての確保に余計な処理を足すので、bench-alloc 機能でビルドしたときだけ使う
+
+#[cfg(feature = "bench-alloc")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use ipcheck_core::{NetworkBlock, aggregate, aggregate_blocks};
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// 確保中のバイト数とその最大値を数えるアロケータ
+#[cfg(feature = "bench-alloc")]
+struct Counting;
+
+#[cfg(feature = "bench-alloc")]
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+#[cfg(feature = "bench-alloc")]
+fn allocated(size: usize) {
+    let now = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+#[cfg(feature = "bench-alloc")]
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new.is_null() {
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+            allocated(new_size);
+        }
+        new
+    }
+}
+
+pub type Strategy = fn(Vec<NetworkBlock>) -> Vec<NetworkBlock>;
+
+/// 比べる方式。先頭が生成で使っている方式
+pub const STRATEGIES: [(&str, Strategy); 3] = [
+    ("stack-merge", aggregate_blocks),
+    ("sweep", aggregate::sweep),
+    ("trie", aggregate::trie),
+];
+
+pub struct Measurement {
+    /// 各回の所要時間の中央値
+    pub median: Duration,
+    pub fastest: Duration,
+    /// 入力のコピーを除いて、集約中に追加で確保したメモリの最大値 (bench-alloc 機能なしでは測らない)
+    pub peak_bytes: Option<usize>,
+    pub entries: usize,
+    /// 1 行 1 CIDR のテキストにしたときのバイト数
+    pub text_bytes: usize,
+}
+
+/// blocks のコピーを iterations 回集約して測る。最後の結果も返す
+pub fn measure(strategy: Strategy, blocks: &[NetworkBlock], iterations: usize) -> (Measurement, Vec<NetworkBlock>) {
+    let mut times = Vec::new();
+    let mut peak_bytes = 0;
+    let mut output = Vec::new();
+    for _ in 0..iterations.max(1) {
+        let input = blocks.to_vec();
+        let base = CURRENT.load(Ordering::Relaxed);
+        PEAK.store(base, Ordering::Relaxed);
+        let start = Instant::now();
+        output = strategy(input);
+        times.push(start.elapsed());
+        peak_bytes = peak_bytes.max(PEAK.load(Ordering::Relaxed).saturating_sub(base));
+    }
+    times.sort();
+    let measurement = Measurement {
+        median: times[times.len() / 2],
+        fastest: times[0],
+        peak_bytes: cfg!(feature = "bench-alloc").then_some(peak_bytes),
+        entries: output.len(),
+        text_bytes: output.iter().map(|b| b.to_string().len() + 1).sum(),
+    };
+    (measurement, output)
+}
+
+#[test]
+fn test_measure() {
+    let blocks: Vec<NetworkBlock> = ["1.0.1.0/24", "1.0.0.0/24", "8.8.8.0/24"].iter().map(|c| c.parse().unwrap()).collect();
+    for (_, strategy) in STRATEGIES {
+        let (measurement, output) = measure(strategy, &blocks, 3);
+        assert_eq!(output.iter().map(|b| b.to_string()).collect::<Vec<_>>(), ["1.0.0.0/23", "8.8.8.0/24"]);
+        assert_eq!((measurement.entries, measurement.text_bytes), (2, 22));
+        assert!(measurement.fastest <= measurement.median);
+    }
+}
@@ -0,0 +1,65 @@
+//! sha256sum 互換の .sha256 ファイル
+
+use std::path::{Path, PathBuf};
+
+use crate::download::{file_sha256, sha256_hex};
+
+/// sha256sum と同じ「ハッシュ 2 つの空白 ファイル名」の 1 行
+pub fn line(data: &[u8], file_name: &str) -> String {
+    format!("{}  {}\n", sha256_hex(data), file_name)
+}
+
+/// .sha256 の各行をハッシュとファイル名に分ける (sha256sum -b の "*ファイル名" も読む)
+pub fn parse(text: &str) -> Result<Vec<(String, String)>, String> {
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| {
+            let (hash, name) = l.split_once(' ').ok_or_else(|| format!("書式が不正です: {}", l))?;
+            let name = name.strip_prefix(' ').or_else(|| name.strip_prefix('*')).unwrap_or(name);
+            if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(format!("SHA256 ではありません: {}", hash));
+            }
+            Ok((hash.to_ascii_lowercase(), name.to_string()))
+        })
+        .collect()
+}
+
+/// path の隣に path.sha256 を書き出す
+pub fn write(path: &Path) -> std::io::Result<PathBuf> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let sidecar = PathBuf::from(format!("{}.sha256", path.display()));
+    let tmp = PathBuf::from(format!("{}.tmp", sidecar.display()));
+    std::fs::write(&tmp, line(&std::fs::read(path)?, &name))?;
+    std::fs::rename(&tmp, &sidecar)?;
+    Ok(sidecar)
+}
+
+/// 検証したファイルと、一致しなかった場合の理由
+pub type Outcome = (PathBuf, Result<(), String>);
+
+/// .sha256 に書かれたファイル (.sha256 と同じディレクトリから探す) を検証し、ファイルごとの結果を返す
+pub fn verify(sidecar: &Path) -> Result<Vec<Outcome>, String> {
+    let text = std::fs::read_to_string(sidecar).map_err(|e| format!("{}: {}", sidecar.display(), e))?;
+    let dir = sidecar.parent().unwrap_or(Path::new(""));
+    Ok(parse(&text)?
+        .into_iter()
+        .map(|(expected, name)| {
+            let path = dir.join(&name);
+            let result = match file_sha256(&path) {
+                Ok(actual) if actual == expected => Ok(()),
+                Ok(actual) => Err(format!("SHA256 が一致しません (期待値 {}, 実際 {})", expected, actual)),
+                Err(e) => Err(e.to_string()),
+            };
+            (path, result)
+        })
+        .collect())
+}
+
+#[test]
+fn test_checksum_line() {
+    let line = line(b"1.0.0.0/24\n", "out.txt");
+    let entries = parse(&line).unwrap();
+    assert_eq!(entries, [(sha256_hex(b"1.0.0.0/24\n"), "out.txt".to_string())]);
+    assert_eq!(parse(&line.replace("  ", " *")).unwrap(), entries);
+    assert!(parse("abc  out.txt").is_err());
+}
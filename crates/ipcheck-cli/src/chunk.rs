@@ -0,0 +1,113 @@
+//! 登録件数に上限があるファイアウォールパネル (TCPShield やゲームサーバーのパネルなど) 向けに、リストを N 件ずつのファイルに分ける
+
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::NetworkBlock;
+use crate::download::sha256_hex;
+use crate::output;
+
+#[derive(Serialize)]
+pub struct ChunkFile {
+    pub name: String,
+    pub entries: usize,
+    /// 先頭と末尾の CIDR。パネル側の既存エントリと突き合わせるときに使う
+    pub first: String,
+    pub last: String,
+    pub sha256: String,
+}
+
+/// <prefix>.index.json の内容。自動化スクリプトはこれを読んで順にアップロードする
+#[derive(Serialize)]
+pub struct Index {
+    pub schema_version: u32,
+    /// 出力に記録する生成日時 (UNIX 時刻)。0 なら記録しない
+    #[serde(skip_serializing_if = "is_zero")]
+    pub generated_at: u64,
+    pub total_entries: usize,
+    pub chunk_size: usize,
+    pub chunks: Vec<ChunkFile>,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// n 番目 (1 始まり) のファイル名。件数が増えても名前順とチャンク順が一致するよう 3 桁以上で揃える
+pub fn file_name(prefix: &str, n: usize, count: usize) -> String {
+    format!("{}-{:0width$}.txt", prefix, n, width = count.to_string().len().max(3))
+}
+
+/// 1 行 1 CIDR のテキストを size 件ずつに分け、ファイル名と本文を返す
+pub fn split(prefix: &str, blocks: &[NetworkBlock], size: usize) -> Vec<(String, String)> {
+    let count = blocks.len().div_ceil(size);
+    blocks
+        .chunks(size)
+        .enumerate()
+        .map(|(i, chunk)| (file_name(prefix, i + 1, count), chunk.iter().map(|b| format!("{}\n", b.to_string())).collect()))
+        .collect()
+}
+
+/// 前回の索引に載っているチャンクのファイル名。索引がなければ空
+fn previous_chunks(path: &Path, prefix: &str) -> Vec<String> {
+    let Ok(text) = std::fs::read_to_string(path) else { return Vec::new() };
+    let Ok(index) = serde_json::from_str::<serde_json::Value>(&text) else { return Vec::new() };
+    index["chunks"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|chunk| chunk["name"].as_str())
+        // 索引を書き換えられても dir の外や別のファイルは消さない
+        .filter(|name| !name.contains(['/', '\\']) && name.starts_with(&format!("{}-", prefix)) && name.ends_with(".txt"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// dir にチャンクと索引を書き出す。前回より数が減った場合は、前回の索引に載っていて今回は書かなかったチャンクを消す
+pub fn write(dir: &Path, prefix: &str, blocks: &[NetworkBlock], size: usize, generated_at: u64) -> Result<Index, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.index.json", prefix));
+    let previous = previous_chunks(&path, prefix);
+    let files = split(prefix, blocks, size);
+    let mut chunks = Vec::new();
+    for ((name, body), chunk) in files.iter().zip(blocks.chunks(size)) {
+        std::fs::write(dir.join(name), body)?;
+        chunks.push(ChunkFile {
+            name: name.clone(),
+            entries: chunk.len(),
+            first: chunk[0].to_string(),
+            last: chunk[chunk.len() - 1].to_string(),
+            sha256: sha256_hex(body.as_bytes()),
+        });
+    }
+    for name in previous.iter().filter(|name| files.iter().all(|(written, _)| written != *name)) {
+        match std::fs::remove_file(dir.join(name)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    let index = Index { schema_version: output::SCHEMA_VERSION, generated_at, total_entries: blocks.len(), chunk_size: size, chunks };
+    let tmp = dir.join(format!(".{}.index.json.tmp", prefix));
+    std::fs::write(&tmp, serde_json::to_string_pretty(&index)? + "\n")?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(index)
+}
+
+#[test]
+fn test_split_chunks() {
+    let blocks: Vec<NetworkBlock> = ["1.0.0.0/24", "2.0.0.0/24", "3.0.0.0/24"].iter().map(|c| c.parse().unwrap()).collect();
+    let files = split("foreign", &blocks, 2);
+    assert_eq!(files, [("foreign-001.txt".to_string(), "1.0.0.0/24\n2.0.0.0/24\n".to_string()), ("foreign-002.txt".to_string(), "3.0.0.0/24\n".to_string())]);
+    assert_eq!(file_name("foreign", 12, 1200), "foreign-0012.txt");
+    assert!(split("foreign", &[], 2).is_empty());
+
+    // 古いチャンクは前回の索引に載っているものだけを消す
+    let dir = std::env::temp_dir().join(format!("ipcheck-chunk-{}", std::process::id()));
+    write(&dir, "foreign", &blocks, 1, 0).unwrap();
+    std::fs::write(dir.join("foreign-2024.txt"), "unrelated\n").unwrap();
+    write(&dir, "foreign", &blocks, 2, 0).unwrap();
+    assert!(!dir.join("foreign-003.txt").exists());
+    assert!(dir.join("foreign-002.txt").exists() && dir.join("foreign-2024.txt").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
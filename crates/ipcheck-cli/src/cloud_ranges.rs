@@ -0,0 +1,155 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::NetworkBlock;
+
+pub const AWS_IP_RANGES: &str = "https://ip-ranges.amazonaws.com/ip-ranges.json";
+pub const GCP_IP_RANGES: &str = "https://www.gstatic.com/ipranges/cloud.json";
+pub const CLOUDFLARE_IPS_V4: &str = "https://www.cloudflare.com/ips-v4";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Provider {
+    Aws,
+    Gcp,
+    /// Service Tags の JSON は URL が毎週変わるので --azure-service-tags で指定する
+    Azure,
+    Cloudflare,
+}
+
+/// 公開されているレンジ 1 件
+pub struct CloudRange {
+    pub block: NetworkBlock,
+    pub service: String,
+    pub region: String,
+}
+
+/// --allow-cloud の値。"プロバイダ[:サービス[:リージョン]]" で、省略または "*" はすべてに一致する
+#[derive(Clone)]
+pub struct CloudSelector {
+    pub provider: Provider,
+    service: Option<String>,
+    region: Option<String>,
+}
+
+impl FromStr for CloudSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let provider = match parts.next().unwrap_or_default().to_ascii_lowercase().as_str() {
+            "aws" => Provider::Aws,
+            "gcp" | "google" => Provider::Gcp,
+            "azure" => Provider::Azure,
+            "cloudflare" => Provider::Cloudflare,
+            other => return Err(format!("プロバイダは aws, gcp, azure, cloudflare のいずれかです: {}", other)),
+        };
+        let mut pattern = || parts.next().filter(|p| !p.is_empty() && *p != "*").map(str::to_string);
+        let (service, region) = (pattern(), pattern());
+        if parts.next().is_some() {
+            return Err(format!("プロバイダ:サービス:リージョン の形式で指定してください: {}", s));
+        }
+        Ok(CloudSelector { provider, service, region })
+    }
+}
+
+impl CloudSelector {
+    pub fn matches(&self, range: &CloudRange) -> bool {
+        let matches = |pattern: &Option<String>, value: &str| pattern.as_ref().is_none_or(|p| p.eq_ignore_ascii_case(value));
+        matches(&self.service, &range.service) && matches(&self.region, &range.region)
+    }
+}
+
+/// IPv6 のプレフィックスは読み飛ばす
+fn ipv4_range(prefix: &str, service: &str, region: &str) -> Option<CloudRange> {
+    let block = prefix.parse().ok().filter(|_| !prefix.contains(':'))?;
+    Some(CloudRange { block, service: service.to_string(), region: region.to_string() })
+}
+
+/// AWS の ip-ranges.json
+pub fn parse_aws(json: &[u8]) -> Result<Vec<CloudRange>, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct Prefix {
+        ip_prefix: String,
+        region: String,
+        service: String,
+    }
+    #[derive(Deserialize)]
+    struct IpRanges {
+        prefixes: Vec<Prefix>,
+    }
+    let ranges: IpRanges = serde_json::from_slice(json)?;
+    Ok(ranges.prefixes.iter().filter_map(|p| ipv4_range(&p.ip_prefix, &p.service, &p.region)).collect())
+}
+
+/// Google Cloud の cloud.json (scope がリージョン)
+pub fn parse_gcp(json: &[u8]) -> Result<Vec<CloudRange>, serde_json::Error> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Prefix {
+        ipv4_prefix: Option<String>,
+        #[serde(default)]
+        service: String,
+        #[serde(default)]
+        scope: String,
+    }
+    #[derive(Deserialize)]
+    struct IpRanges {
+        prefixes: Vec<Prefix>,
+    }
+    let ranges: IpRanges = serde_json::from_slice(json)?;
+    Ok(ranges.prefixes.iter().filter_map(|p| ipv4_range(p.ipv4_prefix.as_deref()?, &p.service, &p.scope)).collect())
+}
+
+/// Azure の Service Tags (ServiceTags_Public_*.json)。サービスは systemService、なければタグ名
+pub fn parse_azure(json: &[u8]) -> Result<Vec<CloudRange>, serde_json::Error> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Properties {
+        #[serde(default)]
+        region: String,
+        #[serde(default)]
+        system_service: String,
+        address_prefixes: Vec<String>,
+    }
+    #[derive(Deserialize)]
+    struct Value {
+        name: String,
+        properties: Properties,
+    }
+    #[derive(Deserialize)]
+    struct ServiceTags {
+        values: Vec<Value>,
+    }
+    let tags: ServiceTags = serde_json::from_slice(json)?;
+    let mut ranges = Vec::new();
+    for tag in &tags.values {
+        let props = &tag.properties;
+        let service = if props.system_service.is_empty() { &tag.name } else { &props.system_service };
+        ranges.extend(props.address_prefixes.iter().filter_map(|prefix| ipv4_range(prefix, service, &props.region)));
+    }
+    Ok(ranges)
+}
+
+/// Cloudflare の ips-v4 (1 行 1 CIDR。サービス・リージョンの区別はない)
+pub fn parse_cloudflare(text: &str) -> Vec<CloudRange> {
+    text.lines().map(str::trim).filter_map(|line| ipv4_range(line, "", "")).collect()
+}
+
+#[test]
+fn test_cloud_selector() {
+    let json = br#"{"prefixes": [
+        {"ip_prefix": "3.5.140.0/22", "region": "ap-northeast-2", "service": "S3"},
+        {"ip_prefix": "13.34.37.64/27", "region": "ap-southeast-4", "service": "EC2"}
+    ], "ipv6_prefixes": []}"#;
+    let ranges = parse_aws(json).unwrap();
+    let selected = |selector: &str| {
+        let selector: CloudSelector = selector.parse().unwrap();
+        ranges.iter().filter(|r| selector.matches(r)).count()
+    };
+    assert_eq!(selected("aws"), 2);
+    assert_eq!(selected("aws:s3"), 1);
+    assert_eq!(selected("aws:*:ap-southeast-4"), 1);
+    assert_eq!(selected("aws:EC2:ap-northeast-2"), 0);
+    assert!("oracle".parse::<CloudSelector>().is_err());
+}
@@ -0,0 +1,105 @@
+//! --config: 1 つの設定ファイルに名前付きのプロファイルを並べ、--profile で選んで実行する
+//!
+//! ```text
+//! # セクションより前の行は全プロファイル共通
+//! db = /var/lib/GeoIP/GeoLite2-Country.mmdb
+//!
+//! [strict]
+//! format = nft
+//! output = /etc/nftables.d/foreign.nft
+//! apply = nft
+//!
+//! [web]
+//! block-asn = 4134
+//! format = json
+//! output = /srv/www/foreign.json
+//! checksum
+//! ```
+//!
+//! 各行はコマンドラインの長いオプションになる (`キー = 値` は `--キー 値`、`キー` だけなら `--キー`)。
+//! 複数指定できるオプションは行を繰り返す。値を 1 つだけとるオプションは、コマンドラインの指定がプロファイルより、
+//! プロファイルが共通の設定より優先する。複数指定できるオプション (--block-asn, --also, --deploy, --source など) は
+//! 置き換わらず、共通の設定・プロファイル・コマンドラインの順にすべて足し合わされる
+
+use std::path::Path;
+
+pub struct Config {
+    common: Vec<String>,
+    profiles: Vec<(String, Vec<String>)>,
+}
+
+impl std::str::FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Config { common: Vec::new(), profiles: Vec::new() };
+        for (number, line) in s.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                let name = name.trim();
+                if name.is_empty() || config.profiles.iter().any(|(existing, _)| existing == name) {
+                    return Err(format!("{} 行目: プロファイル名が空か重複しています: {}", number, line));
+                }
+                config.profiles.push((name.to_string(), Vec::new()));
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (line, None),
+            };
+            if key.is_empty() || !key.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-') {
+                return Err(format!("{} 行目: オプション名が不正です: {}", number, key));
+            }
+            if key == "config" || key == "profile" {
+                return Err(format!("{} 行目: 設定ファイルの中では {} を指定できません", number, key));
+            }
+            let args = match config.profiles.last_mut() {
+                Some((_, args)) => args,
+                None => &mut config.common,
+            };
+            args.push(format!("--{}", key));
+            if let Some(value) = value {
+                // 前後の空白を残したい値は "..." で囲む
+                let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+                args.push(value.to_string());
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl Config {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        text.parse().map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// 共通の設定に profile の設定を続けた引数。profile が None なら共通の設定だけ
+    pub fn args(&self, profile: Option<&str>) -> Result<Vec<String>, String> {
+        let mut args = self.common.clone();
+        if let Some(name) = profile {
+            let (_, profile_args) = self.profiles.iter().find(|(existing, _)| existing == name).ok_or_else(|| {
+                let names: Vec<&str> = self.profiles.iter().map(|(name, _)| name.as_str()).collect();
+                format!("プロファイル {} がありません (あるのは {})", name, names.join(", "))
+            })?;
+            args.extend(profile_args.iter().cloned());
+        }
+        Ok(args)
+    }
+}
+
+#[test]
+fn test_config_profiles() {
+    let config: Config = "db = GeoLite2-Country.mmdb\n\n[strict]\nformat = nft\n# コメント\nchecksum\n[web]\nblock-asn = 4134\nblock-asn = 4837\nset-name = \" spaced \"\n"
+        .parse()
+        .unwrap();
+    assert_eq!(config.args(None).unwrap(), ["--db", "GeoLite2-Country.mmdb"]);
+    assert_eq!(config.args(Some("strict")).unwrap(), ["--db", "GeoLite2-Country.mmdb", "--format", "nft", "--checksum"]);
+    assert_eq!(config.args(Some("web")).unwrap()[2..], ["--block-asn", "4134", "--block-asn", "4837", "--set-name", " spaced "]);
+    assert!(config.args(Some("mail")).unwrap_err().contains("strict, web"));
+    assert!("[a]\n[a]\n".parse::<Config>().is_err());
+    assert!("profile = x\n".parse::<Config>().is_err());
+    assert!("--db x\n".parse::<Config>().is_err());
+}
@@ -0,0 +1,40 @@
+use clap::ValueEnum;
+use maxminddb::Reader;
+use serde::Deserialize;
+
+use crate::NetworkBlock;
+use crate::exceptions::networks_matching;
+
+/// GeoIP2 Connection-Type のレコード
+#[derive(Deserialize)]
+struct ConnectionTypeRecord {
+    connection_type: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConnectionType {
+    /// 携帯回線 (ローミング中の利用者は海外に位置する)
+    Cellular,
+    CableDsl,
+    Corporate,
+    Satellite,
+}
+
+impl ConnectionType {
+    /// データベース上の表記
+    fn name(&self) -> &'static str {
+        match self {
+            ConnectionType::Cellular => "Cellular",
+            ConnectionType::CableDsl => "Cable/DSL",
+            ConnectionType::Corporate => "Corporate",
+            ConnectionType::Satellite => "Satellite",
+        }
+    }
+}
+
+/// 指定した回線種別のいずれかに当たる IPv4 ネットワークを集める
+pub fn networks_of_types<S: AsRef<[u8]>>(reader: &Reader<S>, types: &[ConnectionType]) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    networks_matching(reader, |record: &ConnectionTypeRecord| {
+        record.connection_type.as_deref().is_some_and(|name| types.iter().any(|t| t.name() == name))
+    })
+}
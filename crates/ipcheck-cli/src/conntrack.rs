@@ -0,0 +1,62 @@
+//! --flush-conntrack: 適用した後も残っている、新しく遮断した範囲からの確立済みの接続を conntrack から消す
+
+use std::net::Ipv4Addr;
+use std::process::Command;
+
+use crate::{CidrSet, NetworkBlock};
+
+/// conntrack -L の 1 行から、元の方向の送信元アドレス (最初の src=) を取り出す
+fn source(line: &str) -> Option<Ipv4Addr> {
+    line.split_whitespace().find_map(|field| field.strip_prefix("src="))?.parse().ok()
+}
+
+/// 一覧のうち、今回のリストに含まれ前回のリストには含まれていなかった送信元 (重複なし)
+fn newly_blocked(listing: &str, current: &CidrSet, previous: Option<&CidrSet>) -> Vec<Ipv4Addr> {
+    let mut sources: Vec<Ipv4Addr> = listing
+        .lines()
+        .filter_map(source)
+        .filter(|ip| current.contains(*ip) && !previous.is_some_and(|previous| previous.contains(*ip)))
+        .collect();
+    sources.sort_unstable();
+    sources.dedup();
+    sources
+}
+
+/// 新しく遮断した範囲からの接続を消し、消した送信元の数を返す。前回のリストがなければリスト全体を対象にする
+pub fn flush(blocks: &[NetworkBlock], previous: Option<&[NetworkBlock]>) -> Result<usize, String> {
+    let output = Command::new("conntrack")
+        .args(["-L", "-f", "ipv4"])
+        .output()
+        .map_err(|e| format!("conntrack を実行できません: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("conntrack -L が失敗しました: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    // どちらも書き出したリストのまま使う (/24 に丸めると前回も遮断していたアドレスを取り違える)
+    let current = CidrSet::from_aggregated(blocks.to_vec());
+    let previous = previous.map(|blocks| CidrSet::from_aggregated(blocks.to_vec()));
+    let sources = newly_blocked(&String::from_utf8_lossy(&output.stdout), &current, previous.as_ref());
+    let mut flushed = 0;
+    for ip in sources {
+        // 一覧の取得後に閉じた接続は消せずに失敗するので、エラーにはしない
+        let status = Command::new("conntrack").args(["-D", "-s", &ip.to_string()]).output().map_err(|e| format!("conntrack を実行できません: {}", e))?;
+        if status.status.success() {
+            flushed += 1;
+        }
+    }
+    Ok(flushed)
+}
+
+#[test]
+fn test_newly_blocked_sources() {
+    let listing = "tcp      6 431999 ESTABLISHED src=1.0.0.5 dst=192.0.2.1 sport=50000 dport=22 src=192.0.2.1 dst=1.0.0.5 sport=22 dport=50000 [ASSURED] mark=0 use=1\n\
+                   tcp      6 431999 ESTABLISHED src=1.0.0.5 dst=192.0.2.1 sport=50001 dport=443 src=192.0.2.1 dst=1.0.0.5 sport=443 dport=50001 [ASSURED] mark=0 use=1\n\
+                   udp      17 29 src=8.8.8.8 dst=192.0.2.1 sport=53 dport=40000 [UNREPLIED] src=192.0.2.1 dst=8.8.8.8 sport=40000 dport=53 mark=0 use=1\n\
+                   tcp      6 100 TIME_WAIT src=192.0.2.9 dst=1.0.0.5 sport=1 dport=2 src=1.0.0.5 dst=192.0.2.9 sport=2 dport=1 mark=0 use=1\n";
+    let current = CidrSet::from_blocks(vec!["1.0.0.0/24".parse().unwrap(), "8.0.0.0/8".parse().unwrap()]);
+    let previous = CidrSet::from_blocks(vec!["8.0.0.0/8".parse().unwrap()]);
+    assert_eq!(newly_blocked(listing, &current, Some(&previous)), [Ipv4Addr::new(1, 0, 0, 5)]);
+    assert_eq!(newly_blocked(listing, &current, None), [Ipv4Addr::new(1, 0, 0, 5), Ipv4Addr::new(8, 8, 8, 8)]);
+    // 前回のリストの /32 もそのまま前回遮断していたものとして扱う
+    let previous = CidrSet::from_aggregated(vec!["1.0.0.5/32".parse().unwrap(), "8.8.8.0/25".parse().unwrap()]);
+    assert!(newly_blocked(listing, &current, Some(&previous)).is_empty());
+}
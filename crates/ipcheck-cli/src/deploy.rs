@@ -0,0 +1,144 @@
+//! 生成した出力を ssh でルーターなどへ配置する。認証は ssh コマンドに任せる (鍵や ~/.ssh/config の設定がそのまま効く)
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// ssh://[ユーザー@]ホスト[:ポート]/ディレクトリ
+#[derive(Clone)]
+pub struct Target {
+    host: String,
+    port: Option<u16>,
+    dir: String,
+}
+
+impl std::str::FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s.strip_prefix("ssh://").ok_or_else(|| format!("ssh://[ユーザー@]ホスト[:ポート]/ディレクトリ の形式で指定してください: {}", s))?;
+        let (authority, dir) = rest.split_once('/').ok_or_else(|| format!("配置先のディレクトリがありません: {}", s))?;
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port.parse().map_err(|_| format!("ポート番号が不正です: {}", port))?)),
+            None => (authority, None),
+        };
+        if host.is_empty() || host.ends_with('@') {
+            return Err(format!("ホスト名がありません: {}", s));
+        }
+        if host.starts_with('-') {
+            return Err(format!("ホスト名が不正です: {}", s));
+        }
+        // ssh://host/path は /path、ssh://host/~/path はホームからの相対パス
+        let dir = match dir.strip_prefix('~') {
+            Some(relative) => relative.trim_matches('/').to_string(),
+            None => format!("/{}", dir.trim_end_matches('/')),
+        };
+        Ok(Target { host: host.to_string(), port, dir: if dir.is_empty() { ".".to_string() } else { dir } })
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ssh://{}", self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        match self.dir.as_str() {
+            "." => write!(f, "/~"),
+            dir if dir.starts_with('/') => write!(f, "{}", dir),
+            dir => write!(f, "/~/{}", dir),
+        }
+    }
+}
+
+/// リモートのシェルに渡す 1 語
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+impl Target {
+    fn remote_path(&self, name: &str) -> String {
+        format!("{}/{}", self.dir.trim_end_matches('/'), name)
+    }
+
+    /// 一時ファイルへ書き込む。途中で切れても配置済みのファイルは壊れない
+    fn upload_command(&self, name: &str) -> String {
+        format!("cat > {}", quote(&self.remote_path(&format!(".{}.tmp", name))))
+    }
+
+    /// 全ファイルを送り終えてから、一時ファイルを rename で置き換えて reload を実行する
+    fn publish_command(&self, names: &[String], reload: Option<&str>) -> String {
+        let mut commands: Vec<String> = names
+            .iter()
+            .map(|name| format!("mv -f {} {}", quote(&self.remote_path(&format!(".{}.tmp", name))), quote(&self.remote_path(name))))
+            .collect();
+        commands.extend(reload.map(str::to_string));
+        commands.join(" && ")
+    }
+
+    fn ssh(&self, remote_command: &str, stdin: Option<&[u8]>) -> Result<(), String> {
+        let mut command = Command::new("ssh");
+        // パスワードの入力待ちで止まらないようにする
+        command.args(["-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            command.args(["-p", &port.to_string()]);
+        }
+        command.args([self.host.as_str(), remote_command]);
+        command.stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() });
+        let mut child = command.spawn().map_err(|e| format!("ssh を実行できません: {}", e))?;
+        if let (Some(data), Some(mut pipe)) = (stdin, child.stdin.take()) {
+            pipe.write_all(data).map_err(|e| format!("{}: 送信に失敗しました: {}", self, e))?;
+        }
+        let status = child.wait().map_err(|e| e.to_string())?;
+        if !status.success() {
+            return Err(format!("{}: リモートコマンドが失敗しました ({}): {}", self, status, remote_command));
+        }
+        Ok(())
+    }
+
+    /// files を配置先のディレクトリへ同じ名前で送り、reload があれば実行する
+    pub fn deploy(&self, files: &[PathBuf], reload: Option<&str>) -> Result<(), String> {
+        let mut names = Vec::new();
+        for file in files {
+            let name = file.file_name().ok_or_else(|| format!("ファイル名がありません: {}", file.display()))?.to_string_lossy().into_owned();
+            let data = std::fs::read(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+            self.ssh(&self.upload_command(&name), Some(&data))?;
+            names.push(name);
+        }
+        self.ssh(&self.publish_command(&names, reload), None)
+    }
+}
+
+/// 今回書き出した output と、その署名・チェックサム・.bin (前回の残りは送らない)
+pub fn artifacts(output: &Path, signed: bool, checksum: bool, binary: bool) -> Vec<PathBuf> {
+    let bin = output.with_extension("bin");
+    let mut files = Vec::new();
+    for path in std::iter::once(output).chain(binary.then_some(bin.as_path())) {
+        files.push(path.to_path_buf());
+        for (enabled, suffix) in [(signed, ".minisig"), (checksum, ".sha256")] {
+            if enabled {
+                files.push(PathBuf::from(format!("{}{}", path.display(), suffix)));
+            }
+        }
+    }
+    files
+}
+
+#[test]
+fn test_deploy_target() {
+    let target: Target = "ssh://admin@router1:2222/etc/ipcheck/".parse().unwrap();
+    assert_eq!(target.to_string(), "ssh://admin@router1:2222/etc/ipcheck");
+    assert_eq!(target.upload_command("list.txt"), "cat > '/etc/ipcheck/.list.txt.tmp'");
+    assert_eq!(
+        target.publish_command(&["list.txt".to_string(), "list's.sha256".to_string()], Some("nft -f /etc/nftables.conf")),
+        r"mv -f '/etc/ipcheck/.list.txt.tmp' '/etc/ipcheck/list.txt' && mv -f '/etc/ipcheck/.list'\''s.sha256.tmp' '/etc/ipcheck/list'\''s.sha256' && nft -f /etc/nftables.conf"
+    );
+    let home: Target = "ssh://router1/~/lists".parse().unwrap();
+    assert_eq!(home.remote_path("a.txt"), "lists/a.txt");
+    assert_eq!(home.to_string(), "ssh://router1/~/lists");
+    assert!("ssh://router1".parse::<Target>().is_err());
+    assert!("scp://router1/tmp".parse::<Target>().is_err());
+
+    let files = artifacts(Path::new("out/list.txt"), false, true, true);
+    assert_eq!(files, [PathBuf::from("out/list.txt"), "out/list.txt.sha256".into(), "out/list.bin".into(), "out/list.bin.sha256".into()]);
+}
@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+
+use crate::binary::{ranges, subtract};
+use crate::{NetworkBlock, range_to_blocks};
+
+pub struct ListDiff {
+    pub added: Vec<NetworkBlock>,
+    pub removed: Vec<NetworkBlock>,
+    pub old_addresses: u64,
+    pub new_addresses: u64,
+    pub added_addresses: u64,
+    pub removed_addresses: u64,
+}
+
+impl ListDiff {
+    pub fn percent_change(&self) -> f64 {
+        if self.old_addresses == 0 {
+            return if self.new_addresses == 0 { 0.0 } else { 100.0 };
+        }
+        (self.new_addresses as f64 - self.old_addresses as f64) * 100.0 / self.old_addresses as f64
+    }
+}
+
+fn range_size(ranges: &[(u32, u32)]) -> u64 {
+    ranges.iter().map(|(start, end)| *end as u64 - *start as u64 + 1).sum()
+}
+
+/// 2 つのリストの差分をアドレス空間として計算し、追加分・削除分を最小の CIDR で返す
+pub fn diff(old: &[NetworkBlock], new: &[NetworkBlock]) -> ListDiff {
+    let old_ranges = ranges(old);
+    let new_ranges = ranges(new);
+    let added = subtract(&new_ranges, &old_ranges);
+    let removed = subtract(&old_ranges, &new_ranges);
+
+    ListDiff {
+        old_addresses: range_size(&old_ranges),
+        new_addresses: range_size(&new_ranges),
+        added_addresses: range_size(&added),
+        removed_addresses: range_size(&removed),
+        added: added.iter().flat_map(|&(s, e)| range_to_blocks(s, e)).collect(),
+        removed: removed.iter().flat_map(|&(s, e)| range_to_blocks(s, e)).collect(),
+    }
+}
+
+/// エントリ単位の差分 (新しいリストにだけあるもの, 古いリストにだけあるもの)
+pub fn entry_delta(old: &[NetworkBlock], new: &[NetworkBlock]) -> (Vec<NetworkBlock>, Vec<NetworkBlock>) {
+    let old_set: HashSet<&NetworkBlock> = old.iter().collect();
+    let new_set: HashSet<&NetworkBlock> = new.iter().collect();
+    let mut added: Vec<NetworkBlock> = new.iter().filter(|b| !old_set.contains(b)).copied().collect();
+    let mut removed: Vec<NetworkBlock> = old.iter().filter(|b| !new_set.contains(b)).copied().collect();
+    added.sort_by_key(|b| (b.network, b.prefix_len));
+    removed.sort_by_key(|b| (b.network, b.prefix_len));
+    added.dedup();
+    removed.dedup();
+    (added, removed)
+}
+
+#[test]
+fn test_diff_address_space() {
+    let old: Vec<NetworkBlock> = ["10.0.0.0/8", "192.168.0.0/24"].iter().map(|s| s.parse().unwrap()).collect();
+    let new: Vec<NetworkBlock> = ["10.0.0.0/9", "192.168.0.0/23"].iter().map(|s| s.parse().unwrap()).collect();
+    let d = diff(&old, &new);
+    let added: Vec<String> = d.added.iter().map(|b| b.to_string()).collect();
+    let removed: Vec<String> = d.removed.iter().map(|b| b.to_string()).collect();
+    assert_eq!(added, vec!["192.168.1.0/24"]);
+    assert_eq!(removed, vec!["10.128.0.0/9"]);
+}
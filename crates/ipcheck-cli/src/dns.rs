@@ -0,0 +1,109 @@
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_IXFR: u16 = 251;
+pub const TYPE_AXFR: u16 = 252;
+pub const TYPE_ANY: u16 = 255;
+pub const CLASS_IN: u16 = 1;
+
+pub const RCODE_FORMERR: u16 = 1;
+pub const RCODE_NXDOMAIN: u16 = 3;
+pub const RCODE_NOTIMP: u16 = 4;
+pub const RCODE_REFUSED: u16 = 5;
+
+pub struct Question {
+    /// 小文字化し、末尾のドットを除いた名前
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+    /// 質問セクションの生バイト列
+    pub raw: Vec<u8>,
+    /// 質問セクションの直後の位置
+    pub end: usize,
+}
+
+pub fn parse_question(packet: &[u8]) -> Option<Question> {
+    let mut labels = Vec::new();
+    let mut pos = 12;
+    loop {
+        let len = *packet.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        // 質問セクションで名前圧縮は使われない
+        if len > 63 {
+            return None;
+        }
+        labels.push(std::str::from_utf8(packet.get(pos..pos + len)?).ok()?.to_ascii_lowercase());
+        pos += len;
+    }
+    let fixed = packet.get(pos..pos + 4)?;
+    Some(Question {
+        name: labels.join("."),
+        qtype: u16::from_be_bytes([fixed[0], fixed[1]]),
+        qclass: u16::from_be_bytes([fixed[2], fixed[3]]),
+        raw: packet[12..pos + 4].to_vec(),
+        end: pos + 4,
+    })
+}
+
+/// 圧縮されている可能性のある名前を読み飛ばし、直後の位置を返す
+pub fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            l if l & 0xC0 == 0xC0 => return Some(pos + 2),
+            l => pos += 1 + l as usize,
+        }
+    }
+}
+
+pub fn encode_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.').filter(|l| !l.is_empty()) {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+}
+
+/// リソースレコードを組み立てる。owner が None なら質問の名前を指す
+pub fn record(owner: Option<&str>, rtype: u16, ttl: u32, rdata: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(rdata.len() + 16);
+    match owner {
+        Some(name) => encode_name(&mut out, name),
+        None => out.extend_from_slice(&[0xC0, 0x0C]),
+    }
+    out.extend_from_slice(&rtype.to_be_bytes());
+    out.extend_from_slice(&CLASS_IN.to_be_bytes());
+    out.extend_from_slice(&ttl.to_be_bytes());
+    out.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    out.extend_from_slice(rdata);
+    out
+}
+
+pub fn header_flags(query: &[u8]) -> u16 {
+    u16::from_be_bytes([query[2], query[3]])
+}
+
+/// 問い合わせに対する応答パケットを作る。query は少なくとも 12 バイトのヘッダを持つこと
+pub fn reply(query: &[u8], rcode: u16, question: Option<&Question>, answers: &[Vec<u8>]) -> Vec<u8> {
+    let flags = header_flags(query);
+    let mut out = Vec::with_capacity(512);
+    out.extend_from_slice(&query[..2]);
+    // QR, AA, 受け取った OPCODE と RD をそのまま返す
+    out.extend_from_slice(&(0x8400 | (flags & 0x7900) | rcode).to_be_bytes());
+    out.extend_from_slice(&(question.is_some() as u16).to_be_bytes());
+    out.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+    out.extend_from_slice(&[0, 0, 0, 0]);
+    if let Some(question) = question {
+        out.extend_from_slice(&question.raw);
+    }
+    for answer in answers {
+        out.extend_from_slice(answer);
+    }
+    out
+}
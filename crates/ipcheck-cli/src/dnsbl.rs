@@ -0,0 +1,123 @@
+use std::net::{Ipv4Addr, UdpSocket};
+
+use crate::NetworkBlock;
+use crate::binary::ranges;
+use crate::dns::{self, CLASS_IN, Question, RCODE_FORMERR, RCODE_NOTIMP, RCODE_NXDOMAIN, RCODE_REFUSED, TYPE_A, TYPE_ANY, TYPE_TXT};
+
+const TTL: u32 = 300;
+const LISTED: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 2);
+const LISTED_TXT: &str = "listed as foreign network by ipcheck";
+
+pub struct Dnsbl {
+    /// 末尾のドットを除いた小文字のゾーン名
+    zone: String,
+    ranges: Vec<(u32, u32)>,
+}
+
+impl Dnsbl {
+    pub fn new(zone: &str, blocks: &[NetworkBlock]) -> Self {
+        Dnsbl { zone: zone.trim_end_matches('.').to_ascii_lowercase(), ranges: ranges(blocks) }
+    }
+
+    fn is_listed(&self, ip: Ipv4Addr) -> bool {
+        let ip = u32::from(ip);
+        let idx = self.ranges.partition_point(|&(_, end)| end < ip);
+        self.ranges.get(idx).is_some_and(|&(start, _)| start <= ip)
+    }
+
+    /// "4.3.2.1.zone" を 1.2.3.4 に戻す。ゾーン外なら None、ゾーン内で IP でなければ Some(None)
+    fn query_ip(&self, name: &str) -> Option<Option<Ipv4Addr>> {
+        let prefix = if name == self.zone { "" } else { name.strip_suffix(&self.zone)?.strip_suffix('.')? };
+        let octets: Vec<u8> = prefix.split('.').rev().map_while(|o| o.parse().ok()).collect();
+        Some(match (prefix.split('.').count(), octets.as_slice()) {
+            (4, &[a, b, c, d]) => Some(Ipv4Addr::new(a, b, c, d)),
+            _ => None,
+        })
+    }
+
+    /// 1 つのクエリパケットに対する応答パケットを作る。応答すべきでなければ None
+    pub fn respond(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        if packet.len() < 12 {
+            return None;
+        }
+        let flags = dns::header_flags(packet);
+        if flags & 0x8000 != 0 {
+            return None;
+        }
+        let reply = |rcode: u16, question: Option<&Question>, answers: &[Vec<u8>]| dns::reply(packet, rcode, question, answers);
+
+        if (flags >> 11) & 0xF != 0 {
+            return Some(reply(RCODE_NOTIMP, None, &[]));
+        }
+        let Some(question) = dns::parse_question(packet).filter(|_| packet[4..6] == [0, 1]) else {
+            return Some(reply(RCODE_FORMERR, None, &[]));
+        };
+        if question.qclass != CLASS_IN {
+            return Some(reply(RCODE_REFUSED, Some(&question), &[]));
+        }
+
+        let response = match self.query_ip(&question.name) {
+            None => reply(RCODE_REFUSED, Some(&question), &[]),
+            // ゾーン頂点などは存在するが対応するレコードがない
+            Some(None) if question.name == self.zone => reply(0, Some(&question), &[]),
+            Some(None) => reply(RCODE_NXDOMAIN, Some(&question), &[]),
+            Some(Some(ip)) if !self.is_listed(ip) => reply(RCODE_NXDOMAIN, Some(&question), &[]),
+            Some(Some(_)) => {
+                let mut answers = Vec::new();
+                if matches!(question.qtype, TYPE_A | TYPE_ANY) {
+                    answers.push(dns::record(None, TYPE_A, TTL, &LISTED.octets()));
+                }
+                if matches!(question.qtype, TYPE_TXT | TYPE_ANY) {
+                    let mut txt = vec![LISTED_TXT.len() as u8];
+                    txt.extend_from_slice(LISTED_TXT.as_bytes());
+                    answers.push(dns::record(None, TYPE_TXT, TTL, &txt));
+                }
+                reply(0, Some(&question), &answers)
+            }
+        };
+        Some(response)
+    }
+}
+
+/// UDP で DNSBL の問い合わせに応答し続ける
+pub fn serve(listen: &str, dnsbl: &Dnsbl) -> Result<(), Box<dyn std::error::Error>> {
+    let socket = UdpSocket::bind(listen)?;
+    println!("DNSBL サーバー起動: {} (ゾーン {})", listen, dnsbl.zone);
+
+    let mut buf = [0u8; 512];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("受信エラー: {}", e);
+                continue;
+            }
+        };
+        if let Some(response) = dnsbl.respond(&buf[..len])
+            && let Err(e) = socket.send_to(&response, peer)
+        {
+            eprintln!("{} への応答エラー: {}", peer, e);
+        }
+    }
+}
+
+#[test]
+fn test_dnsbl_respond() {
+    let dnsbl = Dnsbl::new("dnsbl.example.", &["1.0.0.0/24".parse().unwrap()]);
+    let query = |name: &str| {
+        let mut packet = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.extend_from_slice(&[0, 0, 1, 0, 1]);
+        dnsbl.respond(&packet).unwrap()
+    };
+
+    let listed = query("5.0.0.1.DNSBL.example");
+    assert_eq!(listed[3] & 0x0F, 0);
+    assert_eq!(&listed[6..8], &[0, 1]);
+    assert_eq!(&listed[listed.len() - 4..], &[127, 0, 0, 2]);
+    assert_eq!(query("5.1.0.1.dnsbl.example")[3] & 0x0F, RCODE_NXDOMAIN as u8);
+    assert_eq!(query("5.0.0.1.other.example")[3] & 0x0F, RCODE_REFUSED as u8);
+}
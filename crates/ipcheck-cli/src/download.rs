@@ -0,0 +1,166 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+
+const DOWNLOAD_URL: &str = "https://download.maxmind.com/geoip/databases";
+
+/// URL の内容をすべて読み込む。credentials があれば Basic 認証を付ける
+pub fn http_get(url: &str, credentials: Option<(&str, &str)>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut request = ureq::get(url);
+    if let Some((user, password)) = credentials {
+        let token = STANDARD.encode(format!("{}:{}", user, password).as_bytes());
+        request = request.set("Authorization", &format!("Basic {}", token));
+    }
+    let response = request.call().map_err(|e| match e {
+        ureq::Error::Status(401, _) => "認証に失敗しました (アカウント ID とライセンスキーを確認してください)".to_string(),
+        e => format!("{} の取得に失敗しました: {}", url, e),
+    })?;
+    let mut body = Vec::new();
+    response.into_reader().read_to_end(&mut body)?;
+    Ok(body)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn verify_sha256(actual: &str, expected: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(format!("SHA256 が一致しません (期待値 {}, 実際 {})", expected, actual).into());
+    }
+    Ok(())
+}
+
+pub fn file_sha256(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// URL のキャッシュファイルのパス
+fn cache_path(url: &str, cache_dir: &Path) -> PathBuf {
+    // 拡張子 (.gz, .tar.gz) で展開方法を決めるので URL のファイル名を残す
+    let file_name = url.split(['?', '#']).next().unwrap_or(url).rsplit('/').next().filter(|n| !n.is_empty()).unwrap_or("database.mmdb");
+    cache_dir.join(format!("ipcheck-{}-{}", &sha256_hex(url.as_bytes())[..16], file_name))
+}
+
+/// --dry-run 用。取得もキャッシュの更新もせず、既存のキャッシュのパスを返す
+pub fn cached(url: &str, cache_dir: &Path, sha256: Option<&str>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let cache_path = cache_path(url, cache_dir);
+    if !cache_path.exists() {
+        return Err(format!("--dry-run ではダウンロードしません。{} のキャッシュがないので、先に --dry-run なしで実行してください", url).into());
+    }
+    if let Some(expected) = sha256 {
+        verify_sha256(&file_sha256(&cache_path)?, expected)?;
+    }
+    println!("キャッシュを使用 (--dry-run): {}", cache_path.display());
+    Ok(cache_path)
+}
+
+/// URL のファイルをキャッシュディレクトリへストリーミングで保存し、そのパスを返す。
+/// 前回の ETag で条件付きリクエストを送り、変更がなければ、または取得に失敗すればキャッシュを使う
+pub fn fetch_cached(url: &str, cache_dir: &Path, sha256: Option<&str>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let cache_path = cache_path(url, cache_dir);
+    let etag_path = cache_path.with_file_name(format!("{}.etag", cache_path.file_name().unwrap_or_default().to_string_lossy()));
+
+    // チェックサムが固定されていれば、一致するキャッシュはそのまま使える
+    if let Some(expected) = sha256
+        && cache_path.exists()
+        && file_sha256(&cache_path)?.eq_ignore_ascii_case(expected)
+    {
+        return Ok(cache_path);
+    }
+
+    let mut request = if crate::cloud::is_object_uri(url) { crate::cloud::object_request(url)? } else { ureq::get(url) };
+    // チェックサムが固定されていてここまで来たならキャッシュは一致していないので、304 で使い回さないよう条件を付けない
+    if sha256.is_none()
+        && cache_path.exists()
+        && let Ok(etag) = std::fs::read_to_string(&etag_path)
+    {
+        request = request.set("If-None-Match", etag.trim());
+    }
+    println!("データベースを取得中: {}", url);
+    let response = match request.call() {
+        Ok(response) => response,
+        Err(e) if cache_path.exists() && sha256.is_none() => {
+            eprintln!("警告: 取得に失敗したためキャッシュを使います ({})", e);
+            return Ok(cache_path);
+        }
+        Err(e) => return Err(format!("{} の取得に失敗しました: {}", url, e).into()),
+    };
+    if response.status() == 304 {
+        println!("変更なし (キャッシュを使用: {})", cache_path.display());
+        return Ok(cache_path);
+    }
+
+    std::fs::create_dir_all(cache_dir)?;
+    let etag = response.header("ETag").map(str::to_string);
+    let tmp_path = cache_path.with_extension("download");
+    let mut file = File::create(&tmp_path)?;
+    let mut hasher = Sha256::new();
+    let mut reader = response.into_reader();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        file.write_all(&buf[..n])?;
+    }
+    drop(file);
+
+    if let Some(expected) = sha256
+        && let Err(e) = verify_sha256(&hex(&hasher.finalize()), expected)
+    {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    std::fs::rename(&tmp_path, &cache_path)?;
+    match etag {
+        Some(etag) => std::fs::write(&etag_path, etag)?,
+        None => {
+            let _ = std::fs::remove_file(&etag_path);
+        }
+    }
+    Ok(cache_path)
+}
+
+/// MaxMind 配布形式の tar.gz から最初の .mmdb を取り出す
+pub fn extract_mmdb(tar_gz: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(tar_gz));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.extension().is_some_and(|ext| ext == "mmdb") {
+            let mut mmdb = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut mmdb)?;
+            return Ok(mmdb);
+        }
+    }
+    Err("アーカイブに .mmdb ファイルが含まれていません".into())
+}
+
+/// 公式のパーマリンクからデータベースを取得し、SHA256 を検証して展開する
+pub fn download(edition: &str, account_id: &str, license_key: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let url = format!("{}/{}/download?suffix=tar.gz", DOWNLOAD_URL, edition);
+    let credentials = Some((account_id, license_key));
+
+    // "<sha256>  <ファイル名>" 形式
+    let checksum = String::from_utf8(http_get(&format!("{}.sha256", url), credentials)?)?;
+    let expected = checksum.split_whitespace().next().ok_or("SHA256 ファイルが空です")?.to_ascii_lowercase();
+
+    println!("{} をダウンロード中...", edition);
+    let archive = http_get(&url, credentials)?;
+    verify_sha256(&sha256_hex(&archive), &expected)?;
+    println!("SHA256 検証 OK ({:.2} MB)", archive.len() as f64 / 1024.0 / 1024.0);
+    extract_mmdb(&archive)
+}
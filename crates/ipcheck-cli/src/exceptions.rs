@@ -0,0 +1,19 @@
+use ipnetwork::IpNetwork;
+use maxminddb::{Reader, Within};
+use serde::de::DeserializeOwned;
+
+use crate::{NetworkBlock, ip_to_u32};
+
+/// ASN・匿名 IP などの補助データベースから、条件に合う IPv4 ネットワークを集める
+pub fn networks_matching<S: AsRef<[u8]>, T: DeserializeOwned>(reader: &Reader<S>, matches: impl Fn(&T) -> bool) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    let mut blocks = Vec::new();
+    let iter: Within<T, _> = reader.within(IpNetwork::V4("0.0.0.0/0".parse().unwrap()))?;
+    for result in iter {
+        let Ok(item) = result else { continue };
+        let std::net::IpAddr::V4(ip) = item.ip_net.ip() else { continue };
+        if matches(&item.info) {
+            blocks.push(NetworkBlock::new(ip_to_u32(ip), item.ip_net.prefix()));
+        }
+    }
+    Ok(blocks)
+}
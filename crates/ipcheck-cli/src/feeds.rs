@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+use crate::NetworkBlock;
+
+pub const TOR_EXIT_LIST: &str = "https://check.torproject.org/torbulkexitlist";
+/// Team Cymru の fullbogons (未割り振りの空間とプライベート・予約済みの空間)
+pub const FULLBOGONS_IPV4: &str = "https://www.team-cymru.org/Services/Bogons/fullbogons-ipv4.txt";
+
+/// Spamhaus DROP / EDROP (乗っ取られた、またはスパム・攻撃専用に使われているネットワーク)
+pub const SPAMHAUS_DROP: [&str; 2] = ["https://www.spamhaus.org/drop/drop.txt", "https://www.spamhaus.org/drop/edrop.txt"];
+
+/// FireHOL のブロックリスト集 (https://iplists.firehol.org/)
+const FIREHOL_BASE: &str = "https://iplists.firehol.org/files";
+
+/// FireHOL のリスト名 (firehol_level1 など) を URL にする。拡張子を省略すると .netset
+pub fn firehol_url(name: &str) -> String {
+    if name.ends_with(".netset") || name.ends_with(".ipset") {
+        format!("{}/{}", FIREHOL_BASE, name)
+    } else {
+        format!("{}/{}.netset", FIREHOL_BASE, name)
+    }
+}
+
+const ABUSEIPDB_BLACKLIST: &str = "https://api.abuseipdb.com/api/v2/blacklist";
+/// 無料プランの blacklist は 1 日 5 回までなので、これより新しい取得結果は再利用する
+const ABUSEIPDB_REFRESH: Duration = Duration::from_secs(6 * 3600);
+
+/// AbuseIPDB の blacklist API から信頼度 confidence 以上の IP アドレスを取得する。
+/// 結果は cache_dir に保存し、取得に失敗したときは前回の結果を使う。dry_run なら取得せず前回の結果だけを使う
+pub fn fetch_abuseipdb(key: &str, confidence: u8, cache_dir: &Path, dry_run: bool) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    let cache_path = cache_dir.join(format!("ipcheck-abuseipdb-{}.txt", confidence));
+    if dry_run && !cache_path.exists() {
+        return Err("--dry-run では AbuseIPDB から取得しません。前回の結果がないので、先に --dry-run なしで実行してください".into());
+    }
+    let fresh = dry_run
+        || std::fs::metadata(&cache_path)
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age < ABUSEIPDB_REFRESH));
+    if !fresh {
+        let response = ureq::get(ABUSEIPDB_BLACKLIST)
+            .query("confidenceMinimum", &confidence.to_string())
+            .set("Key", key)
+            .set("Accept", "text/plain")
+            .call();
+        match response {
+            Ok(response) => {
+                std::fs::create_dir_all(cache_dir)?;
+                let tmp_path = cache_path.with_extension("download");
+                std::io::copy(&mut response.into_reader(), &mut File::create(&tmp_path)?)?;
+                std::fs::rename(&tmp_path, &cache_path)?;
+            }
+            Err(e) if cache_path.exists() => eprintln!("警告: AbuseIPDB の取得に失敗したため前回の結果を使います ({})", e),
+            Err(ureq::Error::Status(401, _)) => return Err("AbuseIPDB の認証に失敗しました (API キーを確認してください)".into()),
+            Err(e) => return Err(format!("AbuseIPDB の取得に失敗しました: {}", e).into()),
+        }
+    }
+    Ok(read_feed(&cache_path)?.0)
+}
+
+/// フィードに載っているネットワークをどう扱うか
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FeedAction {
+    /// 国に関係なく海外リストに加える
+    Block,
+    /// 国に関係なく海外リストから除く
+    Allow,
+}
+
+/// 1 行 1 アドレスまたは CIDR のフィード (netset/ipset 形式) を読む。
+/// "#" と ";" 以降はコメント。IPv6 と解釈できない行は読み飛ばし、解釈できなかった行数も返す
+pub fn read_feed(path: &Path) -> Result<(Vec<NetworkBlock>, usize), Box<dyn std::error::Error>> {
+    let mut blocks = Vec::new();
+    let mut skipped = 0;
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let Some(entry) = line.split(['#', ';']).next().and_then(|l| l.split_whitespace().next()) else { continue };
+        if entry.contains(':') {
+            continue;
+        }
+        match entry.parse::<NetworkBlock>() {
+            Ok(block) => blocks.push(block),
+            Err(_) => skipped += 1,
+        }
+    }
+    Ok((blocks, skipped))
+}
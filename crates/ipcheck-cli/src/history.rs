@@ -0,0 +1,134 @@
+use rusqlite::{Connection, params};
+
+use crate::NetworkBlock;
+use crate::diff::diff;
+
+/// 生成のたびに 1 行追加する実行記録
+pub struct Run {
+    pub id: i64,
+    pub generated_at: u64,
+    pub build_epoch: u64,
+    pub cidrs: usize,
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// 指定範囲に重なる変更
+pub struct Change {
+    pub run_id: i64,
+    pub generated_at: u64,
+    pub added: bool,
+    pub block: NetworkBlock,
+}
+
+pub fn open(path: &str) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS runs (
+            id INTEGER PRIMARY KEY,
+            generated_at INTEGER NOT NULL,
+            build_epoch INTEGER NOT NULL,
+            cidrs INTEGER NOT NULL,
+            added INTEGER NOT NULL,
+            removed INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS changes (
+            run_id INTEGER NOT NULL REFERENCES runs (id),
+            added INTEGER NOT NULL,
+            start INTEGER NOT NULL,
+            end INTEGER NOT NULL,
+            prefix_len INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS changes_start ON changes (start);
+        -- 次回の差分の基準にする最新のリスト
+        CREATE TABLE IF NOT EXISTS current (
+            start INTEGER NOT NULL,
+            prefix_len INTEGER NOT NULL
+        );",
+    )?;
+    Ok(conn)
+}
+
+/// 前回の記録とのアドレス空間の差分を保存する。初回は全エントリを追加として記録する
+pub fn record(conn: &mut Connection, blocks: &[NetworkBlock], generated_at: u64, build_epoch: u64) -> rusqlite::Result<Run> {
+    let tx = conn.transaction()?;
+    let previous = {
+        let mut stmt = tx.prepare("SELECT start, prefix_len FROM current")?;
+        stmt.query_map([], |row| Ok(NetworkBlock::new(row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    let d = diff(&previous, blocks);
+    tx.execute(
+        "INSERT INTO runs (generated_at, build_epoch, cidrs, added, removed) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![generated_at, build_epoch, blocks.len(), d.added.len(), d.removed.len()],
+    )?;
+    let id = tx.last_insert_rowid();
+    {
+        let mut stmt = tx.prepare("INSERT INTO changes (run_id, added, start, end, prefix_len) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+        for (added, list) in [(true, &d.added), (false, &d.removed)] {
+            for block in list {
+                stmt.execute(params![id, added, block.network, block.last(), block.prefix_len])?;
+            }
+        }
+        tx.execute("DELETE FROM current", [])?;
+        let mut stmt = tx.prepare("INSERT INTO current (start, prefix_len) VALUES (?1, ?2)")?;
+        for block in blocks {
+            stmt.execute(params![block.network, block.prefix_len])?;
+        }
+    }
+    tx.commit()?;
+    Ok(Run { id, generated_at, build_epoch, cidrs: blocks.len(), added: d.added.len(), removed: d.removed.len() })
+}
+
+/// 新しい順に limit 件
+pub fn runs(conn: &Connection, limit: usize) -> rusqlite::Result<Vec<Run>> {
+    let mut stmt = conn.prepare("SELECT id, generated_at, build_epoch, cidrs, added, removed FROM runs ORDER BY id DESC LIMIT ?1")?;
+    stmt.query_map([limit], |row| {
+        Ok(Run {
+            id: row.get(0)?,
+            generated_at: row.get(1)?,
+            build_epoch: row.get(2)?,
+            cidrs: row.get(3)?,
+            added: row.get(4)?,
+            removed: row.get(5)?,
+        })
+    })?
+    .collect()
+}
+
+/// block に重なる追加・削除を古い順に返す
+pub fn changes(conn: &Connection, block: &NetworkBlock) -> rusqlite::Result<Vec<Change>> {
+    let mut stmt = conn.prepare(
+        "SELECT changes.run_id, runs.generated_at, changes.added, changes.start, changes.prefix_len
+         FROM changes JOIN runs ON runs.id = changes.run_id
+         WHERE changes.start <= ?2 AND changes.end >= ?1
+         ORDER BY changes.run_id, changes.start",
+    )?;
+    stmt.query_map(params![block.network, block.last()], |row| {
+        Ok(Change {
+            run_id: row.get(0)?,
+            generated_at: row.get(1)?,
+            added: row.get(2)?,
+            block: NetworkBlock::new(row.get(3)?, row.get(4)?),
+        })
+    })?
+    .collect()
+}
+
+#[test]
+fn test_history() {
+    let parse = |cidrs: &[&str]| cidrs.iter().map(|c| c.parse::<NetworkBlock>().unwrap()).collect::<Vec<_>>();
+    let mut conn = open(":memory:").unwrap();
+    let first = record(&mut conn, &parse(&["1.0.0.0/24", "8.8.8.0/24"]), 100, 10).unwrap();
+    assert_eq!((first.added, first.removed), (2, 0));
+    let second = record(&mut conn, &parse(&["1.0.0.0/23"]), 200, 20).unwrap();
+    assert_eq!((second.added, second.removed), (1, 1));
+
+    let history: Vec<(i64, bool, String)> = changes(&conn, &"8.8.8.8/32".parse().unwrap())
+        .unwrap()
+        .into_iter()
+        .map(|c| (c.run_id, c.added, c.block.to_string()))
+        .collect();
+    assert_eq!(history, [(first.id, true, "8.8.8.0/24".to_string()), (second.id, false, "8.8.8.0/24".to_string())]);
+    assert_eq!(changes(&conn, &"1.0.1.0/24".parse().unwrap()).unwrap().len(), 1);
+    assert_eq!(runs(&conn, 1).unwrap()[0].id, second.id);
+}
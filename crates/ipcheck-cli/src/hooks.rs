@@ -0,0 +1,62 @@
+//! --on-success / --on-change で生成後に実行するローカルのコマンド
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::webhook::Event;
+
+/// コマンドに渡す環境変数。前回の出力がなければ差分の変数は渡さない
+pub fn environment(event: &Event, format: &str, files: &[PathBuf]) -> Vec<(&'static str, String)> {
+    let mut env = vec![
+        ("IPCHECK_OUTPUT", event.output.to_string()),
+        ("IPCHECK_FILES", files.iter().map(|f| f.display().to_string()).collect::<Vec<_>>().join("\n")),
+        ("IPCHECK_FORMAT", format.to_string()),
+        ("IPCHECK_CIDRS", event.cidrs.to_string()),
+        ("IPCHECK_CHANGED", if event.changed { "1" } else { "0" }.to_string()),
+        ("IPCHECK_BUILD_EPOCH", event.build_epoch.to_string()),
+        ("IPCHECK_GENERATED_AT", event.generated_at.to_string()),
+        ("IPCHECK_DURATION_SECONDS", format!("{:.3}", event.duration_seconds)),
+    ];
+    if let Some(diff) = &event.diff {
+        env.push(("IPCHECK_ADDED", diff.added.to_string()));
+        env.push(("IPCHECK_REMOVED", diff.removed.to_string()));
+        env.push(("IPCHECK_ADDED_ADDRESSES", diff.added_addresses.to_string()));
+        env.push(("IPCHECK_REMOVED_ADDRESSES", diff.removed_addresses.to_string()));
+    }
+    env
+}
+
+/// シェル経由で command を実行し、終了を待つ
+pub fn run(command: &str, env: &[(&'static str, String)]) -> Result<(), String> {
+    #[cfg(unix)]
+    let (shell, flag) = ("sh", "-c");
+    #[cfg(windows)]
+    let (shell, flag) = ("cmd", "/C");
+    let status = Command::new(shell)
+        .args([flag, command])
+        .envs(env.iter().map(|(name, value)| (*name, value)))
+        .status()
+        .map_err(|e| format!("フック `{}` を実行できません: {}", command, e))?;
+    if !status.success() {
+        return Err(format!("フック `{}` が失敗しました ({})", command, status));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_hook_environment() {
+    let previous: Vec<crate::NetworkBlock> = vec!["1.0.0.0/24".parse().unwrap()];
+    let blocks: Vec<crate::NetworkBlock> = vec!["1.0.0.0/23".parse().unwrap()];
+    let event = Event::new("out.txt", &blocks, Some(&previous), 100, 200, 0.5);
+    let env = environment(&event, "text", &[PathBuf::from("out.txt"), PathBuf::from("out.txt.sha256")]);
+    let get = |name: &str| env.iter().find(|(n, _)| *n == name).map(|(_, v)| v.as_str());
+    assert_eq!(get("IPCHECK_FILES"), Some("out.txt\nout.txt.sha256"));
+    assert_eq!((get("IPCHECK_CHANGED"), get("IPCHECK_ADDED"), get("IPCHECK_REMOVED")), (Some("1"), Some("1"), Some("0")));
+    assert!(environment(&Event::new("out.txt", &blocks, None, 100, 200, 0.5), "text", &[]).iter().all(|(n, _)| *n != "IPCHECK_ADDED"));
+
+    #[cfg(unix)]
+    {
+        assert!(run("test \"$IPCHECK_CIDRS\" = 1", &env).is_ok());
+        assert!(run("exit 3", &env).is_err());
+    }
+}
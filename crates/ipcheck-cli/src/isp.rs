@@ -0,0 +1,27 @@
+use maxminddb::Reader;
+use serde::Deserialize;
+
+use crate::NetworkBlock;
+use crate::exceptions::networks_matching;
+
+/// GeoIP2 ISP のレコード
+#[derive(Deserialize)]
+struct IspRecord {
+    isp: Option<String>,
+    organization: Option<String>,
+}
+
+/// ISP 名または組織名に、指定した名前のいずれかを含む (大文字小文字は区別しない) IPv4 ネットワークを集める。
+/// "NTT" で "NTT Communications Corporation" や "NTT DOCOMO" に一致する
+pub fn networks_of_isps<S: AsRef<[u8]>>(reader: &Reader<S>, names: &[String]) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    let names: Vec<String> = names.iter().map(|n| n.trim().to_lowercase()).filter(|n| !n.is_empty()).collect();
+    networks_matching(reader, |record: &IspRecord| {
+        [&record.isp, &record.organization]
+            .into_iter()
+            .flatten()
+            .any(|value| {
+                let value = value.to_lowercase();
+                names.iter().any(|name| value.contains(name.as_str()))
+            })
+    })
+}
@@ -0,0 +1,195 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use ipnetwork::IpNetwork;
+use maxminddb::{Reader, Within};
+use prost::Message;
+use serde::Deserialize;
+
+use crate::binary::RangeSet;
+use crate::output::protobuf::ForeignNetworks;
+use crate::{NetworkBlock, Output, range_to_blocks};
+
+#[derive(Deserialize)]
+struct ForeignRecord {
+    foreign: Option<bool>,
+}
+
+/// 生成済みのリストを読み込む。形式は拡張子から判定し、不明な場合はテキストとして扱う
+pub fn read_list(path: &str) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+    match extension {
+        "json" => parse_cidrs(serde_json::from_reader::<_, Output>(BufReader::new(File::open(path)?))?.check_version()?.foreign),
+        "msgpack" => parse_cidrs(rmp_serde::from_read::<_, Output>(BufReader::new(File::open(path)?))?.check_version()?.foreign),
+        "cbor" => parse_cidrs(ciborium::from_reader::<Output, _>(BufReader::new(File::open(path)?))?.check_version()?.foreign),
+        "pb" => {
+            let message = ForeignNetworks::decode(std::fs::read(path)?.as_slice())?;
+            crate::output::check_schema_version(message.metadata.as_ref().map_or(0, |m| m.schema_version))?;
+            Ok(message
+                .networks
+                .iter()
+                .map(|n| NetworkBlock::new(n.network, n.prefix_len as u8))
+                .collect())
+        }
+        "mmdb" => read_mmdb(path),
+        "sqlite" => read_sqlite(path),
+        "redis" => read_redis(path),
+        "nft" => read_nft(BufReader::new(File::open(path)?)),
+        "rpz" => read_rpz(BufReader::new(File::open(path)?)),
+        "ipset" => read_text(
+            BufReader::new(File::open(path)?)
+                .lines()
+                .map_while(Result::ok)
+                .filter(|line| line.starts_with("add "))
+                .collect::<Vec<_>>()
+                .join("\n")
+                .as_bytes(),
+        ),
+        "bin" => {
+            let set = RangeSet::from_bytes(std::fs::read(path)?)?;
+            Ok((0..set.len())
+                .flat_map(|i| {
+                    let (start, end) = set.range(i);
+                    range_to_blocks(start, end)
+                })
+                .collect())
+        }
+        _ => read_text(BufReader::new(File::open(path)?)),
+    }
+}
+
+fn parse_cidrs(cidrs: Vec<String>) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    Ok(cidrs.iter().map(|c| c.parse()).collect::<Result<Vec<NetworkBlock>, _>>()?)
+}
+
+/// 1 行 1 CIDR のテキストを読む。ipset の `add <set> <cidr>` のように末尾に CIDR がある行も受け付ける
+pub fn read_text(reader: impl BufRead) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    let mut blocks = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.split(['#', ';']).next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.parse::<NetworkBlock>() {
+            Ok(block) => blocks.push(block),
+            Err(e) => match line.split_whitespace().last().and_then(|t| t.trim_end_matches(',').parse().ok()) {
+                Some(block) => blocks.push(block),
+                None => return Err(e.into()),
+            },
+        }
+    }
+    Ok(blocks)
+}
+
+/// `add element <table> <set> { a, b, ... }` 形式の行から要素を取り出す
+fn read_nft(reader: impl BufRead) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    let mut blocks = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.starts_with("add element") {
+            continue;
+        }
+        if let Some((_, elements)) = line.split_once('{') {
+            for element in elements.trim_end_matches('}').split(',') {
+                let element = element.trim();
+                if !element.is_empty() {
+                    blocks.push(element.parse()?);
+                }
+            }
+        }
+    }
+    Ok(blocks)
+}
+
+/// rpz-ip トリガーのオーナー名からネットワークを取り出す
+fn read_rpz(reader: impl BufRead) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    let mut blocks = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(block) = line.split_whitespace().next().and_then(crate::output::rpz::parse_owner) {
+            blocks.push(block);
+        }
+    }
+    Ok(blocks)
+}
+
+fn read_mmdb(path: &str) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    let reader = Reader::open_readfile(path)?;
+    let mut blocks = Vec::new();
+    let iter: Within<ForeignRecord, _> = reader.within(IpNetwork::V4("0.0.0.0/0".parse().unwrap()))?;
+    for item in iter {
+        let item = item?;
+        if item.info.foreign == Some(true)
+            && let std::net::IpAddr::V4(ip) = item.ip_net.ip()
+        {
+            blocks.push(NetworkBlock::new(u32::from(ip), item.ip_net.prefix()));
+        }
+    }
+    Ok(blocks)
+}
+
+fn read_sqlite(path: &str) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    let conn = rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare("SELECT start, prefix_len FROM networks WHERE is_foreign = 1 ORDER BY start")?;
+    let blocks = stmt
+        .query_map([], |row| Ok(NetworkBlock::new(row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(blocks)
+}
+
+fn read_redis(path: &str) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    let mut data = String::new();
+    File::open(path)?.read_to_string(&mut data)?;
+
+    // RESP のバルク文字列のうち CIDR として解釈できるものだけを拾う
+    let mut blocks = Vec::new();
+    let mut lines = data.split("\r\n");
+    while let Some(line) = lines.next() {
+        if line.starts_with('$')
+            && let Some(Ok(block)) = lines.next().filter(|s| s.contains('/')).map(str::parse)
+        {
+            blocks.push(block);
+        }
+    }
+    Ok(blocks)
+}
+
+/// 検証用に、リストのエントリを解釈前の文字列のまま (行番号付きで) 読み込む
+pub fn read_raw_entries(path: &str) -> Result<Vec<(usize, String)>, Box<dyn std::error::Error>> {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+    let output: Output = match extension {
+        "json" => serde_json::from_reader(BufReader::new(File::open(path)?))?,
+        "msgpack" => rmp_serde::from_read(BufReader::new(File::open(path)?))?,
+        "cbor" => ciborium::from_reader(BufReader::new(File::open(path)?))?,
+        _ => {
+            let mut entries = Vec::new();
+            for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+                let line = line?;
+                let entry = line.split(['#', ';']).next().unwrap_or("").trim();
+                if !entry.is_empty() {
+                    entries.push((i + 1, entry.to_string()));
+                }
+            }
+            return Ok(entries);
+        }
+    };
+    Ok(output.check_version()?.foreign.into_iter().enumerate().map(|(i, cidr)| (i + 1, cidr)).collect())
+}
+
+#[test]
+fn test_redis_round_trip() {
+    use crate::output::redis::{RedisMode, commands, encode};
+
+    let blocks: Vec<NetworkBlock> = ["1.0.0.0/24", "2.0.0.0/16", "203.0.113.7/32"].iter().map(|c| c.parse().unwrap()).collect();
+    let path = std::env::temp_dir().join(format!("ipcheck-list-{}.redis", std::process::id()));
+    for mode in [RedisMode::Set, RedisMode::Zset] {
+        // zset のスコア (開始アドレス) やキー名は CIDR として拾わない
+        std::fs::write(&path, encode(&commands(&blocks, "ipcheck:foreign", mode))).unwrap();
+        let read = read_list(path.to_str().unwrap()).unwrap();
+        assert_eq!(read.iter().map(|b| b.to_string()).collect::<Vec<_>>(), ["1.0.0.0/24", "2.0.0.0/16", "203.0.113.7/32"]);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
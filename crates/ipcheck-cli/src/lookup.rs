@@ -0,0 +1,137 @@
+use std::io::{BufRead, Write};
+use std::net::Ipv4Addr;
+
+use clap::ValueEnum;
+use ipnetwork::IpNetwork;
+use maxminddb::{Reader, Within};
+use serde::Serialize;
+
+use crate::output::SCHEMA_VERSION;
+use crate::rules::Rules;
+use crate::{CountryRecord, NetworkBlock, ip_to_u32};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ResultFormat {
+    Csv,
+    Json,
+}
+
+/// lookup --output-format json と serve の /lookup が返す 1 件分の結果
+#[derive(Serialize)]
+pub struct LookupResult {
+    pub schema_version: u32,
+    pub ip: String,
+    pub network: Option<String>,
+    pub country: Option<String>,
+    pub foreign: Option<bool>,
+}
+
+impl LookupResult {
+    pub fn new(ip: Ipv4Addr, network: Option<String>, country: Option<String>, foreign: Option<bool>) -> Self {
+        LookupResult { schema_version: SCHEMA_VERSION, ip: ip.to_string(), network, country, foreign }
+    }
+}
+
+/// 行から IP アドレスを取り出す。column は 1 始まりの CSV 列番号
+fn extract_ip(line: &str, column: Option<usize>, delimiter: char) -> Option<Ipv4Addr> {
+    let field = match column {
+        Some(column) => line.split(delimiter).nth(column.checked_sub(1)?)?,
+        None => line,
+    };
+    field.trim().trim_matches('"').parse().ok()
+}
+
+/// 入力の IP をソートしてからデータベースを 1 回だけ走査し、まとめて判定する
+pub fn classify_bulk<S: AsRef<[u8]>>(
+    reader: &Reader<S>,
+    rules: &Rules,
+    input: impl BufRead,
+    column: Option<usize>,
+    delimiter: char,
+) -> Result<Vec<LookupResult>, Box<dyn std::error::Error>> {
+    let mut results = Vec::new();
+    let mut targets: Vec<(u32, usize)> = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match extract_ip(&line, column, delimiter) {
+            Some(ip) => {
+                targets.push((ip_to_u32(ip), results.len()));
+                results.push(LookupResult::new(ip, None, None, None));
+            }
+            None => eprintln!("IP アドレスとして解釈できない行をスキップしました: {}", line),
+        }
+    }
+    targets.sort_unstable();
+
+    let mut pos = 0;
+    let iter: Within<CountryRecord, _> = reader.within(IpNetwork::V4("0.0.0.0/0".parse().unwrap()))?;
+    for item in iter {
+        if pos >= targets.len() {
+            break;
+        }
+        let Ok(item) = item else { continue };
+        let std::net::IpAddr::V4(ip) = item.ip_net.ip() else { continue };
+        let block = NetworkBlock::new(ip_to_u32(ip), item.ip_net.prefix());
+
+        while pos < targets.len() && targets[pos].0 < block.network {
+            pos += 1;
+        }
+        let iso_code = item.info.location(rules);
+        while pos < targets.len() && targets[pos].0 <= block.last() {
+            let result = &mut results[targets[pos].1];
+            result.network = Some(block.to_string());
+            result.country = iso_code.clone();
+            result.foreign = Some(rules.is_foreign(iso_code.as_deref()));
+            pos += 1;
+        }
+    }
+
+    Ok(results)
+}
+
+pub fn write_results<W: Write>(out: &mut W, results: &[LookupResult], format: ResultFormat) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        ResultFormat::Csv => {
+            writeln!(out, "ip,network,country,foreign")?;
+            for result in results {
+                writeln!(
+                    out,
+                    "{},{},{},{}",
+                    result.ip,
+                    result.network.as_deref().unwrap_or(""),
+                    result.country.as_deref().unwrap_or(""),
+                    result.foreign.map(|f| f.to_string()).unwrap_or_default()
+                )?;
+            }
+        }
+        ResultFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, results)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_classify_bulk() {
+    let block = |cidr: &str| -> NetworkBlock { cidr.parse().unwrap() };
+    let networks = vec![(block("1.0.0.0/24"), Some("CN".to_string())), (block("133.0.0.0/16"), Some("JP".to_string()))];
+    let mmdb = crate::output::mmdb::encode_country(&networks, &Rules::default(), 0).unwrap();
+    let reader = Reader::from_source(mmdb).unwrap();
+    // 並んでいない入力・重複・データベースにないアドレス・解釈できない行
+    let input = "ip,note\n133.0.5.5,a\n1.0.0.7,b\n9.9.9.9,c\n1.0.0.7,d\nx,e\n";
+    let results = classify_bulk(&reader, &Rules::default(), input.as_bytes(), Some(1), ',').unwrap();
+    let rows: Vec<_> = results.iter().map(|r| (r.ip.as_str(), r.network.as_deref(), r.country.as_deref(), r.foreign)).collect();
+    assert_eq!(
+        rows,
+        [
+            ("133.0.5.5", Some("133.0.0.0/16"), Some("JP"), Some(false)),
+            ("1.0.0.7", Some("1.0.0.0/24"), Some("CN"), Some(true)),
+            ("9.9.9.9", None, None, None),
+            ("1.0.0.7", Some("1.0.0.0/24"), Some("CN"), Some(true)),
+        ]
+    );
+}
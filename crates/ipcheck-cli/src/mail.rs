@@ -0,0 +1,308 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+use crate::NetworkBlock;
+use crate::diff::diff;
+use crate::schedule;
+use crate::serve::http_date;
+use crate::webhook::{Event, summary_text};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 本文に載せる追加・削除 CIDR の上限
+const MAX_LISTED: usize = 100;
+
+/// SMTP サーバーの接続先。smtps:// は最初から TLS、smtp:// はサーバーが対応していれば STARTTLS を使う
+#[derive(Clone)]
+pub struct Server {
+    host: String,
+    port: u16,
+    implicit_tls: bool,
+}
+
+impl std::str::FromStr for Server {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (implicit_tls, rest, default_port) = if let Some(rest) = s.strip_prefix("smtps://") {
+            (true, rest, 465)
+        } else if let Some(rest) = s.strip_prefix("smtp://") {
+            (false, rest, 587)
+        } else {
+            return Err(format!("smtp://ホスト[:ポート] または smtps://ホスト[:ポート] の形式で指定してください: {}", s));
+        };
+        let rest = rest.trim_end_matches('/');
+        let (host, port) = match rest.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| format!("ポート番号が不正です: {}", port))?),
+            None => (rest, default_port),
+        };
+        if host.is_empty() {
+            return Err(format!("ホスト名がありません: {}", s));
+        }
+        Ok(Server { host: host.to_string(), port, implicit_tls })
+    }
+}
+
+impl std::fmt::Display for Server {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let scheme = if self.implicit_tls { "smtps" } else { "smtp" };
+        write!(f, "{}://{}:{}", scheme, self.host, self.port)
+    }
+}
+
+pub struct Credentials<'a> {
+    pub user: &'a str,
+    pub password: &'a str,
+}
+
+pub struct Attachment {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+enum Connection {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Plain(s) => s.read(buf),
+            Connection::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Connection::Plain(s) => s.write(buf),
+            Connection::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Connection::Plain(s) => s.flush(),
+            Connection::Tls(s) => s.flush(),
+        }
+    }
+}
+
+fn tls(host: &str, stream: TcpStream) -> Result<Connection, Box<dyn std::error::Error>> {
+    let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from(host.to_string())?;
+    let connection = ClientConnection::new(Arc::new(config), name)?;
+    Ok(Connection::Tls(Box::new(StreamOwned::new(connection, stream))))
+}
+
+struct Session {
+    connection: Connection,
+    host: String,
+}
+
+impl Session {
+    /// 応答を読み、code 以外なら失敗にする。複数行の応答は最後の行まで読む
+    fn reply(&mut self, codes: &[u16]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = Vec::new();
+            let mut byte = [0u8];
+            while !line.ends_with(b"\r\n") {
+                if self.connection.read(&mut byte)? == 0 {
+                    return Err("SMTP サーバーが接続を閉じました".into());
+                }
+                line.push(byte[0]);
+            }
+            let line = String::from_utf8_lossy(&line).trim_end().to_string();
+            let last = line.as_bytes().get(3) != Some(&b'-');
+            lines.push(line);
+            if last {
+                break;
+            }
+        }
+        let code = lines.last().and_then(|l| l.get(..3)).and_then(|c| c.parse().ok()).unwrap_or(0);
+        if !codes.contains(&code) {
+            return Err(format!("SMTP エラー: {}", lines.join(" / ")).into());
+        }
+        Ok(lines)
+    }
+
+    fn command(&mut self, command: &str, codes: &[u16]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.connection.write_all(command.as_bytes())?;
+        self.connection.write_all(b"\r\n")?;
+        self.connection.flush()?;
+        self.reply(codes)
+    }
+
+    fn ehlo(&mut self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let lines = self.command("EHLO ipcheck", &[250])?;
+        Ok(lines.iter().skip(1).map(|l| l.get(4..).unwrap_or("").to_ascii_uppercase()).collect())
+    }
+
+    fn starttls(self) -> Result<Self, Box<dyn std::error::Error>> {
+        let Connection::Plain(stream) = self.connection else { return Ok(self) };
+        Ok(Session { connection: tls(&self.host, stream)?, host: self.host })
+    }
+}
+
+/// メールを 1 通送る
+pub fn send(server: &Server, credentials: Option<&Credentials>, from: &str, to: &[String], message: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let stream = TcpStream::connect((server.host.as_str(), server.port))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let connection = if server.implicit_tls { tls(&server.host, stream)? } else { Connection::Plain(stream) };
+    let mut session = Session { connection, host: server.host.clone() };
+
+    session.reply(&[220])?;
+    let mut extensions = session.ehlo()?;
+    if !server.implicit_tls && extensions.iter().any(|e| e == "STARTTLS") {
+        session.command("STARTTLS", &[220])?;
+        session = session.starttls()?;
+        extensions = session.ehlo()?;
+    }
+    if let Some(credentials) = credentials {
+        if matches!(session.connection, Connection::Plain(_)) {
+            return Err("SMTP サーバーが STARTTLS に対応していないため、暗号化されていない接続では認証しません".into());
+        }
+        if !extensions.iter().any(|e| e.starts_with("AUTH") && e.split_whitespace().any(|m| m == "PLAIN")) {
+            return Err("SMTP サーバーが AUTH PLAIN に対応していません".into());
+        }
+        let token = STANDARD.encode(format!("\0{}\0{}", credentials.user, credentials.password));
+        session.command(&format!("AUTH PLAIN {}", token), &[235])?;
+    }
+    session.command(&format!("MAIL FROM:<{}>", from), &[250])?;
+    for address in to {
+        session.command(&format!("RCPT TO:<{}>", address), &[250, 251])?;
+    }
+    session.command("DATA", &[354])?;
+    session.connection.write_all(&dot_stuff(message))?;
+    session.command(".", &[250])?;
+    let _ = session.command("QUIT", &[221]);
+    Ok(())
+}
+
+/// 行頭の "." を重ね、末尾を CRLF にそろえる (RFC 5321 4.5.2)
+fn dot_stuff(message: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(message.len() + 16);
+    for line in message.split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.starts_with(b".") {
+            out.push(b'.');
+        }
+        out.extend_from_slice(line);
+        out.extend_from_slice(b"\r\n");
+    }
+    if message.ends_with(b"\n") {
+        out.truncate(out.len() - 2);
+    }
+    out
+}
+
+/// ヘッダーに ASCII 以外を含む場合は RFC 2047 の encoded-word にする。
+/// 1 語 75 文字に収まるよう、文字の境界で 45 バイトずつに分けて折り返す
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in value.chars() {
+        if chunk.len() + c.len_utf8() > 45 {
+            words.push(format!("=?UTF-8?B?{}?=", STANDARD.encode(&chunk)));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    words.push(format!("=?UTF-8?B?{}?=", STANDARD.encode(&chunk)));
+    words.join("\r\n ")
+}
+
+/// base64 を 76 文字ごとに折り返す
+fn base64_lines(data: &[u8]) -> String {
+    let encoded = STANDARD.encode(data);
+    encoded.as_bytes().chunks(76).map(|c| String::from_utf8_lossy(c).into_owned()).collect::<Vec<_>>().join("\r\n")
+}
+
+/// 生成結果のレポート本文。前回の出力があれば追加・削除された CIDR を並べる
+pub fn report_text(event: &Event, blocks: &[NetworkBlock], previous: Option<&[NetworkBlock]>) -> String {
+    let mut text = summary_text(event);
+    if let Some(previous) = previous {
+        let d = diff(previous, blocks);
+        for (label, list) in [("追加", &d.added), ("削除", &d.removed)] {
+            if list.is_empty() {
+                continue;
+            }
+            text.push_str(&format!("\n\n{} ({} 件):\n", label, list.len()));
+            for block in list.iter().take(MAX_LISTED) {
+                text.push_str(&format!("  {}\n", block.to_string()));
+            }
+            if list.len() > MAX_LISTED {
+                text.push_str(&format!("  ... (残り{}件)\n", list.len() - MAX_LISTED));
+            }
+        }
+    }
+    text
+}
+
+/// text/plain の本文と添付ファイルから MIME メッセージを作る
+pub fn message(from: &str, to: &[String], subject: &str, text: &str, attachment: Option<&Attachment>) -> Vec<u8> {
+    let now = schedule::now_epoch();
+    let mut headers = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}.{}@ipcheck>\r\nMIME-Version: 1.0\r\n",
+        from,
+        to.join(", "),
+        encode_header(subject),
+        http_date(now).replace("GMT", "+0000"),
+        now,
+        std::process::id()
+    );
+    let body = format!("Content-Type: text/plain; charset=UTF-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n", base64_lines(text.as_bytes()));
+    match attachment {
+        None => headers.push_str(&body),
+        Some(attachment) => {
+            let boundary = format!("ipcheck-{:x}", now);
+            headers.push_str(&format!("Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n", boundary));
+            headers.push_str(&format!("--{}\r\n{}", boundary, body));
+            headers.push_str(&format!(
+                "--{}\r\nContent-Type: application/octet-stream\r\nContent-Transfer-Encoding: base64\r\nContent-Disposition: attachment; filename=\"{}\"\r\n\r\n{}\r\n",
+                boundary,
+                encode_header(&attachment.filename),
+                base64_lines(&attachment.data)
+            ));
+            headers.push_str(&format!("--{}--\r\n", boundary));
+        }
+    }
+    headers.into_bytes()
+}
+
+#[test]
+fn test_message() {
+    assert_eq!(dot_stuff(b".a\nb\r\n..c\n"), b"..a\r\nb\r\n...c\r\n");
+    assert_eq!(encode_header("ipcheck"), "ipcheck");
+    assert_eq!(encode_header("海外"), "=?UTF-8?B?5rW35aSW?=");
+    assert!(encode_header(&"海".repeat(20)).split("\r\n ").all(|word| word.len() <= 75));
+
+    let server: Server = "smtps://mail.example.com".parse().unwrap();
+    assert_eq!((server.host.as_str(), server.port, server.implicit_tls), ("mail.example.com", 465, true));
+    assert!("mail.example.com:25".parse::<Server>().is_err());
+
+    let to = vec!["ops@example.com".to_string()];
+    let attachment = Attachment { filename: "foreign.txt".to_string(), data: b"1.0.0.0/24\n".to_vec() };
+    let message = String::from_utf8(message("ipcheck@example.com", &to, "レポート", "本文", Some(&attachment))).unwrap();
+    assert!(message.contains("To: ops@example.com\r\n"));
+    assert!(message.contains("Content-Type: multipart/mixed; boundary="));
+    assert!(message.contains("filename=\"foreign.txt\"\r\n\r\nMS4wLjAuMC8yNAo=\r\n"));
+}
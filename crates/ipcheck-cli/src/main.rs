@@ -0,0 +1,2518 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::Ipv4Addr;
+use maxminddb::Reader;
+use indicatif::{ProgressBar, ProgressStyle};
+use clap::{Parser, Subcommand, ValueEnum};
+
+mod also;
+mod anonymous;
+mod apply;
+mod asn;
+mod bench;
+mod cloud;
+mod checksum;
+mod chunk;
+mod cloud_ranges;
+mod config;
+mod connection;
+mod conntrack;
+mod deploy;
+mod diff;
+mod dns;
+mod dnsbl;
+mod download;
+mod exceptions;
+mod feeds;
+mod history;
+mod hooks;
+mod isp;
+mod list;
+mod lookup;
+mod mail;
+mod metrics;
+mod rpz;
+mod schedule;
+mod serve;
+mod sign;
+mod sources;
+mod split;
+mod stats;
+mod syslog;
+mod systemd;
+mod tail;
+mod timings;
+mod validate;
+mod verify;
+mod versions;
+mod webhook;
+
+use ipcheck_formats as output;
+use ipcheck_formats::{Output, OutputWriter};
+use output::redis::RedisMode;
+use sources::ConflictPolicy;
+use ipcheck_core::{CidrSet, CountryRecord, IpcheckBuilder, NetworkBlock, binary, ip_to_u32, lookup_network, range_to_blocks, rules, union_blocks};
+
+#[derive(Parser)]
+#[command(name = "ipcheck", about = "海外IP CIDR生成ツール", args_override_self = true)]
+struct Cli {
+    /// プロファイルを並べた設定ファイル。[名前] のセクションごとに「オプション = 値」を 1 行ずつ書き、
+    /// セクションより前の行は全プロファイル共通にする。コマンドラインの指定が優先される
+    #[arg(long, global = true, env = "IPCHECK_CONFIG")]
+    config: Option<std::path::PathBuf>,
+
+    /// --config から使うプロファイル。複数指定すると順に生成する (指定しなければ共通の設定だけを使う)
+    #[arg(long, global = true, requires = "config")]
+    profile: Vec<String>,
+
+    /// GeoLite2 データベースのパス、または http(s)://, s3://, gs:// の URL (.gz / .tar.gz も可)
+    #[arg(long, global = true, default_value = "GeoLite2-Country.mmdb")]
+    db: String,
+
+    /// 出力形式
+    #[arg(long, default_value = "json", value_parser = format_parser())]
+    format: &'static dyn OutputWriter,
+
+    /// 出力ファイル (省略時は foreign_ip_cidrs.<拡張子>)
+    #[arg(long, short)]
+    output: Option<String>,
+
+    /// 同じ走査から追加で書き出す出力 (FORMAT[:PATH][,OPTION=VALUE...]、複数指定可)。PATH を省略すると --output の拡張子を変えたもの。
+    /// OPTION でその形式のオプション (set-name や minecraft-mode など) をこの出力だけ変えられる (例: nft:/etc/nftables.d/foreign.nft,set-name=blocked)
+    #[arg(long, value_name = "FORMAT[:PATH]")]
+    also: Vec<also::Also>,
+
+    /// --format の出力を国ごとのファイルにも分けて書き出す (foreign_ip_cidrs.json なら foreign_ip_cidrs.CN.json など)。
+    /// データベース上の国が分からない範囲は unknown にまとめる
+    #[arg(long)]
+    split_by_country: bool,
+
+    /// --split-by-country で国ごとではなくグループごとに書き出す (NAME=CC,CC,...、複数指定可)。どのグループにも入らない国は書き出さない
+    #[arg(long, value_name = "NAME=CC,...", requires = "split_by_country")]
+    country_group: Vec<split::Group>,
+
+    /// mmdb 出力時にレコードへ書き込む値
+    #[arg(long, value_enum, default_value_t = MmdbRecord::Foreign)]
+    mmdb_record: MmdbRecord,
+
+    /// minecraft / minecraft-json 出力に書くリストの種類
+    #[arg(long, global = true, value_enum, default_value_t = MinecraftMode::Deny)]
+    minecraft_mode: MinecraftMode,
+
+    /// ipset / nft のセット名
+    #[arg(long, global = true, default_value = "foreign")]
+    set_name: String,
+
+    /// nft のテーブル (ファミリー + テーブル名)
+    #[arg(long, global = true, default_value = "inet filter")]
+    nft_table: String,
+
+    /// rpz 出力と RPZ サーバーのゾーン名
+    #[arg(long, global = true, default_value = "foreign.rpz")]
+    rpz_zone: String,
+
+    /// bpf 出力と --apply bpf で書き込む、ピン留めされた LPM trie マップ (ipcheck xdp-program の読み込み手順を参照)
+    #[arg(long, global = true, default_value = "/sys/fs/bpf/ipcheck/foreign")]
+    bpf_map: String,
+
+    /// redis 出力で使うキー名
+    #[arg(long, default_value = "ipcheck:foreign")]
+    redis_key: String,
+
+    /// redis 出力のデータ構造
+    #[arg(long, value_enum, default_value_t = RedisMode::Set)]
+    redis_mode: RedisMode,
+
+    /// 出力形式ごとのオプション (NAME=VALUE、複数指定可)。組み込みの形式の --set-name などと同じものに加え、
+    /// register で追加した形式が受け付けるオプションも指定できる (後に書いたものが優先)
+    #[arg(long, global = true, value_name = "NAME=VALUE", value_parser = format_option)]
+    format_option: Vec<(String, String)>,
+
+    /// 指定すると redis 出力をファイルではなくこのサーバーへ直接投入する (redis://[:password@]host[:port][/db])
+    #[arg(long)]
+    redis_url: Option<String>,
+
+    /// markdown 出力で差分を計算する前回の JSON 出力
+    #[arg(long)]
+    previous: Option<String>,
+
+    /// 出力と同じ場所に二分探索用のバイナリリスト (.bin) も書き出す
+    #[arg(long)]
+    binary_sidecar: bool,
+
+    /// 生成のたびに node_exporter の textfile collector 形式でメトリクスを書き出す
+    #[arg(long, value_name = "PATH")]
+    prom_textfile: Option<std::path::PathBuf>,
+
+    /// 分類と集約だけ行い、書き出すファイルや送信先と前回からの差分を表示する (ファイルも外部にも一切書き込まない)。
+    /// URL の --db やフィードはダウンロードせず、--db-cache の既存のキャッシュを使う
+    #[arg(long)]
+    dry_run: bool,
+
+    /// リストが前回の出力から変わったときは終了コード 3 で終える (変わらなければ 0、失敗は 1)。
+    /// --dry-run と組み合わせると、更新が必要かだけを確かめられる
+    #[arg(long)]
+    detailed_exit_code: bool,
+
+    /// 使うスレッド数の上限 (serve の HTTP ワーカーと gRPC ランタイム、rpz の TCP 応答)。
+    /// 共有のマシンで全コアを使い切らないようにする。リストの生成は元から 1 スレッドで行う
+    #[arg(long, global = true, value_name = "N")]
+    threads: Option<std::num::NonZeroUsize>,
+
+    /// 生成したリストを nft / ipset / bpftool コマンドでこのマシンのセット (--set-name, nft は --nft-table) か XDP のマップ (--bpf-map) へ直接入れる。
+    /// --format とは関係なく、書き出しの後に適用する
+    #[arg(long, global = true, value_enum, value_name = "FIREWALL")]
+    apply: Option<apply::Firewall>,
+
+    /// --apply の後、前回のリストになかった範囲からの確立済みの接続を conntrack で切断する (前回の出力がなければリスト全体)
+    #[arg(long, global = true, requires = "apply")]
+    flush_conntrack: bool,
+
+    /// 出力 (と署名・チェックサム・.bin) を ssh で配置する先 (ssh://[ユーザー@]ホスト[:ポート]/ディレクトリ、複数指定可)。
+    /// 一時ファイルに送ってから rename するので、配置先で書きかけのファイルが読まれることはない
+    #[arg(long, global = true, value_name = "URL")]
+    deploy: Vec<deploy::Target>,
+
+    /// --deploy の配置後に配置先で実行するコマンド (例: "nft -f /etc/nftables.d/ipcheck.nft")
+    #[arg(long, global = true, value_name = "COMMAND", requires = "deploy")]
+    deploy_reload: Option<String>,
+
+    /// 生成に成功するたびに実行するコマンド (複数指定可)。IPCHECK_OUTPUT, IPCHECK_FILES, IPCHECK_CIDRS,
+    /// IPCHECK_CHANGED, IPCHECK_ADDED, IPCHECK_REMOVED などの環境変数で生成の内容を渡す
+    #[arg(long, global = true, value_name = "COMMAND")]
+    on_success: Vec<String>,
+
+    /// リストが前回の出力から変わったときだけ実行するコマンド (複数指定可、環境変数は --on-success と同じ)
+    #[arg(long, global = true, value_name = "COMMAND")]
+    on_change: Vec<String>,
+
+    /// 処理時間の内訳 (走査・分類・ソート・集約・シリアライズ・書き出し・送信) を表示する
+    #[arg(long, global = true)]
+    timings: bool,
+
+    /// 出力 (protobuf, xlsx, 署名など) に記録する生成日時。fixed で記録せず、同じ入力から同じバイト列を出力する
+    /// (now, fixed, build = データベースのビルド日時, または UNIX 時刻)
+    #[arg(long, default_value = "now", env = "SOURCE_DATE_EPOCH")]
+    timestamp: schedule::Timestamp,
+
+    /// 出力ごとに sha256sum 互換のチェックサム (.sha256) を書き出す
+    #[arg(long)]
+    checksum: bool,
+
+    /// 出力ごとに minisign 互換の署名 (.minisig) を書き出す秘密鍵 (ipcheck keygen、または minisign -G -W で作る)
+    #[arg(long, value_name = "PATH")]
+    sign_key: Option<std::path::PathBuf>,
+
+    /// 出力を DIR/<生成日時>/ に書き出して残し、DIR/latest を最新の版へのシンボリックリンクにする
+    #[arg(long, global = true, value_name = "DIR")]
+    versions: Option<std::path::PathBuf>,
+
+    /// --versions で残す版の数 (latest が指している版は数を超えても残す)
+    #[arg(long, default_value_t = 10, requires = "versions")]
+    keep: usize,
+
+    /// 生成のたびに CIDR 数と追加・削除された範囲を記録する SQLite データベース (history サブコマンドで参照する)
+    #[arg(long, global = true, value_name = "PATH")]
+    history: Option<String>,
+
+    /// 生成のたびに概要と前回の出力との差分を JSON で POST する URL (複数指定可)
+    #[arg(long, value_name = "URL")]
+    webhook: Vec<String>,
+
+    /// webhook の本文のテンプレート。{{cidrs}} や {{diff.added}} のように値を埋め込める
+    #[arg(long, value_name = "PATH")]
+    webhook_template: Option<String>,
+
+    /// リストが前回の出力から変わったときだけ --webhook を送る (Slack / Discord とメールは毎回送る)
+    #[arg(long)]
+    webhook_on_change: bool,
+
+    /// 生成結果の概要と失敗を Slack の Incoming Webhook に投稿する
+    #[arg(long, value_name = "URL")]
+    slack_webhook: Option<String>,
+
+    /// 生成結果の概要と失敗を Discord の Webhook に投稿する
+    #[arg(long, value_name = "URL")]
+    discord_webhook: Option<String>,
+
+    /// 生成結果のレポートを送る SMTP サーバー (smtp://ホスト:587 は STARTTLS、smtps://ホスト:465 は TLS)
+    #[arg(long, value_name = "URL", requires = "mail_to")]
+    smtp: Option<mail::Server>,
+
+    #[arg(long, requires = "smtp_password")]
+    smtp_user: Option<String>,
+
+    #[arg(long, env = "IPCHECK_SMTP_PASSWORD", hide_env_values = true)]
+    smtp_password: Option<String>,
+
+    /// レポートの差出人
+    #[arg(long, default_value = "ipcheck@localhost")]
+    mail_from: String,
+
+    /// レポートの宛先 (複数指定可)
+    #[arg(long, value_name = "ADDRESS", requires = "smtp")]
+    mail_to: Vec<String>,
+
+    /// 生成したリストをレポートに添付する
+    #[arg(long)]
+    mail_attach: bool,
+
+    /// 生成の結果や失敗を RFC 5424 形式で syslog にも送る (local で /dev/log、udp://ホスト:514、tcp://ホスト:514)
+    #[arg(long, global = true, value_name = "TARGET")]
+    syslog: Option<syslog::Target>,
+
+    /// png 出力の Hilbert 曲線の次数 (1〜13。画像は 2^n 四方、既定の 12 で 1 ピクセル = /24)
+    #[arg(long, default_value_t = 12, value_parser = clap::value_parser!(u32).range(1..=13))]
+    hilbert_order: u32,
+
+    /// --db の形式
+    #[arg(long, global = true, default_value = sources::AUTO, value_parser = clap::builder::PossibleValuesParser::new(sources::names()))]
+    source_format: String,
+
+    /// 複数の入力元を組み合わせる ("mmdb:GeoLite2-Country.mmdb", "rir:rir" など。指定すると --db は使わない)
+    #[arg(long = "source", global = true)]
+    sources: Vec<sources::SourceSpec>,
+
+    /// 入力元の間で国の判定が食い違ったときの扱い
+    #[arg(long, global = true, value_enum, default_value_t = ConflictPolicy::AnyForeign)]
+    conflict_policy: ConflictPolicy,
+
+    /// 国内とみなす国 (ISO 3166-1 alpha-2, 例: JP,KR)。複数指定可
+    #[arg(long, global = true, value_delimiter = ',', value_name = "CC", default_value = rules::DOMESTIC_COUNTRY, value_parser = country_code)]
+    domestic_country: Vec<String>,
+
+    /// 国内のうちこれらの地域 (例: JP-13,JP-27) だけを国内とする。--domestic-country の国の地域でなければならず、
+    /// 国コードを省けるのは国内の国が 1 つのときだけ。City データベースか GeoLite2-City の CSV が必要
+    #[arg(long, global = true, value_delimiter = ',')]
+    domestic_subdivision: Vec<String>,
+
+    /// 走査と出力をこの範囲 (CIDR) に限る。複数指定可。試したいときや地域別のリストを手早く作るときに使う
+    #[arg(long = "range", global = true, value_name = "CIDR")]
+    ranges: Vec<NetworkBlock>,
+
+    /// --allow-asn / --block-asn で使う GeoLite2-ASN データベース (URL も可)
+    #[arg(long, global = true, default_value = "GeoLite2-ASN.mmdb")]
+    asn_db: String,
+
+    /// 国に関係なく海外リストから除く AS 番号 (カンマ区切り可)
+    #[arg(long, global = true, value_delimiter = ',')]
+    allow_asn: Vec<u32>,
+
+    /// 国に関係なく海外リストに加える AS 番号 (--allow-asn より優先)
+    #[arg(long, global = true, value_delimiter = ',')]
+    block_asn: Vec<u32>,
+
+    /// --block-vpn などで使う GeoIP2 Anonymous-IP データベース (URL も可)
+    #[arg(long, global = true, default_value = "GeoIP2-Anonymous-IP.mmdb")]
+    anonymous_db: String,
+
+    /// 国内に位置していても匿名 VPN のネットワークを海外リストに加える
+    #[arg(long, global = true)]
+    block_vpn: bool,
+
+    /// 国内に位置していてもホスティング事業者のネットワークを海外リストに加える
+    #[arg(long, global = true)]
+    block_hosting: bool,
+
+    /// 国内に位置していても Tor 出口ノードを海外リストに加える
+    #[arg(long, global = true)]
+    block_tor: bool,
+
+    /// 国内に位置していても住宅用プロキシのネットワークを海外リストに加える
+    #[arg(long, global = true)]
+    block_residential_proxy: bool,
+
+    /// --allow-connection-type で使う GeoIP2 Connection-Type データベース (URL も可)
+    #[arg(long, global = true, default_value = "GeoIP2-Connection-Type.mmdb")]
+    connection_type_db: String,
+
+    /// 海外に位置していてもこの回線種別のネットワークは海外リストから除く (カンマ区切り可)
+    #[arg(long, global = true, value_enum, value_delimiter = ',')]
+    allow_connection_type: Vec<connection::ConnectionType>,
+
+    /// --allow-isp で使う GeoIP2 ISP データベース (URL も可)
+    #[arg(long, global = true, default_value = "GeoIP2-ISP.mmdb")]
+    isp_db: String,
+
+    /// ISP 名・組織名にこれらを含むネットワークは海外リストから除く (カンマ区切り、大文字小文字は区別しない)
+    #[arg(long, global = true, value_delimiter = ',')]
+    allow_isp: Vec<String>,
+
+    /// Tor Project の出口ノード一覧を国に関係なくブロック、または許可する
+    #[arg(long, global = true, value_enum)]
+    tor_exits: Option<feeds::FeedAction>,
+
+    /// --tor-exits で使う出口ノード一覧の URL またはパス
+    #[arg(long, global = true, default_value = feeds::TOR_EXIT_LIST)]
+    tor_exit_list: String,
+
+    /// Team Cymru の fullbogons を海外リストに加える (10.0.0.0/8 などのプライベートアドレスも含むので、LAN 側にも適用するルールでは注意)
+    #[arg(long, global = true)]
+    bogons: bool,
+
+    /// --bogons で使う fullbogons の URL またはパス
+    #[arg(long, global = true, default_value = feeds::FULLBOGONS_IPV4)]
+    bogons_list: String,
+
+    /// Spamhaus DROP / EDROP を海外リストに加える (JSON 系の出力では feeds に記録する)
+    #[arg(long, global = true)]
+    spamhaus_drop: bool,
+
+    /// --spamhaus-drop で使う一覧の URL またはパス
+    #[arg(long, global = true, default_values = feeds::SPAMHAUS_DROP)]
+    spamhaus_drop_list: Vec<String>,
+
+    /// FireHOL のブロックリスト (firehol_level1, spamhaus_drop.netset など) を海外リストに加える (カンマ区切り可)
+    #[arg(long, global = true, value_delimiter = ',')]
+    firehol: Vec<String>,
+
+    /// 任意の netset/ipset 形式フィード (URL またはパス) を海外リストに加える
+    #[arg(long = "feed", global = true)]
+    feeds: Vec<String>,
+
+    /// AbuseIPDB の API キー。指定すると blacklist の IP アドレスを海外リストに加える
+    #[arg(long, global = true, env = "ABUSEIPDB_API_KEY", hide_env_values = true)]
+    abuseipdb_key: Option<String>,
+
+    /// AbuseIPDB の blacklist に含める最小の信頼度 (25-100)
+    #[arg(long, global = true, default_value_t = 90, value_parser = clap::value_parser!(u8).range(25..=100))]
+    abuseipdb_confidence: u8,
+
+    /// 公開されているクラウドのレンジを海外リストから除く ("aws:EC2:ap-northeast-1", "gcp", "cloudflare" など。"*" はすべて)
+    #[arg(long, global = true)]
+    allow_cloud: Vec<cloud_ranges::CloudSelector>,
+
+    /// --allow-cloud azure で使う Service Tags の JSON (URL またはパス)
+    #[arg(long, global = true)]
+    azure_service_tags: Option<String>,
+
+    /// データベースの判定より優先する RFC 8805 geofeed の URL またはパス (先に指定したものが優先)
+    #[arg(long, global = true)]
+    geofeed: Vec<String>,
+
+    /// --source が複数のとき、海外・国内の判定が食い違う範囲を CSV で書き出す
+    #[arg(long, global = true)]
+    discrepancy_report: Option<String>,
+
+    /// --db が URL の場合に検証する SHA256
+    #[arg(long, global = true)]
+    db_sha256: Option<String>,
+
+    /// --db が URL の場合のキャッシュディレクトリ (既定は一時ディレクトリ)
+    #[arg(long, global = true)]
+    db_cache: Option<std::path::PathBuf>,
+
+    /// 速度より使用メモリを優先する。データベースをメモリマップで読み、走査しながら集約する
+    /// (展開済みの .mmdb だけが対象で、--source, --geofeed や国別の情報を使う出力形式とは併用できない)
+    #[arg(long, global = true)]
+    low_memory: bool,
+
+    /// データベースのビルド日時がこれより古ければ警告する
+    #[arg(long, global = true, value_parser = schedule::parse_duration, default_value = "30d")]
+    warn_age: std::time::Duration,
+
+    /// データベースのビルド日時がこれより古ければ出力せずに失敗する (例: 30d)
+    #[arg(long, global = true, value_parser = schedule::parse_duration)]
+    max_age: Option<std::time::Duration>,
+
+    /// 生成したリストの CIDR 数がこれより少なければ、出力も送信もせずに失敗する (壊れたデータベース対策)
+    #[arg(long, global = true, value_name = "N")]
+    fail_if_entries_below: Option<usize>,
+
+    /// 前回の出力よりアドレス数がこの割合 (%) を超えて減ったら、出力も送信もせずに失敗する。前回の出力が読めなければ失敗する。
+    /// Redis への投入や sshd / geofeed / minecraft / minecraft-json のように海外リストとして読み直せない形式では --binary-sidecar と併用する
+    #[arg(long, global = true, value_name = "PERCENT")]
+    fail_if_shrink_percent: Option<f64>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// SQLite 出力から IP アドレスを検索する
+    Query {
+        /// --format sqlite で生成したデータベース
+        database: String,
+        ip: Ipv4Addr,
+    },
+    /// --format protobuf の .proto スキーマを出力する
+    ProtoSchema,
+    /// bpf 出力のマップで送信元を破棄する XDP プログラムの参考実装 (C) を出力する
+    XdpProgram,
+    /// 生成済みのリストに IP アドレスが含まれるかを確認する (mmdb は不要)
+    Check {
+        /// 生成済みのリスト (json, txt, nft, ipset, msgpack, cbor, pb, mmdb, sqlite, redis, bin)
+        #[arg(long)]
+        list: String,
+        ip: Ipv4Addr,
+    },
+    /// 2 つの生成済みリストの差分をアドレス空間として表示する
+    Diff {
+        old: String,
+        new: String,
+        /// 差分の代わりに変更エントリだけの add/del コマンドを出力する
+        #[arg(long, value_enum)]
+        emit_delta: Option<DeltaFormat>,
+    },
+    /// 任意の CIDR リストを重複除去・包含除去・隣接結合して最小化する
+    Optimize {
+        /// 入力リスト ("-" で標準入力からテキストを読む)
+        input: String,
+        /// 出力形式
+        #[arg(long, default_value = "text", value_parser = format_parser())]
+        to: &'static dyn OutputWriter,
+        /// 出力ファイル (省略時は標準出力)
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+    /// 複数のリスト (形式は混在可) を結合し、再集約して 1 つのリストにする
+    Merge {
+        #[arg(required = true, num_args = 1..)]
+        inputs: Vec<String>,
+        /// 出力形式
+        #[arg(long, default_value = "text", value_parser = format_parser())]
+        to: &'static dyn OutputWriter,
+        /// 出力ファイル (省略時は標準出力)
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+    /// --history に記録した生成の履歴を表示する。IP アドレスか CIDR を指定するとその範囲の追加・削除を表示する
+    History {
+        query: Option<String>,
+        /// 表示する実行の件数
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// 出力の署名に使う ed25519 の鍵を作る (NAME.key と minisign 互換の NAME.pub)
+    Keygen {
+        #[arg(default_value = "ipcheck")]
+        name: String,
+    },
+    /// 署名 (.minisig) を公開鍵で検証する。ルーターなどで取得したリストを適用する前に使う
+    VerifySig {
+        file: String,
+        /// 公開鍵のファイル、または minisign -P と同じ base64 の公開鍵
+        #[arg(long, short = 'P')]
+        pubkey: String,
+        /// 署名ファイル (省略時は FILE.minisig)
+        #[arg(long)]
+        sig: Option<String>,
+    },
+    /// チェックサム (.sha256) でファイルが壊れていないか検証する。FILE を指定すると FILE.sha256 を読む
+    VerifyChecksum {
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// --versions の latest を以前の版に向け直す (次の生成で最新の版に戻る)
+    Rollback {
+        /// 戻す版のディレクトリ名 (省略時は latest の 1 つ前の版)
+        version: Option<String>,
+    },
+    /// 国ではなく AS 番号から、その AS に属するネットワークのリストを生成する (--asn-db を使う)
+    Asn {
+        /// AS 番号 (カンマ区切り可)
+        #[arg(required = true, value_delimiter = ',')]
+        asns: Vec<u32>,
+        /// 出力形式
+        #[arg(long, default_value = "text", value_parser = format_parser())]
+        to: &'static dyn OutputWriter,
+        /// 出力ファイル (省略時は標準出力)
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+    /// 生成済みのリスト (またはテキスト) を別の形式に変換する
+    Convert {
+        input: String,
+        /// 変換先の形式
+        #[arg(long, value_parser = format_parser())]
+        to: &'static dyn OutputWriter,
+        /// 出力ファイル (省略時は入力ファイルの拡張子を変えたもの)
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+    /// 登録件数に上限があるファイアウォールパネル向けに、リストを N 件ずつのテキストに分け、索引 (PREFIX.index.json) を添える
+    Chunk {
+        /// 分割する生成済みリスト ("-" で標準入力からテキストを読む)
+        input: String,
+        /// 1 ファイルあたりの最大件数
+        #[arg(long)]
+        size: std::num::NonZeroUsize,
+        /// 書き出し先のディレクトリ
+        #[arg(long, default_value = ".")]
+        output_dir: String,
+        /// ファイル名の接頭辞 (PREFIX-001.txt, PREFIX-002.txt, ...)
+        #[arg(long, default_value = "foreign")]
+        prefix: String,
+    },
+    /// データベースを再走査して、生成済みリストが分類結果と一致するか検証する
+    Verify {
+        /// 検証する生成済みリスト
+        #[arg(long, default_value = "foreign_ip_cidrs.json")]
+        list: String,
+        /// 表示する不一致の最大件数
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// CIDR リストの書式・重複・包含・結合可能な隣接エントリを検査する
+    Validate {
+        list: String,
+        /// 正規化した最小の CIDR 列を書き出す
+        #[arg(long)]
+        fix: bool,
+        /// --fix の書き出し先 (省略時は元のファイルを上書き)
+        #[arg(long, short, requires = "fix")]
+        output: Option<String>,
+    },
+    /// リストは生成せず、データベースの国別統計を表示する
+    Stats {
+        /// アドレス数の多い海外の国の上位 N 件と、ブロックリストへの寄与を表示する
+        #[arg(long)]
+        top: Option<usize>,
+    },
+    /// 集約方式 (スタック結合・区間の掃引・2 分木) を同じ入力で比べ、時間・メモリ・出力サイズを表示する
+    /// (メモリは bench-alloc 機能でビルドしたときだけ)
+    Bench {
+        /// 集約するリスト ("-" で標準入力。省略時は --db の海外ネットワーク)
+        input: Option<String>,
+        /// 方式ごとの実行回数 (時間は中央値を表示する)
+        #[arg(long, default_value_t = 5)]
+        iterations: usize,
+    },
+    /// 検索 API と生成したリストを HTTP で提供する (/metrics で Prometheus のメトリクス、/healthz と /readyz で状態も出す)
+    Serve {
+        /// 待ち受けアドレス
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// gRPC サービスの待ち受けアドレス (省略時は起動しない)
+        #[cfg(feature = "grpc")]
+        #[arg(long)]
+        grpc_listen: Option<String>,
+    },
+    /// 海外ネットワークを DNSBL として UDP で応答する
+    Dnsbl {
+        /// DNSBL のゾーン名 (例: foreign.dnsbl.example)
+        #[arg(long)]
+        zone: String,
+        /// 待ち受けアドレス
+        #[arg(long, default_value = "127.0.0.1:5353")]
+        listen: String,
+    },
+    /// 海外ネットワークの RPZ ゾーンを DNS (AXFR/IXFR) で配信する
+    Rpz {
+        /// 待ち受けアドレス (UDP と TCP)
+        #[arg(long, default_value = "127.0.0.1:5353")]
+        listen: String,
+    },
+    /// 常駐し、スケジュールに従って出力を再生成する
+    Daemon {
+        /// 再生成の間隔 (例: 24h, 30m)
+        #[arg(long, value_parser = schedule::parse_duration, required_unless_present = "cron", conflicts_with = "cron")]
+        every: Option<std::time::Duration>,
+        /// 再生成する時刻の cron 式 (分 時 日 月 曜日, UTC)
+        #[arg(long)]
+        cron: Option<schedule::Cron>,
+    },
+    /// ログに追記された行から IP アドレスを取り出し、生成済みリストに含まれる (海外の) アドレスを表示する
+    Tail {
+        /// 追いかけるログファイル (ローテーションされても開き直す)
+        #[arg(long)]
+        file: std::path::PathBuf,
+        /// この正規表現に一致する行だけを見る (グループ・選択・回数指定は使えない)
+        #[arg(long)]
+        pattern: Option<tail::Pattern>,
+        /// 判定に使う生成済みリスト (更新されると読み直す)
+        #[arg(long, default_value = "foreign_ip_cidrs.json")]
+        list: String,
+        /// 海外のアドレスを見つけたときに実行するコマンド (IPCHECK_IP, IPCHECK_CIDR, IPCHECK_LINE, IPCHECK_FILE を渡す)
+        #[arg(long)]
+        exec: Option<String>,
+        /// 同じアドレスで --exec を再び実行するまでの間隔
+        #[arg(long, value_parser = schedule::parse_duration, default_value = "60s")]
+        cooldown: std::time::Duration,
+        /// 既存の内容も先頭から読む (省略時は起動後に追記された行だけ)
+        #[arg(long)]
+        from_start: bool,
+    },
+    /// データベースファイルの置き換えを監視し、更新されるたびに出力を再生成する
+    Watch {
+        /// 更新を検知してから生成を始めるまでに待つ時間 (書き込み途中の読み込みを避ける)
+        #[arg(long, value_parser = schedule::parse_duration, default_value = "2s")]
+        settle: std::time::Duration,
+    },
+    /// MaxMind からデータベースをダウンロードし、--db の場所に配置する
+    Download {
+        #[arg(long, env = "MAXMIND_ACCOUNT_ID")]
+        account_id: String,
+        #[arg(long, env = "MAXMIND_LICENSE_KEY", hide_env_values = true)]
+        license_key: String,
+        /// データベースのエディション
+        #[arg(long, default_value = "GeoLite2-Country")]
+        edition: String,
+    },
+    /// データベースから 1 つの IP アドレスを検索し、海外判定を表示する。
+    /// 判定はデータベースの国コードと --domestic-country / --domestic-subdivision だけで行い、ASN・クラウド・フィードなどの例外、
+    /// --geofeed、--source、--range は反映しない。生成したリストに含まれるかどうかは check で確かめる
+    Lookup {
+        #[arg(required_unless_present_any = ["stdin", "input"])]
+        ip: Option<Ipv4Addr>,
+        /// 標準入力から IP アドレスを 1 行ずつ読み込んで一括判定する
+        #[arg(long, conflicts_with = "ip")]
+        stdin: bool,
+        /// ファイルから IP アドレスを読み込んで一括判定する
+        #[arg(long, conflicts_with_all = ["ip", "stdin"])]
+        input: Option<String>,
+        /// CSV の場合に IP アドレスが入っている列番号 (1 始まり)
+        #[arg(long)]
+        column: Option<usize>,
+        /// CSV の区切り文字
+        #[arg(long, default_value_t = ',')]
+        delimiter: char,
+        /// 一括判定結果の出力形式
+        #[arg(long, value_enum, default_value_t = lookup::ResultFormat::Csv)]
+        output_format: lookup::ResultFormat,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DeltaFormat {
+    Ipset,
+    Nft,
+    Bpf,
+}
+
+struct Classification {
+    /// --low-memory では空
+    networks: Vec<(NetworkBlock, Option<String>)>,
+    domestic_networks: usize,
+    foreign_networks: usize,
+    foreign_blocks: Vec<NetworkBlock>,
+    foreign: Vec<String>,
+    build_epoch: u64,
+    timings: timings::Timings,
+}
+
+/// --format と --to の値。登録されている出力形式から選ぶ
+fn format_parser() -> impl clap::builder::TypedValueParser<Value = &'static dyn OutputWriter> {
+    use clap::builder::{PossibleValue, PossibleValuesParser, TypedValueParser};
+
+    let values = output::writers().into_iter().map(|writer| match writer.description() {
+        Some(help) => PossibleValue::new(writer.name()).help(help),
+        None => PossibleValue::new(writer.name()),
+    });
+    PossibleValuesParser::new(values).map(|name| output::find(&name).expect("登録済みの形式です"))
+}
+
+/// --format-option の NAME=VALUE。どの形式も受け付けない名前はエラーにする
+fn format_option(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s.split_once('=').ok_or_else(|| format!("NAME=VALUE の形式で指定してください: {}", s))?;
+    let writers = output::writers();
+    if !writers.iter().any(|writer| writer.options().iter().any(|option| option.name == name)) {
+        let mut names: Vec<&str> = writers.iter().flat_map(|writer| writer.options().iter().map(|option| option.name)).collect();
+        names.sort_unstable();
+        names.dedup();
+        return Err(format!("どの出力形式も受け付けないオプションです: {} (指定できるのは {})", name, names.join(", ")));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// --domestic-country の国コード。大文字にそろえる
+fn country_code(s: &str) -> Result<String, String> {
+    if s.len() != 2 || !s.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return Err(format!("2 文字の国コード (ISO 3166-1 alpha-2) を指定してください: {}", s));
+    }
+    Ok(s.to_ascii_uppercase())
+}
+
+#[test]
+fn test_domestic_country() {
+    assert_eq!(Cli::parse_from(["ipcheck"]).domestic_country, ["JP"]);
+    assert_eq!(Cli::parse_from(["ipcheck", "--domestic-country", "jp,kr", "--domestic-country", "TW"]).domestic_country, ["JP", "KR", "TW"]);
+    assert!(Cli::try_parse_from(["ipcheck", "--domestic-country", "JPN"]).is_err());
+}
+
+/// ValueEnum の値の、コマンドラインで指定する名前
+fn value_name(value: impl ValueEnum) -> String {
+    value.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MmdbRecord {
+    /// 海外ネットワークのみを {"foreign": true} として書き込む
+    Foreign,
+    /// 全ネットワークに国コードと foreign フラグを書き込む
+    Country,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MinecraftMode {
+    /// 海外の範囲を blacklist として並べる
+    Deny,
+    /// 海外以外の範囲 (データベースにないアドレスを含む) を whitelist として並べる
+    Allow,
+}
+
+#[test]
+fn test_unknown_country() {
+    use ipnetwork::IpNetwork;
+    use maxminddb::Within;
+
+    let reader = Reader::open_readfile("GeoLite2-Country.mmdb");
+    let binding = reader.expect("aaaaa");
+    let mut iter: Within<CountryRecord, _> = binding.within(IpNetwork::V4("1.0.164.22/32".parse().unwrap())).unwrap();
+    while let Some(result) = iter.next() {
+        match result {
+            Ok(item) => {
+                if let Some(country) = item.info.country {
+                    println!("{}", country.iso_code.unwrap())
+                } else {
+                    println!("None")
+                }
+            }
+            Err(_) => {}
+        }
+    }
+    println!("end")
+}
+
+fn is_remote(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://") || cloud::is_object_uri(path)
+}
+
+/// URL (s3://, gs:// を含む) ならキャッシュへダウンロードし、ローカルのパスを返す
+fn resolve_path(cli: &Cli, path: &str, sha256: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    if !is_remote(path) {
+        return Ok(path.to_string());
+    }
+    let cache_dir = cli.db_cache.clone().unwrap_or_else(std::env::temp_dir);
+    let path = if cli.dry_run { download::cached(path, &cache_dir, sha256)? } else { download::fetch_cached(path, &cache_dir, sha256)? };
+    Ok(path.display().to_string())
+}
+
+fn resolve_db(cli: &Cli) -> Result<String, Box<dyn std::error::Error>> {
+    resolve_path(cli, &cli.db, cli.db_sha256.as_deref())
+}
+
+/// --db で指定されたデータベースを開く
+fn open_database(cli: &Cli) -> Result<Reader<Vec<u8>>, Box<dyn std::error::Error>> {
+    open_mmdb(&resolve_db(cli)?)
+}
+
+/// .mmdb.gz と MaxMind 配布形式の .tar.gz はメモリ上で展開する
+fn open_mmdb(path: &str) -> Result<Reader<Vec<u8>>, Box<dyn std::error::Error>> {
+    let lower = path.to_ascii_lowercase();
+    if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+        return Ok(Reader::from_source(download::extract_mmdb(&std::fs::read(path)?)?)?);
+    }
+    if lower.ends_with(".gz") {
+        let mut mmdb = Vec::new();
+        flate2::read::GzDecoder::new(File::open(path)?).read_to_end(&mut mmdb)?;
+        return Ok(Reader::from_source(mmdb)?);
+    }
+    Ok(Reader::open_readfile(path)?)
+}
+
+/// --db (--source があればそれらをまとめたもの) の IPv4 ネットワークと国コードの一覧、ビルド日時を読み込み、
+/// --geofeed の訂正を反映する
+fn load_networks(cli: &Cli) -> Result<(sources::Networks, u64), Box<dyn std::error::Error>> {
+    let (networks, build_epoch) = load_base_networks(cli)?;
+    if cli.geofeed.is_empty() {
+        return Ok((networks, build_epoch));
+    }
+    let mut overrides = Vec::new();
+    for feed in &cli.geofeed {
+        let path = resolve_path(cli, feed, None)?;
+        let (prefixes, skipped) = sources::read_geofeed(&path).map_err(|e| format!("{}: {}", feed, e))?;
+        println!("  geofeed {}: {} プレフィックス", feed, prefixes.len());
+        if skipped > 0 {
+            eprintln!("警告: {} の {} 行を解釈できなかったため無視しました", feed, skipped);
+        }
+        overrides.push(prefixes);
+    }
+    Ok((sources::apply_overrides(networks, overrides), build_epoch))
+}
+
+fn load_base_networks(cli: &Cli) -> Result<(sources::Networks, u64), Box<dyn std::error::Error>> {
+    if cli.discrepancy_report.is_some() && cli.sources.len() < 2 {
+        eprintln!("警告: --discrepancy-report は --source を 2 つ以上指定したときだけ書き出します");
+    }
+    if cli.sources.is_empty() {
+        return load_source(cli, &cli.source_format, &cli.db, cli.db_sha256.as_deref());
+    }
+    let mut all = Vec::new();
+    let mut build_epoch = u64::MAX;
+    for spec in &cli.sources {
+        let (networks, epoch) = load_source(cli, &spec.format, &spec.path, None).map_err(|e| format!("{}: {}", spec.path, e))?;
+        println!("  {}: {} ネットワーク", spec.path, networks.len());
+        all.push(networks);
+        // 鮮度の判定は最も古い入力元に合わせる
+        build_epoch = build_epoch.min(epoch);
+    }
+    if let Some(path) = &cli.discrepancy_report {
+        let labels: Vec<&str> = cli.sources.iter().map(|spec| spec.path.as_str()).collect();
+        let (report, ranges, addresses) = sources::discrepancy_report(&labels, &all, cli.conflict_policy, &domestic_rules(cli));
+        std::fs::write(path, report)?;
+        println!("  判定の食い違い: {} 範囲 ({} アドレス) → {}", ranges, addresses, path);
+    }
+    Ok((sources::merge(&all, cli.conflict_policy, &domestic_rules(cli)), build_epoch))
+}
+
+/// 形式に応じて 1 つの入力元を読み込む
+fn load_source(cli: &Cli, format: &str, path: &str, sha256: Option<&str>) -> Result<(sources::Networks, u64), Box<dyn std::error::Error>> {
+    let fetcher = sources::Fetcher {
+        cache_dir: cli.db_cache.clone().unwrap_or_else(std::env::temp_dir),
+        sha256,
+        dry_run: cli.dry_run,
+    };
+    sources::load(format, path, &fetcher, &domestic_rules(cli))
+}
+
+/// --low-memory で読む .mmdb のパス。ネットワークの一覧を持たないので、それが要る機能とは併用できない
+fn low_memory_db(cli: &Cli) -> Result<String, Box<dyn std::error::Error>> {
+    if !cli.sources.is_empty() || !cli.geofeed.is_empty() {
+        return Err("--low-memory は --source や --geofeed と併用できません".into());
+    }
+    let formats = std::iter::once(cli.format).chain(cli.also.iter().map(|also| also.format));
+    if let Some(format) = formats.into_iter().find(|format| format.needs_networks(&metadata(cli, 0, &[]))) {
+        return Err(format!("{} 形式は国別情報が必要なため --low-memory では出力できません", format.label()).into());
+    }
+    let path = resolve_db(cli)?;
+    if !sources::is_mmdb(&cli.source_format, &path) || path.to_ascii_lowercase().ends_with(".gz") {
+        return Err(format!("--low-memory は展開済みの .mmdb だけに対応しています: {}", path).into());
+    }
+    Ok(path)
+}
+
+fn process_geolite2_networks(cli: &Cli) -> Result<Classification, Box<dyn std::error::Error>> {
+    println!("GeoLite2データベースを読み込み中...");
+    let mut timings = timings::Timings::default();
+    let builder = if cli.low_memory {
+        IpcheckBuilder::new().db(low_memory_db(cli)?).low_memory(true)
+    } else {
+        let (networks, build_epoch) = timings.time("データベースの走査", || load_networks(cli))?;
+        IpcheckBuilder::new().networks(networks, build_epoch)
+    };
+    
+    println!("ネットワーク情報を取得中...");
+    // 国の判定に関係なく海外リストから除く・加えるネットワーク
+    let (allow, block) = timings.time("例外リストの読み込み", || collect_exceptions(cli))?;
+    let ipcheck = builder
+        .allow(&cli.domestic_country)
+        .domestic_subdivisions(&cli.domestic_subdivision)
+        .ranges(cli.ranges.iter().copied())
+        .exclude(allow)
+        .include(block)
+        .build()?;
+    let build_epoch = ipcheck.build_epoch;
+    if cli.low_memory {
+        timings.add("走査・分類・集約 (--low-memory)", ipcheck.timings.walk);
+    } else {
+        timings.add("分類", ipcheck.timings.classify);
+        timings.add("ソート", ipcheck.timings.sort);
+        timings.add("集約", ipcheck.timings.aggregate);
+    }
+
+    println!("\nネットワーク処理完了:");
+    if cli.low_memory {
+        println!("  総ネットワーク数: {}", ipcheck.domestic_networks + ipcheck.foreign_networks);
+    } else {
+        println!("  総ネットワーク数: {}", ipcheck.networks.len());
+    }
+    println!("  日本のネットワーク: {}", ipcheck.domestic_networks);
+    println!("  海外のネットワーク: {}", ipcheck.foreign_networks);
+    println!("CIDR最適化: {} -> {} ブロック", ipcheck.foreign_networks, ipcheck.foreign.len());
+    let optimized_blocks = ipcheck.foreign.blocks().to_vec();
+
+    let sort_start = std::time::Instant::now();
+    let mut result: Vec<String> = optimized_blocks.iter()
+        .map(|block| block.to_string())
+        .collect();
+    
+    result.sort_by(|a, b| {
+        let parse_ip = |s: &str| -> (u32, u8) {
+            let parts: Vec<&str> = s.split('/').collect();
+            let ip_parts: Vec<u32> = parts[0].split('.').map(|x| x.parse().unwrap()).collect();
+            let ip = (ip_parts[0] << 24) | (ip_parts[1] << 16) | (ip_parts[2] << 8) | ip_parts[3];
+            let prefix: u8 = parts[1].parse().unwrap();
+            (ip, prefix)
+        };
+        
+        let (ip_a, prefix_a) = parse_ip(a);
+        let (ip_b, prefix_b) = parse_ip(b);
+        ip_a.cmp(&ip_b).then(prefix_a.cmp(&prefix_b))
+    });
+    timings.add("ソート", sort_start.elapsed());
+    
+    Ok(Classification {
+        networks: ipcheck.networks,
+        domestic_networks: ipcheck.domestic_networks,
+        foreign_networks: ipcheck.foreign_networks,
+        foreign_blocks: optimized_blocks,
+        foreign: result,
+        build_epoch,
+        timings,
+    })
+}
+
+fn anonymous_flags(cli: &Cli) -> anonymous::AnonymousFlags {
+    anonymous::AnonymousFlags {
+        vpn: cli.block_vpn,
+        hosting: cli.block_hosting,
+        tor: cli.block_tor,
+        residential_proxy: cli.block_residential_proxy,
+    }
+}
+
+/// URL またはパスのフィードを読み込む (URL はキャッシュし、前回から変更がなければ再取得しない)
+fn load_feed(cli: &Cli, url: &str) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    let path = resolve_path(cli, url, None)?;
+    let (blocks, skipped) = feeds::read_feed(std::path::Path::new(&path)).map_err(|e| format!("{}: {}", url, e))?;
+    if skipped > 0 {
+        eprintln!("警告: {} の {} 行を解釈できなかったため無視しました", url, skipped);
+    }
+    Ok(blocks)
+}
+
+/// --allow-cloud に一致する公開レンジ。プロバイダごとに一覧を 1 回だけ取得する
+fn cloud_networks(cli: &Cli) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    use cloud_ranges::Provider;
+
+    let mut blocks = Vec::new();
+    for provider in [Provider::Aws, Provider::Gcp, Provider::Azure, Provider::Cloudflare] {
+        let selectors: Vec<_> = cli.allow_cloud.iter().filter(|s| s.provider == provider).collect();
+        if selectors.is_empty() {
+            continue;
+        }
+        let url = match provider {
+            Provider::Aws => cloud_ranges::AWS_IP_RANGES,
+            Provider::Gcp => cloud_ranges::GCP_IP_RANGES,
+            Provider::Azure => cli.azure_service_tags.as_deref().ok_or("--allow-cloud azure には --azure-service-tags が必要です")?,
+            Provider::Cloudflare => cloud_ranges::CLOUDFLARE_IPS_V4,
+        };
+        let content = std::fs::read(resolve_path(cli, url, None)?)?;
+        let ranges = match provider {
+            Provider::Aws => cloud_ranges::parse_aws(&content),
+            Provider::Gcp => cloud_ranges::parse_gcp(&content),
+            Provider::Azure => cloud_ranges::parse_azure(&content),
+            Provider::Cloudflare => Ok(cloud_ranges::parse_cloudflare(&String::from_utf8_lossy(&content))),
+        }
+        .map_err(|e| format!("{}: {}", url, e))?;
+        blocks.extend(ranges.iter().filter(|r| selectors.iter().any(|s| s.matches(r))).map(|r| r.block));
+    }
+    Ok(blocks)
+}
+
+/// 国の判定に関係なく海外リストから除くネットワークと加えるネットワークを補助データベースから集める
+fn collect_exceptions(cli: &Cli) -> Result<(Vec<NetworkBlock>, Vec<NetworkBlock>), Box<dyn std::error::Error>> {
+    let (mut allow, mut block) = (Vec::new(), Vec::new());
+    if !cli.allow_asn.is_empty() || !cli.block_asn.is_empty() {
+        let reader = open_mmdb(&resolve_path(cli, &cli.asn_db, None)?).map_err(|e| format!("{}: {}", cli.asn_db, e))?;
+        let (asn_allow, asn_block) = (asn::networks_of(&reader, &cli.allow_asn)?, asn::networks_of(&reader, &cli.block_asn)?);
+        println!("ASN 例外: 許可 {} ネットワーク, 強制 {} ネットワーク", asn_allow.len(), asn_block.len());
+        allow.extend(asn_allow);
+        block.extend(asn_block);
+    }
+    if !cli.allow_connection_type.is_empty() {
+        let reader = open_mmdb(&resolve_path(cli, &cli.connection_type_db, None)?).map_err(|e| format!("{}: {}", cli.connection_type_db, e))?;
+        let networks = connection::networks_of_types(&reader, &cli.allow_connection_type)?;
+        println!("回線種別による除外: {} ネットワーク", networks.len());
+        allow.extend(networks);
+    }
+    if !cli.allow_isp.is_empty() {
+        let reader = open_mmdb(&resolve_path(cli, &cli.isp_db, None)?).map_err(|e| format!("{}: {}", cli.isp_db, e))?;
+        let networks = isp::networks_of_isps(&reader, &cli.allow_isp)?;
+        println!("ISP による除外: {} ネットワーク", networks.len());
+        allow.extend(networks);
+    }
+    if let Some(action) = cli.tor_exits {
+        let exits = load_feed(cli, &cli.tor_exit_list)?;
+        println!("Tor 出口ノード: {} アドレス", exits.len());
+        match action {
+            feeds::FeedAction::Block => block.extend(exits),
+            feeds::FeedAction::Allow => allow.extend(exits),
+        }
+    }
+    if cli.bogons {
+        let bogons = load_feed(cli, &cli.bogons_list)?;
+        println!("bogon: {} ネットワーク", bogons.len());
+        block.extend(bogons);
+    }
+    if cli.spamhaus_drop {
+        let mut drop = Vec::new();
+        for url in &cli.spamhaus_drop_list {
+            drop.extend(load_feed(cli, url)?);
+        }
+        println!("Spamhaus DROP: {} ネットワーク", drop.len());
+        block.extend(drop);
+    }
+    for (name, url) in cli.firehol.iter().map(|name| (name.as_str(), feeds::firehol_url(name))).chain(cli.feeds.iter().map(|url| (url.as_str(), url.clone()))) {
+        let listed = load_feed(cli, &url)?;
+        println!("フィード {}: {} ネットワーク", name, listed.len());
+        block.extend(listed);
+    }
+    if let Some(key) = &cli.abuseipdb_key {
+        let cache_dir = cli.db_cache.clone().unwrap_or_else(std::env::temp_dir);
+        let abusive = feeds::fetch_abuseipdb(key, cli.abuseipdb_confidence, &cache_dir, cli.dry_run)?;
+        println!("AbuseIPDB (信頼度 {} 以上): {} アドレス", cli.abuseipdb_confidence, abusive.len());
+        block.extend(abusive);
+    }
+    if !cli.allow_cloud.is_empty() {
+        let cloud = cloud_networks(cli)?;
+        println!("クラウドのレンジによる除外: {} ネットワーク", cloud.len());
+        allow.extend(cloud);
+    }
+    let flags = anonymous_flags(cli);
+    if flags.any() {
+        let reader = open_mmdb(&resolve_path(cli, &cli.anonymous_db, None)?).map_err(|e| format!("{}: {}", cli.anonymous_db, e))?;
+        let anonymous = anonymous::networks_flagged(&reader, flags)?;
+        println!("匿名化ネットワーク: {} ネットワークを強制", anonymous.len());
+        block.extend(anonymous);
+    }
+    Ok((allow, block))
+}
+
+/// --domestic-country / --domestic-subdivision / --range から作る判定規則
+fn domestic_rules(cli: &Cli) -> rules::Rules {
+    rules::Rules {
+        domestic_countries: cli.domestic_country.clone(),
+        domestic_subdivisions: cli.domestic_subdivision.clone(),
+        ranges: cli.ranges.clone(),
+    }
+}
+
+/// 出力形式に渡す情報。networks は既存のリストから出力するときは空
+fn metadata<'a>(cli: &'a Cli, build_epoch: u64, networks: &'a [(NetworkBlock, Option<String>)]) -> output::Metadata<'a> {
+    output::Metadata {
+        build_epoch,
+        generated_at: cli.timestamp.resolve(build_epoch),
+        feeds: merged_feeds(cli),
+        networks,
+        rules: domestic_rules(cli),
+        options: [
+            ("set-name", cli.set_name.clone()),
+            ("nft-table", cli.nft_table.clone()),
+            ("redis-key", cli.redis_key.clone()),
+            ("redis-mode", value_name(cli.redis_mode)),
+            ("rpz-zone", cli.rpz_zone.clone()),
+            ("bpf-map", cli.bpf_map.clone()),
+            ("mmdb-record", value_name(cli.mmdb_record)),
+            ("hilbert-order", cli.hilbert_order.to_string()),
+            ("minecraft-mode", value_name(cli.minecraft_mode)),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .chain(cli.format_option.iter().cloned())
+        .collect(),
+        previous: None,
+    }
+}
+
+/// 集約済みの CIDR だけから to の形式を描画する
+fn render_list(cli: &Cli, to: &dyn OutputWriter, blocks: Vec<NetworkBlock>, build_epoch: u64) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let metadata = metadata(cli, build_epoch, &[]);
+    if to.needs_networks(&metadata) {
+        return Err(format!("{} 形式は国別情報が必要なためリストからは出力できません (データベースから生成してください)", to.label()).into());
+    }
+    output::render(to, &CidrSet::from_aggregated(blocks), &metadata)
+}
+
+/// 有効になっている第三者フィードの名前
+fn merged_feeds(cli: &Cli) -> Vec<&str> {
+    let mut feeds = Vec::new();
+    if cli.tor_exits.is_some() {
+        feeds.push("tor-exits");
+    }
+    if cli.bogons {
+        feeds.push("fullbogons");
+    }
+    if cli.spamhaus_drop {
+        feeds.push("spamhaus-drop");
+    }
+    if cli.abuseipdb_key.is_some() {
+        feeds.push("abuseipdb");
+    }
+    feeds.extend(cli.firehol.iter().map(String::as_str));
+    feeds.extend(cli.feeds.iter().map(String::as_str));
+    feeds
+}
+
+fn run_convert(cli: &Cli, input: &str, to: &dyn OutputWriter, output_path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let blocks = list::read_list(input)?;
+    let entries = blocks.len();
+    let output_path = match output_path {
+        Some(path) => path.to_string(),
+        None => std::path::Path::new(input).with_extension(to.extension()).display().to_string(),
+    };
+    let bytes = render_list(cli, to, blocks, 0)?;
+    File::create(&output_path)?.write_all(&bytes)?;
+    println!("{} ({} エントリ) → {} ({})", input, entries, output_path, to.label());
+    Ok(())
+}
+
+fn read_list_or_stdin(path: &str) -> Result<Vec<NetworkBlock>, Box<dyn std::error::Error>> {
+    if path == "-" {
+        list::read_text(std::io::stdin().lock())
+    } else {
+        list::read_list(path)
+    }
+}
+
+fn write_or_stdout(path: Option<&str>, bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    match path {
+        Some(path) => File::create(path)?.write_all(bytes)?,
+        None => std::io::stdout().lock().write_all(bytes)?,
+    }
+    Ok(())
+}
+
+fn run_chunk(cli: &Cli, input: &str, size: usize, output_dir: &str, prefix: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let blocks = read_list_or_stdin(input)?;
+    let index = chunk::write(std::path::Path::new(output_dir), prefix, &blocks, size, cli.timestamp.resolve(0))?;
+    println!("{} エントリ → {} ファイル ({} 件ずつ, 索引 {}.index.json)", index.total_entries, index.chunks.len(), size, prefix);
+    Ok(())
+}
+
+fn run_optimize(cli: &Cli, inputs: &[String], to: &dyn OutputWriter, output_path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut blocks = Vec::new();
+    for input in inputs {
+        let list = read_list_or_stdin(input)?;
+        if inputs.len() > 1 {
+            eprintln!("{}: {} エントリ", input, list.len());
+        }
+        blocks.extend(list);
+    }
+    let total = blocks.len();
+    // 同じアドレスの集合のまま最小にする (/24 への丸めはしない)
+    let optimized = union_blocks(&blocks);
+    let entries = optimized.len();
+
+    let bytes = render_list(cli, to, optimized, 0)?;
+    write_or_stdout(output_path, &bytes)?;
+    eprintln!("最適化完了: {} エントリ → {} エントリ", total, entries);
+    Ok(())
+}
+
+#[test]
+fn test_optimize_exact() {
+    let dir = std::env::temp_dir().join(format!("ipcheck-optimize-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("list.txt");
+    let output_path = dir.join("optimized.txt");
+    std::fs::write(&input, "1.2.3.0/32\n5.5.5.5/32\n8.0.0.0/24\n8.0.1.0/24\n8.0.0.128/25\n").unwrap();
+    let cli = Cli::parse_from(["ipcheck"]);
+    let text = output::find("text").unwrap();
+    run_optimize(&cli, &[input.display().to_string()], text, output_path.to_str()).unwrap();
+    let optimized = std::fs::read_to_string(&output_path).unwrap();
+    assert_eq!(optimized.lines().collect::<Vec<_>>(), ["1.2.3.0/32", "5.5.5.5/32", "8.0.0.0/23"]);
+
+    // merge: 第三者フィードの /32 や半端な /25 も和集合に残る
+    let feed = dir.join("feed.txt");
+    std::fs::write(&feed, "1.2.3.1/32\n10.0.0.128/25\n8.0.2.0/24\n").unwrap();
+    run_optimize(&cli, &[input.display().to_string(), feed.display().to_string()], text, output_path.to_str()).unwrap();
+    let merged = std::fs::read_to_string(&output_path).unwrap();
+    assert_eq!(merged.lines().collect::<Vec<_>>(), ["1.2.3.0/31", "5.5.5.5/32", "8.0.0.0/23", "8.0.2.0/24", "10.0.0.128/25"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn run_asn(cli: &Cli, asns: &[u32], to: &dyn OutputWriter, output_path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = open_mmdb(&resolve_path(cli, &cli.asn_db, None)?).map_err(|e| format!("{}: {}", cli.asn_db, e))?;
+    let blocks = asn::networks_of(&reader, asns)?;
+    let total = blocks.len();
+    // AS が広報している /24 より細かいプレフィックスも丸めずに残す
+    let aggregated = union_blocks(&blocks);
+    let entries = aggregated.len();
+
+    let bytes = render_list(cli, to, aggregated, reader.metadata.build_epoch)?;
+    write_or_stdout(output_path, &bytes)?;
+    eprintln!("指定した AS のネットワーク: {} → {} エントリ", total, entries);
+    Ok(())
+}
+
+fn run_query(database: &str, ip: Ipv4Addr) -> Result<(), Box<dyn std::error::Error>> {
+    match output::sqlite::query(database, ip)? {
+        Some(hit) => {
+            println!("ネットワーク: {}", hit.block.to_string());
+            println!("国コード: {}", hit.country.as_deref().unwrap_or("不明"));
+            println!("海外判定: {}", if hit.foreign { "海外" } else { "国内" });
+        }
+        None => println!("{} に該当するネットワークはありません", ip),
+    }
+    Ok(())
+}
+
+fn run_check(list_path: &str, ip: Ipv4Addr) -> Result<(), Box<dyn std::error::Error>> {
+    if list_path.ends_with(".bin") {
+        let set = binary::RangeSet::from_bytes(std::fs::read(list_path)?)?;
+        match set.find(ip) {
+            Some((start, end)) => println!("{} は {} - {} に含まれています ({})", ip, Ipv4Addr::from(start), Ipv4Addr::from(end), list_path),
+            None => {
+                println!("{} は {} に含まれていません", ip, list_path);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    let blocks = list::read_list(list_path)?;
+    let target = ip_to_u32(ip);
+    match blocks.iter().find(|b| b.network <= target && target <= b.last()) {
+        Some(block) => println!("{} は {} に含まれています ({})", ip, block.to_string(), list_path),
+        None => {
+            println!("{} は {} に含まれていません", ip, list_path);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn run_diff(old_path: &str, new_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let old = list::read_list(old_path)?;
+    let new = list::read_list(new_path)?;
+    let d = diff::diff(&old, &new);
+
+    println!("=== 差分: {} → {} ===", old_path, new_path);
+    println!("エントリ数: {} → {}", old.len(), new.len());
+    println!("アドレス数: {} → {} ({:+.2}%)", d.old_addresses, d.new_addresses, d.percent_change());
+    println!("追加: {} アドレス ({} CIDR)", d.added_addresses, d.added.len());
+    println!("削除: {} アドレス ({} CIDR)", d.removed_addresses, d.removed.len());
+
+    if !d.added.is_empty() || !d.removed.is_empty() {
+        println!();
+    }
+    for block in &d.added {
+        println!("+ {}", block.to_string());
+    }
+    for block in &d.removed {
+        println!("- {}", block.to_string());
+    }
+    Ok(())
+}
+
+fn run_history(cli: &Cli, query: Option<&str>, limit: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let path = cli.history.as_deref().ok_or("--history で履歴データベースを指定してください")?;
+    let conn = history::open(path)?;
+    let Some(query) = query else {
+        println!("{:>5}  {:<23} {:>8} {:>8} {:>8}  データベース", "#", "生成日時", "CIDR数", "追加", "削除");
+        for run in history::runs(&conn, limit)? {
+            println!(
+                "{:>5}  {:<23} {:>8} {:>8} {:>8}  {}",
+                run.id,
+                output::format_epoch(run.generated_at),
+                run.cidrs,
+                run.added,
+                run.removed,
+                output::format_epoch(run.build_epoch)
+            );
+        }
+        return Ok(());
+    };
+    let block: NetworkBlock = if query.contains('/') {
+        query.parse()?
+    } else {
+        NetworkBlock::new(query.parse::<Ipv4Addr>()?.into(), 32)
+    };
+    let changes = history::changes(&conn, &block)?;
+    if changes.is_empty() {
+        println!("{} は記録された履歴の中で一度も変更されていません", query);
+    }
+    for change in &changes {
+        println!(
+            "#{}  {}  {} {}",
+            change.run_id,
+            output::format_epoch(change.generated_at),
+            if change.added { "+" } else { "-" },
+            change.block.to_string()
+        );
+    }
+    Ok(())
+}
+
+fn run_keygen(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let key = sign::SecretKey::generate()?;
+    let secret_path = format!("{}.key", name);
+    let public_path = format!("{}.pub", name);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&secret_path).map_err(|e| format!("{}: {}", secret_path, e))?.write_all(key.to_text().as_bytes())?;
+    std::fs::write(&public_path, key.public_key().to_text())?;
+    println!("秘密鍵: {} (--sign-key に指定する)", secret_path);
+    println!("公開鍵: {} (verify-sig -P や minisign -V -p に指定する)", public_path);
+    Ok(())
+}
+
+fn run_verify_sig(path: &str, pubkey: &str, sig_path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let pubkey = match std::fs::read_to_string(pubkey) {
+        Ok(text) => text,
+        Err(_) => pubkey.to_string(),
+    };
+    let pubkey = sign::PublicKey::parse(&pubkey)?;
+    let sig_path = sig_path.map(str::to_string).unwrap_or_else(|| format!("{}.minisig", path));
+    let minisig = std::fs::read_to_string(&sig_path).map_err(|e| format!("{}: {}", sig_path, e))?;
+    match pubkey.verify(&std::fs::read(path)?, &minisig) {
+        Ok(trusted_comment) => {
+            println!("署名を確認しました: {}", path);
+            println!("trusted comment: {}", trusted_comment);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("署名の検証に失敗しました: {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_verify_checksum(files: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut failed = 0;
+    for file in files {
+        let sidecar = if file.ends_with(".sha256") { file.clone() } else { format!("{}.sha256", file) };
+        for (path, result) in checksum::verify(std::path::Path::new(&sidecar))? {
+            match result {
+                Ok(()) => println!("{}: OK", path.display()),
+                Err(e) => {
+                    println!("{}: 失敗 ({})", path.display(), e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        eprintln!("{} 件のファイルがチェックサムと一致しません", failed);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_rollback(cli: &Cli, version: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let root = cli.versions.as_deref().ok_or("--versions で出力ディレクトリを指定してください")?;
+    let names = versions::list(root)?;
+    let target = match version {
+        Some(version) => names
+            .iter()
+            .find(|name| *name == version)
+            .ok_or_else(|| format!("版 {} はありません (残っている版: {})", version, names.join(", ")))?,
+        None => {
+            let current = versions::latest(root).and_then(|latest| names.iter().position(|name| *name == latest));
+            current
+                .unwrap_or(names.len())
+                .checked_sub(1)
+                .map(|i| &names[i])
+                .ok_or("戻せる以前の版がありません")?
+        }
+    };
+    versions::publish(root, target)?;
+    println!("{} を {} に向けました", root.join(versions::LATEST).display(), target);
+    Ok(())
+}
+
+fn run_verify(cli: &Cli, list_path: &str, limit: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = open_database(cli)?;
+    let blocks = list::read_list(list_path)?;
+    println!("検証中: {} ({} エントリ) を {} と照合", list_path, blocks.len(), cli.db);
+
+    let report = verify::verify(&reader, &domestic_rules(cli), &blocks)?;
+    println!("検証したネットワーク: {}", report.checked);
+    println!("リストに含まれていない海外ネットワーク: {}", report.missing.len());
+    for (block, iso_code) in report.missing.iter().take(limit) {
+        println!("  {} ({})", block.to_string(), iso_code.as_deref().unwrap_or("不明"));
+    }
+    println!("リストに含まれている国内ネットワーク: {}", report.overblocked.len());
+    for (block, iso_code) in report.overblocked.iter().take(limit) {
+        println!("  {} ({})", block.to_string(), iso_code.as_deref().unwrap_or("不明"));
+    }
+
+    if !report.is_ok() {
+        eprintln!("検証失敗: リストとデータベースの分類が一致しません");
+        std::process::exit(1);
+    }
+    println!("検証成功: リストはデータベースの分類と一致しています");
+    Ok(())
+}
+
+fn run_validate(list_path: &str, fix: bool, output: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = list::read_raw_entries(list_path)?;
+    let (issues, minimal) = validate::validate(&entries);
+
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    println!("エントリ数: {} / 問題: {} / 最小形: {} エントリ", entries.len(), issues.len(), minimal.len());
+
+    if fix {
+        let path = output.unwrap_or(list_path);
+        let cidrs: Vec<String> = minimal.iter().map(|b| b.to_string()).collect();
+        let content = if path.ends_with(".json") {
+            serde_json::to_string_pretty(&Output::new(cidrs, Vec::new()))?
+        } else {
+            cidrs.iter().map(|c| format!("{}\n", c)).collect()
+        };
+        File::create(path)?.write_all(content.as_bytes())?;
+        println!("正規化したリストを書き出しました: {}", path);
+    } else if !issues.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_bench(cli: &Cli, input: Option<&str>, iterations: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let blocks: Vec<NetworkBlock> = match input {
+        Some(path) => read_list_or_stdin(path)?,
+        None => {
+            let (networks, _) = load_networks(cli)?;
+            let rules = domestic_rules(cli);
+            networks.into_iter().filter(|(_, code)| rules.is_foreign(code.as_deref())).map(|(block, _)| block).collect()
+        }
+    };
+    println!("入力: {} ブロック / 各方式 {} 回", blocks.len(), iterations.max(1));
+    // 全角文字は幅 2 で数えて列をそろえる
+    println!("方式                中央値         最速   ピークメモリ   エントリ      テキスト");
+    if !cfg!(feature = "bench-alloc") {
+        println!("(ピークメモリは bench-alloc 機能を有効にしてビルドしたときだけ測ります)");
+    }
+    let mut outputs = Vec::new();
+    for (name, strategy) in bench::STRATEGIES {
+        let (m, output) = bench::measure(strategy, &blocks, iterations);
+        println!(
+            "{:<12} {:>10.3}ms {:>10.3}ms {:>14} {:>10} {:>12}B",
+            name,
+            m.median.as_secs_f64() * 1000.0,
+            m.fastest.as_secs_f64() * 1000.0,
+            m.peak_bytes.map_or("-".to_string(), |bytes| format!("{:.1}KiB", bytes as f64 / 1024.0)),
+            m.entries,
+            m.text_bytes
+        );
+        outputs.push((name, output));
+    }
+    for (name, output) in &outputs[1..] {
+        if *output != outputs[0].1 {
+            let d = diff::diff(&outputs[0].1, output);
+            println!(
+                "注意: {} の出力は {} と異なります (追加 {} / 削除 {} エントリ)",
+                name,
+                outputs[0].0,
+                d.added.len(),
+                d.removed.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_stats(cli: &Cli, top: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = open_database(cli)?;
+    let stats = stats::collect(&reader)?;
+
+    println!("=== データベース統計: {} ===", cli.db);
+    println!("作成日時: {}", output::format_epoch(reader.metadata.build_epoch));
+    println!(
+        "IPv4: {} ネットワーク / {} アドレス ({:.2}%)",
+        stats.networks_v4,
+        stats.addresses_v4,
+        stats::coverage_v4(stats.addresses_v4)
+    );
+    println!(
+        "IPv6: {} ネットワーク / {} アドレス ({:.6}%)",
+        stats.networks_v6,
+        stats.addresses_v6,
+        stats::coverage_v6(stats.addresses_v6)
+    );
+
+    println!("\n{:<4} {:>10} {:>14} {:>9} {:>10} {:>12}", "国", "v4 NW", "v4 アドレス", "v4 %", "v6 NW", "v6 %");
+    for (code, c) in &stats.countries {
+        println!(
+            "{:<4} {:>10} {:>14} {:>8.4}% {:>10} {:>11.6}%",
+            code,
+            c.networks_v4,
+            c.addresses_v4,
+            stats::coverage_v4(c.addresses_v4),
+            c.networks_v6,
+            stats::coverage_v6(c.addresses_v6)
+        );
+    }
+
+    if let Some(n) = top {
+        let (total_entries, ranking) = stats::top_countries(&reader, &domestic_rules(cli), n)?;
+        println!("\n=== 海外アドレス数 上位{}か国 (ブロックリスト: {} エントリ) ===", n, total_entries);
+        println!("{:>4} {:<4} {:>14} {:>8} {:>10} {:>10} {:>12}", "順位", "国", "アドレス", "割合", "NW", "エントリ", "許可時の削減");
+        for (i, c) in ranking.iter().enumerate() {
+            println!(
+                "{:>4} {:<4} {:>14} {:>7.2}% {:>10} {:>10} {:>12}",
+                i + 1,
+                c.code,
+                c.addresses,
+                c.share,
+                c.networks,
+                c.entries,
+                c.savings
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_lookup(cli: &Cli, ip: Ipv4Addr) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = open_database(cli)?;
+    let rules = domestic_rules(cli);
+    match lookup_network(&reader, &rules, ip)? {
+        Some((block, iso_code)) => {
+            println!("ネットワーク: {}", block.to_string());
+            println!("国コード: {}", iso_code.as_deref().unwrap_or("不明"));
+            println!("海外判定: {}", if rules.is_foreign(iso_code.as_deref()) { "海外" } else { "国内" });
+        }
+        None => {
+            println!("{} はデータベースに含まれていません (リストには含まれません)", ip);
+        }
+    }
+    Ok(())
+}
+
+/// 設定ファイルの共通の設定と profile の設定をコマンドラインの前に置いて解析し直す
+fn with_config(path: &std::path::Path, profile: Option<&str>) -> Result<Cli, Box<dyn std::error::Error>> {
+    let config = config::Config::read(path)?;
+    let mut args: Vec<std::ffi::OsString> = std::env::args_os().take(1).collect();
+    args.extend(config.args(profile)?.into_iter().map(Into::into));
+    args.extend(std::env::args_os().skip(1));
+    Cli::try_parse_from(args).map_err(|e| {
+        eprintln!("{} の設定を含めた引数を解釈できません", path.display());
+        e.exit()
+    })
+}
+
+/// プロファイルごとに自分自身を子プロセスとして実行する。
+/// syslog の送信先はプロセス全体の設定なので、プロファイルを 1 つずつ別のプロセスで扱う
+fn run_profiles(cli: &Cli, path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    if cli.command.is_some() {
+        return Err("--profile を複数指定できるのはリストの生成 (サブコマンドなし) だけです".into());
+    }
+    let config = config::Config::read(path)?;
+    for name in &cli.profile {
+        config.args(Some(name))?;
+    }
+    // --profile 以外の引数はそのまま子プロセスに渡す
+    let mut args = Vec::new();
+    let mut original = std::env::args_os().skip(1);
+    while let Some(arg) = original.next() {
+        if arg == "--profile" {
+            original.next();
+        } else if !arg.to_string_lossy().starts_with("--profile=") {
+            args.push(arg);
+        }
+    }
+    let exe = std::env::current_exe()?;
+    let (mut failed, mut changed) = (Vec::new(), false);
+    for name in &cli.profile {
+        println!("=== プロファイル: {} ===", name);
+        let status = std::process::Command::new(&exe).args(&args).args(["--profile", name]).status()?;
+        match status.code() {
+            Some(0) => {}
+            Some(EXIT_CHANGED) if cli.detailed_exit_code => changed = true,
+            _ => failed.push(name.as_str()),
+        }
+    }
+    if !failed.is_empty() {
+        eprintln!("エラー: 失敗したプロファイル: {}", failed.join(", "));
+        std::process::exit(1);
+    }
+    if changed {
+        std::process::exit(EXIT_CHANGED);
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    let mut cli = match cli.config.clone() {
+        Some(path) if cli.profile.len() > 1 => return run_profiles(&cli, &path),
+        Some(path) => with_config(&path, cli.profile.first().map(String::as_str))?,
+        None => cli,
+    };
+    // 地域コードは国内の国 (設定ファイルで変わることもある) が決まってから確かめる
+    cli.domestic_subdivision =
+        cli.domestic_subdivision.iter().map(|s| rules::normalize_subdivision(s, &cli.domestic_country)).collect::<Result<_, _>>()?;
+    if let Some(target) = &cli.syslog {
+        syslog::init(target).map_err(|e| format!("syslog に接続できません: {}", e))?;
+    }
+    match &cli.command {
+        Some(Command::ProtoSchema) => {
+            print!("{}", output::protobuf::SCHEMA);
+            return Ok(());
+        }
+        Some(Command::XdpProgram) => {
+            print!("{}", output::bpf::XDP_PROGRAM);
+            return Ok(());
+        }
+        Some(Command::Query { database, ip }) => return run_query(database, *ip),
+        Some(Command::Check { list, ip }) => return run_check(list, *ip),
+        Some(Command::Diff { old, new, emit_delta: Some(format) }) => {
+            let (added, removed) = diff::entry_delta(&list::read_list(old)?, &list::read_list(new)?);
+            let commands = match format {
+                DeltaFormat::Ipset => output::ipset::delta(&cli.set_name, &added, &removed),
+                DeltaFormat::Nft => output::nft::delta(&cli.nft_table, &cli.set_name, &added, &removed),
+                DeltaFormat::Bpf => output::bpf::delta(&cli.bpf_map, &added, &removed),
+            };
+            print!("{}", commands);
+            return Ok(());
+        }
+        Some(Command::Diff { old, new, .. }) => return run_diff(old, new),
+        Some(Command::Optimize { input, to, output }) => {
+            return run_optimize(&cli, std::slice::from_ref(input), *to, output.as_deref());
+        }
+        Some(Command::Merge { inputs, to, output }) => return run_optimize(&cli, inputs, *to, output.as_deref()),
+        Some(Command::Asn { asns, to, output }) => return run_asn(&cli, asns, *to, output.as_deref()),
+        Some(Command::Convert { input, to, output }) => return run_convert(&cli, input, *to, output.as_deref()),
+        Some(Command::Chunk { input, size, output_dir, prefix }) => return run_chunk(&cli, input, size.get(), output_dir, prefix),
+        Some(Command::Verify { list, limit }) => return run_verify(&cli, list, *limit),
+        Some(Command::Serve {
+            listen,
+            #[cfg(feature = "grpc")]
+            grpc_listen,
+        }) => {
+            let classification = process_geolite2_networks(&cli)?;
+            let reader = open_database(&cli)?;
+            let metadata = metadata(&cli, classification.build_epoch, &[]);
+            let mut state = serve::ServeState::new(reader, &classification.foreign_blocks, &metadata)?;
+            if let Some(path) = &cli.sign_key {
+                state.sign(&sign::SecretKey::read(path)?);
+            }
+            state.max_age(cli.max_age);
+            let state = std::sync::Arc::new(state);
+            #[cfg(feature = "grpc")]
+            if let Some(addr) = grpc_listen {
+                serve::grpc::spawn(addr, std::sync::Arc::clone(&state), cli.threads.map(|n| n.get()))?;
+            }
+            return serve::serve(listen, state, cli.threads.map_or(serve::WORKERS, |n| n.get()));
+        }
+        Some(Command::Dnsbl { zone, listen }) => {
+            let classification = process_geolite2_networks(&cli)?;
+            return dnsbl::serve(listen, &dnsbl::Dnsbl::new(zone, &classification.foreign_blocks));
+        }
+        Some(Command::Rpz { listen }) => {
+            let classification = process_geolite2_networks(&cli)?;
+            let serial = output::rpz::serial(classification.build_epoch, schedule::now_epoch());
+            let zone = rpz::RpzZone::new(&cli.rpz_zone, serial, &classification.foreign_blocks);
+            return rpz::serve(listen, zone, cli.threads.map_or(rpz::TCP_WORKERS, |n| n.get()));
+        }
+        Some(Command::Stats { top }) => return run_stats(&cli, *top),
+        Some(Command::Bench { input, iterations }) => return run_bench(&cli, input.as_deref(), *iterations),
+        Some(Command::History { query, limit }) => return run_history(&cli, query.as_deref(), *limit),
+        Some(Command::Rollback { version }) => return run_rollback(&cli, version.as_deref()),
+        Some(Command::Keygen { name }) => return run_keygen(name),
+        Some(Command::VerifyChecksum { files }) => return run_verify_checksum(files),
+        Some(Command::VerifySig { file, pubkey, sig }) => return run_verify_sig(file, pubkey, sig.as_deref()),
+        Some(Command::Validate { list, fix, output }) => return run_validate(list, *fix, output.as_deref()),
+        Some(Command::Lookup { ip: Some(ip), .. }) => return run_lookup(&cli, *ip),
+        Some(Command::Lookup { input, column, delimiter, output_format, .. }) => {
+            let reader = open_database(&cli)?;
+            let rules = domestic_rules(&cli);
+            let results = match input {
+                Some(path) => lookup::classify_bulk(&reader, &rules, std::io::BufReader::new(File::open(path)?), *column, *delimiter)?,
+                None => lookup::classify_bulk(&reader, &rules, std::io::stdin().lock(), *column, *delimiter)?,
+            };
+            lookup::write_results(&mut std::io::stdout().lock(), &results, *output_format)?;
+            return Ok(());
+        }
+        Some(Command::Daemon { every, cron }) => return run_daemon(&cli, *every, cron.as_ref()),
+        Some(Command::Watch { settle }) => return run_watch(&cli, *settle),
+        Some(Command::Tail { file, pattern, list, exec, cooldown, from_start }) => {
+            return run_tail(file, pattern.as_ref(), list, exec.as_deref(), *cooldown, *from_start);
+        }
+        Some(Command::Download { account_id, license_key, edition }) => return run_download(&cli.db, account_id, license_key, edition),
+        None => {}
+    }
+
+    match generate(&cli) {
+        Ok(true) if cli.detailed_exit_code => std::process::exit(EXIT_CHANGED),
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("エラー: {}", e);
+            notify_failure(&cli, &e);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// 出力先と同じディレクトリの一時ファイルへ書き出してから rename で置き換える
+fn write_output(
+    cli: &Cli,
+    classification: &Classification,
+    split: &std::collections::BTreeMap<String, Vec<NetworkBlock>>,
+    output_path: &str,
+    timings: &mut timings::Timings,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let key = cli.sign_key.as_deref().map(sign::SecretKey::read).transpose()?;
+    let written = match (cli.format.name(), cli.redis_url.as_deref()) {
+        ("redis", Some(url)) => {
+            println!("\nRedisへ投入中... ({})", url);
+            let commands = timings.time("シリアライズ", || output::redis::commands(&classification.foreign_blocks, &cli.redis_key, cli.redis_mode));
+            timings.time("Redis への送信", || output::redis::push(url, &commands))?;
+            output::redis::encode(&commands).len()
+        }
+        _ => {
+            let previous = match &cli.previous {
+                Some(path) => {
+                    let output: Output = serde_json::from_reader(File::open(path)?)?;
+                    let blocks = output.foreign.iter()
+                        .map(|cidr| cidr.parse())
+                        .collect::<Result<Vec<NetworkBlock>, _>>()?;
+                    Some(blocks)
+                }
+                None => None,
+            };
+            let metadata = output::Metadata {
+                previous: previous.as_deref(),
+                ..metadata(cli, classification.build_epoch, &classification.networks)
+            };
+            write_file(cli, &classification.foreign_blocks, cli.format, &metadata, output_path, key.as_ref(), timings)?
+        }
+    };
+    for also in &cli.also {
+        let metadata = also.metadata(metadata(cli, classification.build_epoch, &classification.networks));
+        write_file(cli, &classification.foreign_blocks, also.format, &metadata, &also_path(cli, also, output_path), key.as_ref(), timings)?;
+    }
+    for (name, blocks) in split {
+        let metadata = metadata(cli, classification.build_epoch, &[]);
+        write_file(cli, blocks, cli.format, &metadata, &split::path(output_path, name), key.as_ref(), timings)?;
+    }
+    if cli.binary_sidecar {
+        let sidecar_path = std::path::Path::new(output_path).with_extension("bin");
+        let tmp_path = sidecar_path.with_extension("bin.tmp");
+        println!("バイナリリスト出力中... ({})", sidecar_path.display());
+        let bytes = timings.time("シリアライズ", || binary::encode(&classification.foreign_blocks));
+        timings.time("書き出し", || {
+            File::create(&tmp_path)?.write_all(&bytes)?;
+            std::fs::rename(&tmp_path, &sidecar_path)
+        })?;
+        if let Some(key) = &key {
+            timings.time("署名", || write_signature(key, &sidecar_path, cli.timestamp.resolve(classification.build_epoch)))?;
+        }
+        if cli.checksum {
+            timings.time("書き出し", || checksum::write(&sidecar_path))?;
+        }
+    }
+    Ok(written)
+}
+
+/// format で一時ファイルに書いてから output_path へ rename し、署名とチェックサムを添える
+fn write_file(
+    cli: &Cli,
+    blocks: &[NetworkBlock],
+    format: &dyn OutputWriter,
+    metadata: &output::Metadata,
+    output_path: &str,
+    key: Option<&sign::SecretKey>,
+    timings: &mut timings::Timings,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let tmp_path = format!("{}.tmp", output_path);
+    // 例外の適用で細かくなったブロックもそのまま出力する (集約し直さない)
+    let set = CidrSet::from_aggregated(blocks.to_vec());
+    println!("\n{}出力中...", format.label());
+    let bytes = timings.time("シリアライズ", || output::render(format, &set, metadata))?;
+    timings.time("書き出し", || File::create(&tmp_path)?.write_all(&bytes))?;
+    timings.time("書き出し", || std::fs::rename(&tmp_path, output_path))?;
+    if let Some(key) = key {
+        timings.time("署名", || write_signature(key, std::path::Path::new(output_path), cli.timestamp.resolve(metadata.build_epoch)))?;
+    }
+    if cli.checksum {
+        timings.time("書き出し", || checksum::write(std::path::Path::new(output_path)))?;
+    }
+    Ok(bytes.len())
+}
+
+/// --split-by-country で書き出す国 (グループ) ごとのリスト。指定がなければ空
+fn split_parts(cli: &Cli, classification: &Classification) -> std::collections::BTreeMap<String, Vec<NetworkBlock>> {
+    if !cli.split_by_country {
+        return std::collections::BTreeMap::new();
+    }
+    let countries = split::by_country(&classification.networks, &domestic_rules(cli), &classification.foreign_blocks);
+    if cli.country_group.is_empty() {
+        countries
+    } else {
+        split::by_group(&countries, &cli.country_group)
+    }
+}
+
+/// --also の書き出し先。--versions では版のディレクトリに同じファイル名で置く
+fn also_path(cli: &Cli, also: &also::Also, output_path: &str) -> String {
+    let path = also.path(output_path);
+    match (&cli.versions, std::path::Path::new(output_path).parent(), std::path::Path::new(&path).file_name()) {
+        (Some(_), Some(dir), Some(name)) => dir.join(name).display().to_string(),
+        _ => path,
+    }
+}
+
+/// path の隣に path.minisig を書き出す
+fn write_signature(key: &sign::SecretKey, path: &std::path::Path, generated_at: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let minisig = key.sign(&std::fs::read(path)?, &sign::trusted_comment(generated_at, &name));
+    let sig_path = format!("{}.minisig", path.display());
+    let tmp_path = format!("{}.tmp", sig_path);
+    File::create(&tmp_path)?.write_all(minisig.as_bytes())?;
+    std::fs::rename(&tmp_path, &sig_path)?;
+    println!("署名を書き出しました: {}", sig_path);
+    Ok(())
+}
+
+/// 古いデータベースから生成したリストを配布してしまわないよう、ビルド日時を確認する
+fn check_freshness(cli: &Cli, build_epoch: u64) -> Result<(), Box<dyn std::error::Error>> {
+    // 日付のない RIR のファイルなどではビルド日時が 0 になる。1970 年のデータベースとして扱わず、確認を省く
+    if build_epoch == 0 {
+        let message = "データベースのビルド日時が分からないため、古さを確認できません (--max-age / --warn-age は効きません)";
+        eprintln!("警告: {}", message);
+        syslog::warning("freshness", message);
+        return Ok(());
+    }
+    let age = schedule::now_epoch().saturating_sub(build_epoch);
+    let built = output::format_epoch(build_epoch);
+    if let Some(max_age) = cli.max_age
+        && age > max_age.as_secs()
+    {
+        return Err(format!(
+            "データベースが古すぎます (ビルド日時 {}, {} 日前, 上限 {} 日)",
+            built,
+            age / 86400,
+            max_age.as_secs() / 86400
+        )
+        .into());
+    }
+    if age > cli.warn_age.as_secs() {
+        let message = format!("データベースが古くなっています (ビルド日時 {}, {} 日前)", built, age / 86400);
+        eprintln!("警告: {}", message);
+        syslog::warning("freshness", &message);
+    }
+    Ok(())
+}
+
+/// 切り詰められたデータベースなどから、不自然に小さいリストを出力してしまわないよう確認する
+fn check_thresholds(cli: &Cli, previous: Option<&[NetworkBlock]>, blocks: &[NetworkBlock]) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(min) = cli.fail_if_entries_below
+        && blocks.len() < min
+    {
+        return Err(format!("CIDR 数 {} が下限 {} を下回っているため出力しません", blocks.len(), min).into());
+    }
+    if let (Some(max_shrink), Some(previous)) = (cli.fail_if_shrink_percent, previous) {
+        let d = diff::diff(previous, blocks);
+        let shrink = -d.percent_change();
+        if shrink > max_shrink {
+            return Err(format!(
+                "アドレス数が前回の出力から {:.2}% 減っている ({} → {}, 上限 {}%) ため出力しません",
+                shrink, d.old_addresses, d.new_addresses, max_shrink
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// 前回の海外リストを読むファイル。--binary-sidecar があればその .bin を、なければ出力そのものを読む。
+/// 出力が海外リストそのものでない (Redis へ投入した、許可する側のリストを書いた) 場合は None
+fn previous_list_path(cli: &Cli, previous_output: &str) -> Option<String> {
+    if cli.binary_sidecar {
+        return Some(std::path::Path::new(previous_output).with_extension("bin").display().to_string());
+    }
+    let complement = match cli.format.name() {
+        "redis" => cli.redis_url.is_some(),
+        "sshd" | "geofeed" => true,
+        // YAML の mode: 行や引用符付きの CIDR は list::read_list で読めず、minecraft-json は通常の JSON 出力と
+        // 拡張子が同じで読み違えるので、どちらもリストの種類によらず比べない
+        "minecraft" | "minecraft-json" => true,
+        _ => false,
+    };
+    (!complement).then(|| previous_output.to_string())
+}
+
+/// 前回の海外リスト (初回は None)。--fail-if-shrink-percent があるのに前回のリストと比べられなければ、
+/// 切り詰められたデータベースを見逃さないようエラーにする
+fn read_previous(cli: &Cli, path: Option<&str>) -> Result<Option<Vec<NetworkBlock>>, Box<dyn std::error::Error>> {
+    let guarded = cli.fail_if_shrink_percent.is_some();
+    let Some(path) = path else {
+        if guarded {
+            return Err(format!("{} 形式の出力は海外リストとして読み直せないため --fail-if-shrink-percent には --binary-sidecar が必要です", cli.format.label()).into());
+        }
+        return Ok(None);
+    };
+    if !std::path::Path::new(path).exists() {
+        return Ok(None);
+    }
+    match list::read_list(path) {
+        Ok(blocks) => Ok(Some(blocks)),
+        Err(e) if guarded => Err(format!("前回の出力 {} を読めないため --fail-if-shrink-percent で比べられません: {}", path, e).into()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// --detailed-exit-code で、リストが前回の出力から変わったことを示す終了コード
+const EXIT_CHANGED: i32 = 3;
+
+/// 前回の出力がない場合も変わったとみなす
+fn list_changed(previous: Option<&[NetworkBlock]>, blocks: &[NetworkBlock]) -> bool {
+    previous.is_none_or(|previous| {
+        let d = diff::diff(previous, blocks);
+        d.added_addresses + d.removed_addresses > 0
+    })
+}
+
+/// データベースを分類して出力を書き出し、結果の概要を表示する。リストが前回の出力から変わったかを返す
+fn generate(cli: &Cli) -> Result<bool, Box<dyn std::error::Error>> {
+    let db_path = cli.db.as_str();
+    let output_path = cli.output.clone()
+        .unwrap_or_else(|| format!("foreign_ip_cidrs.{}", cli.format.extension()));
+    
+    if !cli.deploy.is_empty() && cli.format.name() == "redis" && cli.redis_url.is_some() {
+        return Err("Redis へ投入する出力は --deploy できません".into());
+    }
+    if let Some(also) = cli.also.iter().find(|also| also.path(&output_path) == output_path) {
+        return Err(format!("--also {} の書き出し先が --output と同じです: {}", also.format.name(), output_path).into());
+    }
+    if cli.split_by_country {
+        if cli.low_memory {
+            return Err("--split-by-country は国別の情報を使うため --low-memory と併用できません".into());
+        }
+        if cli.format.name() == "redis" && cli.redis_url.is_some() {
+            return Err("Redis へ投入する出力は --split-by-country で分けられません".into());
+        }
+        if cli.format.needs_networks(&metadata(cli, 0, &[])) {
+            return Err(format!("{} 形式は国別の情報を使うため --split-by-country では分けられません", cli.format.label()).into());
+        }
+    }
+
+    println!("=== 海外IP CIDR生成ツール ===");
+    println!("対象データベース: {}", db_path);
+    
+    let start_time = std::time::Instant::now();
+    
+    match process_geolite2_networks(cli) {
+        Ok(mut classification) => {
+            let mut timings = std::mem::take(&mut classification.timings);
+            check_freshness(cli, classification.build_epoch)?;
+            // --versions では前回の出力を latest から読む
+            let previous_path = match &cli.versions {
+                Some(root) => {
+                    let name = std::path::Path::new(&output_path).file_name().ok_or("--output にファイル名を指定してください")?;
+                    root.join(versions::LATEST).join(name).to_string_lossy().into_owned()
+                }
+                None => output_path.clone(),
+            };
+            // 差分の確認と通知のため、置き換える前の出力を読んでおく
+            let previous_path = previous_list_path(cli, &previous_path);
+            let previous = read_previous(cli, previous_path.as_deref())?;
+            check_thresholds(cli, previous.as_deref(), &classification.foreign_blocks)?;
+            let changed = list_changed(previous.as_deref(), &classification.foreign_blocks);
+            if cli.dry_run {
+                dry_run(cli, &classification, &output_path, previous_path.as_deref(), previous.as_deref())?;
+                return Ok(changed);
+            }
+            // --versions では日時のディレクトリへ書き出す
+            let (output_path, version) = match &cli.versions {
+                Some(root) => {
+                    let name = std::path::Path::new(&output_path).file_name().unwrap_or_default();
+                    let dir = versions::create(root, schedule::now_epoch())?;
+                    (dir.join(name).to_string_lossy().into_owned(), Some(dir))
+                }
+                None => (output_path, None),
+            };
+            let split = split_parts(cli, &classification);
+            let written = match write_output(cli, &classification, &split, &output_path, &mut timings) {
+                Ok(written) => written,
+                Err(e) => {
+                    if let Some(dir) = &version {
+                        let _ = std::fs::remove_dir_all(dir);
+                    }
+                    return Err(e);
+                }
+            };
+            if let (Some(root), Some(dir)) = (&cli.versions, &version) {
+                let name = dir.file_name().unwrap_or_default().to_string_lossy();
+                versions::publish(root, &name)?;
+                for name in versions::prune(root, cli.keep)? {
+                    println!("古い版を削除しました: {}", name);
+                }
+            }
+            if let Some(firewall) = cli.apply {
+                let metadata = metadata(cli, classification.build_epoch, &[]);
+                println!("{} へ適用中...", firewall.target(&metadata));
+                timings.time("適用", || firewall.apply(&metadata, &classification.foreign_blocks))?;
+                if cli.flush_conntrack {
+                    let flushed = timings.time("接続の切断", || conntrack::flush(&classification.foreign_blocks, previous.as_deref()))?;
+                    println!("新しく遮断した範囲の {} アドレスからの接続を切断しました", flushed);
+                }
+            }
+            let files = written_files(cli, &output_path, split.keys());
+            if !cli.deploy.is_empty() {
+                for target in &cli.deploy {
+                    println!("配置中... ({})", target);
+                    timings.time("配置", || target.deploy(&files, cli.deploy_reload.as_deref()))?;
+                }
+            }
+            let output = Output::new(classification.foreign, Vec::new());
+            
+            let elapsed = start_time.elapsed();
+            if let Some(path) = &cli.prom_textfile {
+                let generation = metrics::Generation {
+                    generated_at: schedule::now_epoch(),
+                    build_epoch: classification.build_epoch,
+                    duration: elapsed,
+                    database_networks: classification.domestic_networks + classification.foreign_networks,
+                    foreign_networks: classification.foreign_networks,
+                    list_networks: classification.foreign_blocks.len(),
+                    output_bytes: written,
+                };
+                let mut body = String::new();
+                generation.write(&mut body);
+                timings.time("書き出し", || metrics::write_textfile(path, &body)).map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+            if let Some(path) = &cli.history {
+                timings.time("履歴の記録", || -> rusqlite::Result<_> {
+                    let mut conn = history::open(path)?;
+                    history::record(&mut conn, &classification.foreign_blocks, schedule::now_epoch(), classification.build_epoch)
+                })?;
+            }
+            let hooks: Vec<&String> = cli.on_success.iter().chain(cli.on_change.iter().filter(|_| changed)).collect();
+            if notifies(cli) || !hooks.is_empty() {
+                let event = webhook::Event::new(
+                    &output_path,
+                    &classification.foreign_blocks,
+                    previous.as_deref(),
+                    classification.build_epoch,
+                    schedule::now_epoch(),
+                    elapsed.as_secs_f64(),
+                );
+                if notifies(cli) {
+                    timings.time("通知", || notify(cli, &event, &classification.foreign_blocks, previous.as_deref()));
+                }
+                let env = hooks::environment(&event, cli.format.name(), &files);
+                for command in hooks {
+                    println!("フックを実行中: {}", command);
+                    timings.time("フック", || hooks::run(command, &env))?;
+                }
+            }
+            
+            syslog::info("generate", &format!(
+                "{} を生成しました: {} CIDR, {:.2}秒, データベースのビルド日時 {}",
+                output_path,
+                output.foreign.len(),
+                elapsed.as_secs_f64(),
+                output::format_epoch(classification.build_epoch)
+            ));
+            println!("\n=== 処理完了 ===");
+            println!("出力ファイル: {}", output_path);
+            println!("CIDR数: {}", output.foreign.len());
+            println!("処理時間: {:.2}秒", elapsed.as_secs_f64());
+            println!("ファイルサイズ: {:.2} KB", written as f64 / 1024.0);
+            if cli.timings {
+                println!("\n=== 処理時間の内訳 ===");
+                for line in timings.report(start_time.elapsed()) {
+                    println!("{}", line);
+                }
+            }
+            
+            if !output.foreign.is_empty() {
+                println!("\n=== サンプル (最初の50件) ===");
+                for (i, cidr) in output.foreign.iter().take(50).enumerate() {
+                    println!("{:2}: {}", i + 1, cidr);
+                }
+                if output.foreign.len() > 50 {
+                    println!("... (残り{}件)", output.foreign.len() - 50);
+                }
+                
+                let mut prefix_counts = std::collections::BTreeMap::new();
+                for block in &classification.foreign_blocks {
+                    *prefix_counts.entry(block.prefix_len).or_insert(0) += 1;
+                }
+                
+                println!("\n=== プレフィックス長別統計 ===");
+                for (prefix, count) in prefix_counts {
+                    println!("/{}: {} ブロック", prefix, count);
+                }
+            }
+            Ok(changed)
+        }
+        Err(e) => {
+            Err(format!("{}\nファイル '{}' が存在することを確認してください。", e, db_path).into())
+        }
+    }
+}
+
+/// 今回書き出したファイル (Redis へ投入した場合はなし)
+fn written_files<'a>(cli: &Cli, output_path: &str, split_names: impl IntoIterator<Item = &'a String>) -> Vec<std::path::PathBuf> {
+    let mut files = Vec::new();
+    if cli.format.name() != "redis" || cli.redis_url.is_none() {
+        files = deploy::artifacts(std::path::Path::new(output_path), cli.sign_key.is_some(), cli.checksum, cli.binary_sidecar);
+    }
+    for also in &cli.also {
+        files.extend(deploy::artifacts(std::path::Path::new(&also_path(cli, also, output_path)), cli.sign_key.is_some(), cli.checksum, false));
+    }
+    for name in split_names {
+        files.extend(deploy::artifacts(std::path::Path::new(&split::path(output_path, name)), cli.sign_key.is_some(), cli.checksum, false));
+    }
+    files
+}
+
+/// --dry-run: 生成した場合に書き出すもの・送るものと、前回の出力からの差分を表示する
+fn dry_run(
+    cli: &Cli,
+    classification: &Classification,
+    output_path: &str,
+    previous_path: Option<&str>,
+    previous: Option<&[NetworkBlock]>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let blocks = &classification.foreign_blocks;
+    let path = match &cli.versions {
+        Some(root) => {
+            let name = std::path::Path::new(output_path).file_name().unwrap_or_default();
+            root.join(versions::dir_name(schedule::now_epoch())).join(name).display().to_string()
+        }
+        None => output_path.to_string(),
+    };
+
+    println!("\n=== ドライラン (何も書き込みません) ===");
+    println!("CIDR数: {}", blocks.len());
+    let mut targets = Vec::new();
+    match (cli.format.name(), cli.redis_url.as_deref()) {
+        ("redis", Some(url)) => {
+            let commands = output::redis::commands(blocks, &cli.redis_key, cli.redis_mode);
+            targets.push(format!("Redis {} へ {} コマンドを送信", url, commands.len()));
+        }
+        _ => {
+            let metadata = metadata(cli, classification.build_epoch, &classification.networks);
+            let bytes = output::render(cli.format, &CidrSet::from_aggregated(blocks.clone()), &metadata)?;
+            targets.push(format!("{} ({}, {:.2} KB)", path, cli.format.label(), bytes.len() as f64 / 1024.0));
+            if cli.sign_key.is_some() {
+                targets.push(format!("{}.minisig", path));
+            }
+            if cli.checksum {
+                targets.push(format!("{}.sha256", path));
+            }
+            if cli.binary_sidecar {
+                let sidecar = std::path::Path::new(&path).with_extension("bin").display().to_string();
+                targets.push(format!("{} ({:.2} KB)", sidecar, binary::encode(blocks).len() as f64 / 1024.0));
+            }
+        }
+    }
+    for also in &cli.also {
+        let metadata = also.metadata(metadata(cli, classification.build_epoch, &classification.networks));
+        let bytes = output::render(also.format, &CidrSet::from_aggregated(blocks.clone()), &metadata)?;
+        targets.push(format!("{} ({}, {:.2} KB)", also_path(cli, also, &path), also.format.label(), bytes.len() as f64 / 1024.0));
+    }
+    for (name, blocks) in split_parts(cli, classification) {
+        let bytes = output::render(cli.format, &CidrSet::from_aggregated(blocks.clone()), &metadata(cli, classification.build_epoch, &[]))?;
+        targets.push(format!("{} ({}, {} CIDR, {:.2} KB)", split::path(&path, &name), cli.format.label(), blocks.len(), bytes.len() as f64 / 1024.0));
+    }
+    if let Some(root) = &cli.versions {
+        targets.push(format!("{} (保持数 {})", root.join(versions::LATEST).display(), cli.keep));
+    }
+    if let Some(firewall) = cli.apply {
+        let metadata = metadata(cli, 0, &[]);
+        let (program, args, _) = firewall.command(&metadata, blocks)?;
+        targets.push(format!("{} {} で {} へ {} エントリを適用", program, args.join(" "), firewall.target(&metadata), blocks.len()));
+        if cli.flush_conntrack {
+            targets.push("conntrack で新しく遮断した範囲からの接続を切断".to_string());
+        }
+    }
+    for target in &cli.deploy {
+        match &cli.deploy_reload {
+            Some(reload) => targets.push(format!("{} へ配置して `{}` を実行", target, reload)),
+            None => targets.push(format!("{} へ配置", target)),
+        }
+    }
+    if let Some(path) = &cli.prom_textfile {
+        targets.push(path.display().to_string());
+    }
+    for command in &cli.on_success {
+        targets.push(format!("フック `{}`", command));
+    }
+    let changed = list_changed(previous, blocks);
+    for command in &cli.on_change {
+        targets.push(format!("変更時のフック `{}` ({})", command, if changed { "実行する" } else { "変更がないため実行しない" }));
+    }
+    if let Some(path) = &cli.history {
+        targets.push(format!("{} (履歴)", path));
+    }
+    for url in &cli.webhook {
+        targets.push(format!("webhook {}", url));
+    }
+    for (chat, _) in chat_webhooks(cli) {
+        let name = match chat {
+            webhook::Chat::Slack => "Slack",
+            webhook::Chat::Discord => "Discord",
+        };
+        targets.push(format!("{} への投稿", name));
+    }
+    if let Some(server) = &cli.smtp {
+        targets.push(format!("{} 経由で {} へメール", server, cli.mail_to.join(", ")));
+    }
+    println!("書き出し・送信先:");
+    for target in &targets {
+        println!("  {}", target);
+    }
+
+    match previous {
+        Some(previous) => {
+            let d = diff::diff(previous, blocks);
+            println!("前回の出力 ({}) からの差分:", previous_path.unwrap_or_default());
+            println!("  エントリ数: {} → {}", previous.len(), blocks.len());
+            println!("  アドレス数: {} → {} ({:+.2}%)", d.old_addresses, d.new_addresses, d.percent_change());
+            println!("  追加: {} アドレス ({} CIDR)", d.added_addresses, d.added.len());
+            println!("  削除: {} アドレス ({} CIDR)", d.removed_addresses, d.removed.len());
+            let changes = d.added.iter().map(|b| ('+', b)).chain(d.removed.iter().map(|b| ('-', b)));
+            for (sign, block) in changes.clone().take(50) {
+                println!("  {} {}", sign, block.to_string());
+            }
+            let total = d.added.len() + d.removed.len();
+            if total > 50 {
+                println!("  ... (残り{}件)", total - 50);
+            }
+        }
+        None => match previous_path {
+            Some(path) => println!("前回の出力 ({}) がないため、すべて新規になります", path),
+            None => println!("{} 形式の出力は海外リストとして読み直せないため、前回との差分は表示できません (--binary-sidecar で比べられます)", cli.format.label()),
+        },
+    }
+    Ok(())
+}
+
+fn chat_webhooks(cli: &Cli) -> Vec<(webhook::Chat, &str)> {
+    let mut chats = Vec::new();
+    if let Some(url) = &cli.slack_webhook {
+        chats.push((webhook::Chat::Slack, url.as_str()));
+    }
+    if let Some(url) = &cli.discord_webhook {
+        chats.push((webhook::Chat::Discord, url.as_str()));
+    }
+    chats
+}
+
+/// 生成後に通知する先があるか
+fn notifies(cli: &Cli) -> bool {
+    !cli.webhook.is_empty() || cli.slack_webhook.is_some() || cli.discord_webhook.is_some() || cli.smtp.is_some()
+}
+
+/// 通知の失敗は生成の失敗にせず、警告として表示と syslog に残す
+fn notify_warning(message: &str) {
+    eprintln!("警告: {}", message);
+    syslog::warning("notify", message);
+}
+
+fn send_webhook(url: &str, body: &str) {
+    match webhook::send(url, body) {
+        Ok(()) => println!("webhook 送信: {}", url),
+        Err(e) => notify_warning(&format!("webhook {} への送信に失敗しました: {}", url, e)),
+    }
+}
+
+/// 出力は書き終えているので、送信に失敗しても警告にとどめる
+fn notify(cli: &Cli, event: &webhook::Event, blocks: &[NetworkBlock], previous: Option<&[NetworkBlock]>) {
+    // --webhook-on-change は --webhook だけに効く。チャットとメールは毎回送る
+    if cli.webhook_on_change && !event.changed {
+        if !cli.webhook.is_empty() {
+            println!("\nリストに変更がないため webhook は送りません");
+        }
+    } else if !cli.webhook.is_empty() {
+        let body = match &cli.webhook_template {
+            Some(path) => std::fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|template| webhook::render(&template, event)).map_err(|e| format!("{}: {}", path, e)),
+            None => serde_json::to_string(event).map_err(|e| e.to_string()),
+        };
+        match body {
+            Ok(body) => {
+                for url in &cli.webhook {
+                    send_webhook(url, &body);
+                }
+            }
+            Err(e) => notify_warning(&format!("webhook の本文を作れないため送信しません ({})", e)),
+        }
+    }
+    let text = webhook::summary_text(event);
+    for (chat, url) in chat_webhooks(cli) {
+        send_webhook(url, &chat.body(&text));
+    }
+    if let Some(server) = &cli.smtp {
+        let attachment = match cli.mail_attach {
+            true => match std::fs::read(event.output) {
+                Ok(data) => Some(mail::Attachment {
+                    filename: std::path::Path::new(event.output).file_name().map_or_else(|| event.output.to_string(), |n| n.to_string_lossy().into_owned()),
+                    data,
+                }),
+                Err(e) => {
+                    notify_warning(&format!("{} を添付できないため、添付なしで送信します: {}", event.output, e));
+                    None
+                }
+            },
+            false => None,
+        };
+        let subject = format!("ipcheck: 海外リストを生成しました ({} CIDR)", event.cidrs);
+        let message = mail::message(&cli.mail_from, &cli.mail_to, &subject, &mail::report_text(event, blocks, previous), attachment.as_ref());
+        let credentials = cli.smtp_user.as_deref().zip(cli.smtp_password.as_deref()).map(|(user, password)| mail::Credentials { user, password });
+        match mail::send(server, credentials.as_ref(), &cli.mail_from, &cli.mail_to, &message) {
+            Ok(()) => println!("レポートを送信しました: {}", cli.mail_to.join(", ")),
+            Err(e) => notify_warning(&format!("レポートの送信に失敗しました: {}", e)),
+        }
+    }
+}
+
+/// 生成の失敗を syslog とチャットに知らせる
+fn notify_failure(cli: &Cli, error: &dyn std::fmt::Display) {
+    if cli.dry_run {
+        return;
+    }
+    syslog::error("generate", &format!("生成に失敗しました: {}", error));
+    let text = format!("ipcheck: 生成に失敗しました\n{}", error);
+    for (chat, url) in chat_webhooks(cli) {
+        send_webhook(url, &chat.body(&text));
+    }
+}
+
+fn run_download(db_path: &str, account_id: &str, license_key: &str, edition: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mmdb = download::download(edition, account_id, license_key)?;
+    // 壊れたファイルで既存のデータベースを置き換えないよう、開けることを確認してから配置する
+    let build_epoch = Reader::from_source(mmdb.as_slice())?.metadata.build_epoch;
+    let tmp_path = format!("{}.tmp", db_path);
+    File::create(&tmp_path)?.write_all(&mmdb)?;
+    std::fs::rename(&tmp_path, db_path)?;
+    println!("{} を配置しました (ビルド日時: {})", db_path, output::format_epoch(build_epoch));
+    Ok(())
+}
+
+/// 常駐してスケジュールどおりに生成を繰り返す。失敗しても次回の生成は続ける
+fn run_daemon(cli: &Cli, every: Option<std::time::Duration>, cron: Option<&schedule::Cron>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut first = true;
+    loop {
+        let status = match generate(cli) {
+            Ok(_) => format!("{} に生成しました", output::format_epoch(schedule::now_epoch())),
+            Err(e) => {
+                eprintln!("生成エラー: {}", e);
+                notify_failure(cli, &e);
+                format!("{} の生成に失敗しました: {}", output::format_epoch(schedule::now_epoch()), e)
+            }
+        };
+        // 初回の生成を終えてから起動完了とする (失敗しても次回の生成は続ける)
+        if std::mem::take(&mut first) {
+            systemd::ready(&status);
+        } else {
+            systemd::status(&status);
+        }
+        let now = schedule::now_epoch();
+        let next = match (every, cron) {
+            (Some(every), _) => now.saturating_add(every.as_secs().max(1)),
+            (None, Some(cron)) => cron.next_after(now),
+            (None, None) => unreachable!("clap で --every か --cron のどちらかを必須にしている"),
+        };
+        println!("\n次回の生成: {}", output::format_epoch(next));
+        syslog::info("schedule", &format!("次回の生成: {}", output::format_epoch(next)));
+        systemd::sleep(std::time::Duration::from_secs(next.saturating_sub(now)));
+    }
+}
+
+/// ログファイルを追いかけ、海外リストに含まれるアドレスが現れた行を表示する (--exec があればコマンドも実行する)。
+/// リストが書き換えられたら読み直す
+fn run_tail(
+    file: &std::path::Path,
+    pattern: Option<&tail::Pattern>,
+    list_path: &str,
+    exec: Option<&str>,
+    cooldown: std::time::Duration,
+    from_start: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let modified = || std::fs::metadata(list_path).and_then(|m| m.modified()).ok();
+    let mut list_modified = modified();
+    let mut set = CidrSet::from_blocks(list::read_list(list_path)?);
+    let mut follower = tail::Follower::open(file, from_start).map_err(|e| format!("{}: {}", file.display(), e))?;
+    let mut executed: std::collections::HashMap<Ipv4Addr, std::time::Instant> = std::collections::HashMap::new();
+    println!("{} を監視しています ({}: {} エントリ)", file.display(), list_path, set.len());
+    systemd::ready(&format!("{} を監視しています", file.display()));
+    loop {
+        while let Some(line) = follower.next_line()? {
+            if pattern.is_some_and(|p| !p.is_match(&line)) {
+                continue;
+            }
+            for ip in tail::addresses(&line) {
+                let Some(block) = set.find_block(ip) else { continue };
+                println!("海外 {} ({}): {}", ip, block.to_string(), line);
+                let Some(command) = exec else { continue };
+                if executed.get(&ip).is_some_and(|at| at.elapsed() < cooldown) {
+                    continue;
+                }
+                executed.insert(ip, std::time::Instant::now());
+                let env = [
+                    ("IPCHECK_IP", ip.to_string()),
+                    ("IPCHECK_CIDR", block.to_string()),
+                    ("IPCHECK_LINE", line.clone()),
+                    ("IPCHECK_FILE", file.display().to_string()),
+                ];
+                if let Err(e) = hooks::run(command, &env) {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+        if follower.rotated(file) {
+            match tail::Follower::open(file, true) {
+                Ok(reopened) => {
+                    println!("{} が置き換えられたので開き直しました", file.display());
+                    follower = reopened;
+                }
+                Err(e) => eprintln!("{}: {}", file.display(), e),
+            }
+        }
+        if modified() != list_modified {
+            list_modified = modified();
+            match list::read_list(list_path) {
+                Ok(blocks) => {
+                    set = CidrSet::from_blocks(blocks);
+                    println!("{} を読み直しました ({} エントリ)", list_path, set.len());
+                }
+                // 書き込み途中などで読めなければ、次の更新まで前のリストで判定を続ける
+                Err(e) => eprintln!("{} を読み直せません: {}", list_path, e),
+            }
+        }
+        executed.retain(|_, at| at.elapsed() < cooldown);
+        systemd::sleep(std::time::Duration::from_millis(500));
+    }
+}
+
+/// データベースのあるディレクトリを監視する。geoipupdate は新しいファイルを rename で置くため、ファイルではなくディレクトリを見る
+fn run_watch(cli: &Cli, settle: std::time::Duration) -> Result<(), Box<dyn std::error::Error>> {
+    use notify::{EventKind, RecursiveMode, Watcher};
+
+    if is_remote(&cli.db) || cli.db == sources::RIR_LATEST {
+        return Err("URL のデータベースは監視できません (daemon で定期的に取得してください)".into());
+    }
+    let db_path = std::path::absolute(&cli.db)?;
+    let dir = db_path.parent().ok_or("データベースのディレクトリが分かりません")?;
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    let is_db_event = |event: &notify::Result<notify::Event>| match event {
+        Ok(event) => !matches!(event.kind, EventKind::Access(_)) && event.paths.iter().any(|p| p.file_name() == db_path.file_name()),
+        Err(_) => false,
+    };
+
+    if let Err(e) = generate(cli) {
+        eprintln!("生成エラー: {}", e);
+        notify_failure(cli, &e);
+    }
+    println!("\n{} の更新を監視しています...", db_path.display());
+    systemd::ready(&format!("{} を監視しています", db_path.display()));
+    // 書き込みが落ち着くのを待つ間や生成中も止まらないよう、watchdog には別スレッドから知らせる
+    systemd::spawn_watchdog(|| true);
+    loop {
+        let event = rx.recv()?;
+        if let Err(e) = &event {
+            eprintln!("監視エラー: {}", e);
+        }
+        if !is_db_event(&event) {
+            continue;
+        }
+        // 一連の書き込みが落ち着くまで待つ
+        while rx.recv_timeout(settle).is_ok() {}
+        if !db_path.exists() {
+            continue;
+        }
+        println!("\nデータベースの更新を検知しました");
+        syslog::info("watch", &format!("{} の更新を検知しました", db_path.display()));
+        if let Err(e) = generate(cli) {
+            eprintln!("生成エラー: {}", e);
+            notify_failure(cli, &e);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Prometheus のテキスト形式 (exposition format) のメトリクス
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::lookup::LookupResult;
+
+/// ラベルの値を "" で囲める形にエスケープする
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// HELP と TYPE に続けて、ラベル付きの値を書き出す。labels が空のサンプルはラベルなし
+pub fn write_family(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(Vec<(&str, &str)>, f64)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(out, "{} {}", name, value);
+        } else {
+            let labels: Vec<String> = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape(v))).collect();
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), value);
+        }
+    }
+}
+
+/// ラベルなしの値を 1 つだけ持つメトリクス
+pub fn write_value(out: &mut String, name: &str, kind: &str, help: &str, value: f64) {
+    write_family(out, name, kind, help, &[(Vec::new(), value)]);
+}
+
+/// serve での判定の回数
+#[derive(Default)]
+pub struct Lookups {
+    foreign: AtomicU64,
+    domestic: AtomicU64,
+    errors: AtomicU64,
+    countries: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Lookups {
+    pub fn record(&self, result: &LookupResult) {
+        let counter = if result.foreign == Some(true) { &self.foreign } else { &self.domestic };
+        counter.fetch_add(1, Ordering::Relaxed);
+        let country = result.country.as_deref().unwrap_or("unknown");
+        *self.countries.lock().unwrap().entry(country.to_string()).or_default() += 1;
+    }
+
+    pub fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn write(&self, out: &mut String) {
+        write_family(out, "ipcheck_lookups_total", "counter", "判定した IP アドレスの数", &[
+            (vec![("result", "foreign")], self.foreign.load(Ordering::Relaxed) as f64),
+            (vec![("result", "domestic")], self.domestic.load(Ordering::Relaxed) as f64),
+        ]);
+        write_value(out, "ipcheck_lookup_errors_total", "counter", "データベースの読み込みに失敗した判定の数", self.errors.load(Ordering::Relaxed) as f64);
+        let countries = self.countries.lock().unwrap();
+        let samples: Vec<_> = countries.iter().map(|(country, count)| (vec![("country", country.as_str())], *count as f64)).collect();
+        write_family(out, "ipcheck_lookups_by_country_total", "counter", "国 (位置コード) ごとの判定の数。データベースにない場合は unknown", &samples);
+    }
+}
+
+/// 1 回の生成の結果
+pub struct Generation {
+    pub generated_at: u64,
+    pub build_epoch: u64,
+    pub duration: Duration,
+    pub database_networks: usize,
+    pub foreign_networks: usize,
+    pub list_networks: usize,
+    pub output_bytes: usize,
+}
+
+impl Generation {
+    pub fn write(&self, out: &mut String) {
+        write_value(out, "ipcheck_last_generation_timestamp_seconds", "gauge", "リストを生成した時刻", self.generated_at as f64);
+        write_value(out, "ipcheck_database_build_timestamp_seconds", "gauge", "元データベースのビルド日時", self.build_epoch as f64);
+        write_value(out, "ipcheck_generation_duration_seconds", "gauge", "生成にかかった時間", self.duration.as_secs_f64());
+        write_family(out, "ipcheck_database_networks", "gauge", "データベースの IPv4 ネットワーク数", &[
+            (vec![("class", "foreign")], self.foreign_networks as f64),
+            (vec![("class", "domestic")], (self.database_networks - self.foreign_networks) as f64),
+        ]);
+        write_value(out, "ipcheck_list_networks", "gauge", "出力した海外リストの CIDR 数", self.list_networks as f64);
+        write_value(out, "ipcheck_output_bytes", "gauge", "出力ファイルのサイズ", self.output_bytes as f64);
+    }
+}
+
+/// node_exporter が書きかけのファイルを読まないよう、一時ファイルに書いてから rename で置き換える
+pub fn write_textfile(path: &Path, body: &str) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("prom.tmp");
+    std::fs::write(&tmp_path, body)?;
+    std::fs::rename(&tmp_path, path)
+}
+
+#[test]
+fn test_lookup_metrics() {
+    let lookups = Lookups::default();
+    lookups.record(&LookupResult::new("8.8.8.8".parse().unwrap(), None, Some("US".to_string()), Some(true)));
+    lookups.record(&LookupResult::new("8.8.4.4".parse().unwrap(), None, Some("US".to_string()), Some(true)));
+    lookups.record(&LookupResult::new("10.0.0.1".parse().unwrap(), None, None, Some(false)));
+    let mut out = String::new();
+    lookups.write(&mut out);
+    assert!(out.contains("# TYPE ipcheck_lookups_total counter\n"));
+    assert!(out.contains("ipcheck_lookups_total{result=\"foreign\"} 2\n"));
+    assert!(out.contains("ipcheck_lookups_total{result=\"domestic\"} 1\n"));
+    assert!(out.contains("ipcheck_lookup_errors_total 0\n"));
+    assert!(out.contains("ipcheck_lookups_by_country_total{country=\"US\"} 2\n"));
+    assert!(out.contains("ipcheck_lookups_by_country_total{country=\"unknown\"} 1\n"));
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::sync::Arc;
+
+use crate::NetworkBlock;
+use crate::dns::{
+    self, CLASS_IN, Question, RCODE_FORMERR, RCODE_NOTIMP, RCODE_NXDOMAIN, RCODE_REFUSED, TYPE_ANY, TYPE_AXFR, TYPE_CNAME,
+    TYPE_IXFR, TYPE_NS, TYPE_SOA,
+};
+use crate::output::rpz::{EXPIRE, REFRESH, RETRY, TTL, owner};
+
+/// AXFR の 1 メッセージに詰めるレコード数 (64KiB に十分収まる)
+const RECORDS_PER_MESSAGE: usize = 500;
+/// --threads を指定しないときに TCP (AXFR/IXFR) を同時に応答する数
+pub const TCP_WORKERS: usize = 4;
+/// TCP の接続がこの時間読み書きできなければ閉じる。ワーカーの数は限られるので、黙ったままの接続に占有させない
+const TCP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+pub struct RpzZone {
+    /// 末尾のドットを除いた小文字のゾーン名
+    zone: String,
+    serial: u32,
+    soa: Vec<u8>,
+    ns: Vec<u8>,
+    /// SOA を除くゾーン内の全レコード (NS, CNAME)
+    records: Vec<Vec<u8>>,
+    /// オーナー名から records の位置への索引
+    names: HashMap<String, usize>,
+}
+
+fn soa_rdata(serial: u32) -> Vec<u8> {
+    let mut rdata = Vec::new();
+    dns::encode_name(&mut rdata, "localhost");
+    dns::encode_name(&mut rdata, "hostmaster.localhost");
+    for value in [serial, REFRESH, RETRY, EXPIRE, TTL] {
+        rdata.extend_from_slice(&value.to_be_bytes());
+    }
+    rdata
+}
+
+/// IXFR の権威セクションに入っている、クライアントが持つ SOA シリアル
+fn client_serial(packet: &[u8], question: &Question) -> Option<u32> {
+    if packet.get(8..10)? == [0, 0] {
+        return None;
+    }
+    let pos = dns::skip_name(packet, question.end)? + 10;
+    let pos = dns::skip_name(packet, pos)?;
+    let pos = dns::skip_name(packet, pos)?;
+    Some(u32::from_be_bytes(packet.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+impl RpzZone {
+    pub fn new(zone: &str, serial: u32, blocks: &[NetworkBlock]) -> Self {
+        let zone = zone.trim_end_matches('.').to_ascii_lowercase();
+        let soa = dns::record(Some(&zone), TYPE_SOA, TTL, &soa_rdata(serial));
+        let mut ns_rdata = Vec::new();
+        dns::encode_name(&mut ns_rdata, "localhost");
+        let ns = dns::record(Some(&zone), TYPE_NS, TTL, &ns_rdata);
+
+        let mut records = vec![ns.clone()];
+        let mut names = HashMap::new();
+        for block in blocks {
+            let name = format!("{}.{}", owner(block), zone);
+            names.insert(name.clone(), records.len());
+            // RDATA はルート (".") で、NXDOMAIN を返すポリシーを表す
+            records.push(dns::record(Some(&name), TYPE_CNAME, TTL, &[0]));
+        }
+        RpzZone { zone, serial, soa, ns, records, names }
+    }
+
+    /// SOA, 全レコード, SOA の順に並べたゾーン転送の応答
+    fn transfer(&self, query: &[u8], question: &Question) -> Vec<Vec<u8>> {
+        let mut all = Vec::with_capacity(self.records.len() + 2);
+        all.push(self.soa.clone());
+        all.extend(self.records.iter().cloned());
+        all.push(self.soa.clone());
+        all.chunks(RECORDS_PER_MESSAGE).map(|chunk| dns::reply(query, 0, Some(question), chunk)).collect()
+    }
+
+    /// 問い合わせに対する応答メッセージ列を作る。ゾーン転送は TCP でのみ受け付ける
+    pub fn respond(&self, packet: &[u8], tcp: bool) -> Vec<Vec<u8>> {
+        if packet.len() < 12 {
+            return Vec::new();
+        }
+        let flags = dns::header_flags(packet);
+        if flags & 0x8000 != 0 {
+            return Vec::new();
+        }
+        let reply = |rcode: u16, question: Option<&Question>, answers: &[Vec<u8>]| vec![dns::reply(packet, rcode, question, answers)];
+
+        if (flags >> 11) & 0xF != 0 {
+            return reply(RCODE_NOTIMP, None, &[]);
+        }
+        let Some(question) = dns::parse_question(packet).filter(|_| packet[4..6] == [0, 1]) else {
+            return reply(RCODE_FORMERR, None, &[]);
+        };
+        let in_zone = question.name == self.zone || question.name.ends_with(&format!(".{}", self.zone));
+        if question.qclass != CLASS_IN || !in_zone {
+            return reply(RCODE_REFUSED, Some(&question), &[]);
+        }
+
+        match question.qtype {
+            TYPE_AXFR if tcp => self.transfer(packet, &question),
+            TYPE_IXFR => match client_serial(packet, &question) {
+                // RFC 1982 のシリアル比較で、クライアントが最新なら SOA だけを返す
+                Some(serial) if serial.wrapping_sub(self.serial) as i32 >= 0 => reply(0, Some(&question), std::slice::from_ref(&self.soa)),
+                // 差分の履歴は持たないので AXFR 形式で全体を返す (RFC 1995 4 節)
+                _ if tcp => self.transfer(packet, &question),
+                _ => reply(0, Some(&question), std::slice::from_ref(&self.soa)),
+            },
+            TYPE_AXFR => reply(RCODE_REFUSED, Some(&question), &[]),
+            _ if question.name == self.zone => {
+                let answers: Vec<Vec<u8>> = match question.qtype {
+                    TYPE_SOA => vec![self.soa.clone()],
+                    TYPE_NS => vec![self.ns.clone()],
+                    TYPE_ANY => vec![self.soa.clone(), self.ns.clone()],
+                    _ => Vec::new(),
+                };
+                reply(0, Some(&question), &answers)
+            }
+            _ => match self.names.get(&question.name) {
+                Some(&idx) => reply(0, Some(&question), &[self.records[idx].clone()]),
+                None => reply(RCODE_NXDOMAIN, Some(&question), &[]),
+            },
+        }
+    }
+}
+
+fn handle_tcp(zone: &RpzZone, mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(TCP_TIMEOUT))?;
+    stream.set_write_timeout(Some(TCP_TIMEOUT))?;
+    loop {
+        let mut len = [0u8; 2];
+        if stream.read_exact(&mut len).is_err() {
+            return Ok(());
+        }
+        let mut packet = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut packet)?;
+        for message in zone.respond(&packet, true) {
+            stream.write_all(&(message.len() as u16).to_be_bytes())?;
+            stream.write_all(&message)?;
+        }
+    }
+}
+
+/// RPZ ゾーンを UDP と TCP (AXFR/IXFR) で配信し続ける。TCP は tcp_workers 本のスレッドで応答する
+pub fn serve(listen: &str, zone: RpzZone, tcp_workers: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let zone = Arc::new(zone);
+    let socket = UdpSocket::bind(listen)?;
+    let listener = TcpListener::bind(listen)?;
+    println!("RPZ サーバー起動: {} (ゾーン {}, シリアル {})", listen, zone.zone, zone.serial);
+
+    let udp_zone = Arc::clone(&zone);
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        loop {
+            let Ok((len, peer)) = socket.recv_from(&mut buf) else { continue };
+            for message in udp_zone.respond(&buf[..len], false) {
+                if let Err(e) = socket.send_to(&message, peer) {
+                    eprintln!("{} への応答エラー: {}", peer, e);
+                }
+            }
+        }
+    });
+
+    let workers = (0..tcp_workers)
+        .map(|_| {
+            let listener = listener.try_clone()?;
+            let zone = Arc::clone(&zone);
+            Ok(std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            eprintln!("接続エラー: {}", e);
+                            continue;
+                        }
+                    };
+                    if let Err(e) = handle_tcp(&zone, stream) {
+                        eprintln!("TCP 応答エラー: {}", e);
+                    }
+                }
+            }))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::output::civil_from_epoch;
+
+/// "30s", "15m", "24h", "7d" 形式の期間。単位を省略すると秒
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let number: u64 = number.parse().map_err(|_| format!("期間として解釈できません: {}", s))?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return Err(format!("期間の単位は s, m, h, d, w のいずれかです: {}", s)),
+    };
+    let seconds = number.checked_mul(seconds).ok_or_else(|| format!("期間が長すぎます: {}", s))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+pub fn now_epoch() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// 出力に記録する生成日時の決め方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timestamp {
+    /// 実行した時刻
+    Now,
+    /// 記録しない (同じ入力から同じバイト列を出力する)
+    Fixed,
+    /// データベースのビルド日時
+    Build,
+    /// 指定した UNIX 時刻 (SOURCE_DATE_EPOCH と同じ)
+    Epoch(u64),
+}
+
+impl FromStr for Timestamp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "now" => Ok(Timestamp::Now),
+            "fixed" => Ok(Timestamp::Fixed),
+            "build" => Ok(Timestamp::Build),
+            _ => s
+                .parse()
+                .map(Timestamp::Epoch)
+                .map_err(|_| format!("now, fixed, build または UNIX 時刻を指定してください: {}", s)),
+        }
+    }
+}
+
+impl Timestamp {
+    /// 出力に書く UNIX 時刻。0 は記録しないことを表す
+    pub fn resolve(self, build_epoch: u64) -> u64 {
+        match self {
+            Timestamp::Now => now_epoch(),
+            Timestamp::Fixed => 0,
+            Timestamp::Build => build_epoch,
+            Timestamp::Epoch(epoch) => epoch,
+        }
+    }
+}
+
+/// 5 フィールド (分 時 日 月 曜日) の cron 式。時刻は UTC で評価する
+#[derive(Clone, Debug)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// 日と曜日の両方が指定されている場合はどちらかに一致すればよい (cron の慣習)
+    day_or_weekday: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| format!("間隔が不正です: {}", part))?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (a.parse().map_err(|_| format!("範囲が不正です: {}", part))?, b.parse().map_err(|_| format!("範囲が不正です: {}", part))?),
+                None => {
+                    let value = range.parse().map_err(|_| format!("値が不正です: {}", part))?;
+                    // "5/10" は 5 から最大値まで
+                    (value, if part.contains('/') { max } else { value })
+                }
+            },
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(format!("{}-{} の範囲外です: {}", min, max, part));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err(format!("cron 式は 5 フィールド (分 時 日 月 曜日) で指定してください: {}", s));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        // 7 も日曜日
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Cron {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            day_or_weekday: *day != "*" && *weekday != "*",
+        })
+    }
+}
+
+impl Cron {
+    /// epoch より後で最初に一致する時刻 (分単位)
+    pub fn next_after(&self, epoch: u64) -> u64 {
+        let mut t = (epoch / 60 + 1) * 60;
+        // 存在しない日付 (2 月 30 日など) しか指定されていなくても止まらないよう 5 年で打ち切る
+        let limit = t + 5 * 366 * 86400;
+        while t < limit {
+            let (_, month, day, secs) = civil_from_epoch(t);
+            let weekday = (t / 86400 + 4) % 7;
+            let day_match = if self.day_or_weekday {
+                self.days & (1 << day) != 0 || self.weekdays & (1 << weekday) != 0
+            } else {
+                self.days & (1 << day) != 0 && self.weekdays & (1 << weekday) != 0
+            };
+            if self.months & (1 << month) == 0 || !day_match {
+                t = (t / 86400 + 1) * 86400;
+                continue;
+            }
+            if self.hours & (1 << (secs / 3600)) == 0 {
+                t = (t / 3600 + 1) * 3600;
+                continue;
+            }
+            if self.minutes & (1 << (secs / 60 % 60)) == 0 {
+                t += 60;
+                continue;
+            }
+            return t;
+        }
+        limit
+    }
+}
+
+#[test]
+fn test_cron_next_after() {
+    // 2023-11-14 22:13:20 UTC (火曜日)
+    let epoch = 1_700_000_000;
+    let daily: Cron = "30 3 * * *".parse().unwrap();
+    assert_eq!(daily.next_after(epoch), 1_700_019_000);
+    let monday: Cron = "0 0 * * 1".parse().unwrap();
+    assert_eq!(monday.next_after(epoch), 1_700_438_400);
+    assert!("61 * * * *".parse::<Cron>().is_err());
+    assert_eq!(parse_duration("24h"), Ok(Duration::from_secs(86400)));
+    assert!(parse_duration("99999999999999999w").is_err());
+}
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use maxminddb::{MaxMindDBError, Reader};
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::download::sha256_hex;
+use crate::lookup::LookupResult;
+use crate::metrics::{self, Lookups};
+use crate::output::{self, Metadata};
+use crate::rules::Rules;
+use crate::{CidrSet, NetworkBlock, checksum, lookup_network, schedule, sign, systemd};
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
+/// --threads を指定しないときの HTTP ワーカー数
+pub const WORKERS: usize = 4;
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// 起動時に生成しておく配信用のリスト
+struct Artifact {
+    body: Vec<u8>,
+    gzip: Vec<u8>,
+    etag: String,
+    content_type: &'static str,
+    /// --sign-key を指定したときの .minisig
+    signature: Option<String>,
+}
+
+impl Artifact {
+    fn new(body: Vec<u8>, content_type: &'static str) -> std::io::Result<Self> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&body)?;
+        Ok(Artifact { gzip: encoder.finish()?, etag: format!("\"{}\"", sha256_hex(&body)), body, content_type, signature: None })
+    }
+
+    /// gzip 版は別の表現なので ETag を区別する
+    fn gzip_etag(&self) -> String {
+        format!("{}-gzip\"", self.etag.trim_end_matches('"'))
+    }
+}
+
+pub struct ServeState {
+    reader: Reader<Vec<u8>>,
+    /// /lookup の判定に使う規則 (リストを作ったときと同じもの)
+    rules: Rules,
+    artifacts: HashMap<String, Artifact>,
+    build_epoch: u64,
+    /// リストを生成した時刻 (UNIX 時刻)
+    generated_at: u64,
+    list_size: usize,
+    lookups: Lookups,
+    /// --max-age。これより古いデータベースから作ったリストは /readyz で準備できていない扱いにする
+    max_age: Option<u64>,
+}
+
+impl ServeState {
+    pub fn new(reader: Reader<Vec<u8>>, blocks: &[NetworkBlock], metadata: &Metadata) -> Result<Self, Box<dyn std::error::Error>> {
+        let set = CidrSet::from_aggregated(blocks.to_vec());
+        let mut artifacts = HashMap::new();
+        // 国別の情報が必要な形式は配信しない
+        for writer in output::writers().into_iter().filter(|w| !w.needs_networks(metadata)) {
+            let body = output::render(writer, &set, metadata)?;
+            artifacts.insert(writer.name().to_string(), Artifact::new(body, writer.content_type())?);
+        }
+        Ok(ServeState {
+            reader,
+            rules: metadata.rules.clone(),
+            artifacts,
+            build_epoch: metadata.build_epoch,
+            generated_at: schedule::now_epoch(),
+            list_size: set.len(),
+            lookups: Lookups::default(),
+            max_age: None,
+        })
+    }
+
+    /// 配信するすべての形式に署名し、/list.minisig で返せるようにする
+    pub fn sign(&mut self, key: &sign::SecretKey) {
+        for (name, artifact) in &mut self.artifacts {
+            artifact.signature = Some(key.sign(&artifact.body, &sign::trusted_comment(self.generated_at, &file_name(name))));
+        }
+    }
+
+    pub fn max_age(&mut self, max_age: Option<std::time::Duration>) {
+        self.max_age = max_age.map(|age| age.as_secs());
+    }
+
+    /// /healthz と /readyz の本文
+    fn health(&self) -> Health {
+        let now = schedule::now_epoch();
+        let database_age_seconds = now.saturating_sub(self.build_epoch);
+        Health {
+            schema_version: output::SCHEMA_VERSION,
+            status: readiness(self.list_size, database_age_seconds, self.max_age),
+            list_networks: self.list_size,
+            generated_at: self.generated_at,
+            list_age_seconds: now.saturating_sub(self.generated_at),
+            build_epoch: self.build_epoch,
+            database_age_seconds,
+            max_age_seconds: self.max_age,
+        }
+    }
+
+    /// 1 つの IP アドレスを検索する。データベースにない場合は国内扱い
+    pub fn lookup(&self, ip: Ipv4Addr) -> Result<LookupResult, MaxMindDBError> {
+        let result = match lookup_network(&self.reader, &self.rules, ip) {
+            Ok(Some((block, iso_code))) => {
+                let foreign = self.rules.is_foreign(iso_code.as_deref());
+                LookupResult::new(ip, Some(block.to_string()), iso_code, Some(foreign))
+            }
+            Ok(None) => LookupResult::new(ip, None, None, Some(false)),
+            Err(e) => {
+                self.lookups.record_error();
+                return Err(e);
+            }
+        };
+        self.lookups.record(&result);
+        Ok(result)
+    }
+
+    /// /metrics の本文
+    fn metrics(&self) -> String {
+        let mut out = String::new();
+        self.lookups.write(&mut out);
+        metrics::write_value(&mut out, "ipcheck_list_networks", "gauge", "配信中の海外リストの CIDR 数", self.list_size as f64);
+        metrics::write_value(&mut out, "ipcheck_last_generation_timestamp_seconds", "gauge", "リストを生成した時刻", self.generated_at as f64);
+        metrics::write_value(&mut out, "ipcheck_database_build_timestamp_seconds", "gauge", "元データベースのビルド日時", self.build_epoch as f64);
+        out
+    }
+}
+
+#[derive(Serialize)]
+struct Health {
+    schema_version: u32,
+    /// ready / empty / stale
+    status: &'static str,
+    list_networks: usize,
+    generated_at: u64,
+    list_age_seconds: u64,
+    build_epoch: u64,
+    database_age_seconds: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_age_seconds: Option<u64>,
+}
+
+/// リストが空なら empty、データベースが max_age より古ければ stale
+fn readiness(list_size: usize, database_age: u64, max_age: Option<u64>) -> &'static str {
+    if list_size == 0 {
+        "empty"
+    } else if max_age.is_some_and(|max_age| database_age > max_age) {
+        "stale"
+    } else {
+        "ready"
+    }
+}
+
+/// HTTP-date (IMF-fixdate) 形式に変換する
+pub fn http_date(epoch: u64) -> String {
+    let (year, month, day, secs) = output::civil_from_epoch(epoch);
+    let weekday = ((epoch / 86400 + 4) % 7) as usize;
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[weekday],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// IMF-fixdate を UNIX 時刻に戻す。それ以外の形式は無視する
+fn parse_http_date(value: &str) -> Option<u64> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let [_, day, month, year, time, "GMT"] = parts.as_slice() else { return None };
+    let day: i64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| m == month)? as i64 + 1;
+    let year: i64 = year.parse().ok()?;
+    let mut hms = time.split(':').map(|n| n.parse::<u64>());
+    let (h, m, s) = (hms.next()?.ok()?, hms.next()?.ok()?, hms.next()?.ok()?);
+    output::epoch_from_civil(year, month, day).map(|midnight| midnight + h * 3600 + m * 60 + s)
+}
+
+fn request_header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("ヘッダーが不正です")
+}
+
+fn json_response(status: u16, body: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json; charset=utf-8"))
+}
+
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+    schema_version: u32,
+    error: &'a str,
+}
+
+fn error_response(status: u16, message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    let body = ErrorResponse { schema_version: output::SCHEMA_VERSION, error: message };
+    json_response(status, serde_json::to_string(&body).unwrap_or_default())
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn handle_lookup(state: &ServeState, ip: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    let Ok(ip) = ip.parse::<Ipv4Addr>() else {
+        return error_response(400, "IPv4 アドレスとして解釈できません");
+    };
+    match state.lookup(ip) {
+        Ok(result) => json_response(200, serde_json::to_string(&result).unwrap_or_default()),
+        Err(e) => error_response(500, &e.to_string()),
+    }
+}
+
+/// 署名やチェックサムに書くファイル名 (generate の既定の出力名と同じ)
+fn file_name(format: &str) -> String {
+    format!("foreign_ip_cidrs.{}", output::find(format).map_or(format, |writer| writer.extension()))
+}
+
+fn format_name(query: &str) -> &str {
+    match query_param(query, "format").unwrap_or("plain") {
+        "plain" => "text",
+        name => name,
+    }
+}
+
+fn find_artifact<'a>(state: &'a ServeState, query: &str) -> Result<&'a Artifact, Response<std::io::Cursor<Vec<u8>>>> {
+    state.artifacts.get(format_name(query)).ok_or_else(|| {
+        let mut names: Vec<&str> = state.artifacts.keys().map(String::as_str).collect();
+        names.sort();
+        error_response(400, &format!("format は plain, {} のいずれかを指定してください", names.join(", ")))
+    })
+}
+
+fn handle_list(state: &ServeState, request: &Request, query: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    let artifact = match find_artifact(state, query) {
+        Ok(artifact) => artifact,
+        Err(response) => return response,
+    };
+
+    let gzip = request_header(request, "Accept-Encoding").is_some_and(|v| v.split(',').any(|e| e.trim().starts_with("gzip")));
+    let etag = if gzip { artifact.gzip_etag() } else { artifact.etag.clone() };
+    let last_modified = http_date(state.build_epoch);
+
+    // If-None-Match があれば If-Modified-Since より優先する (RFC 9110)
+    let not_modified = match request_header(request, "If-None-Match") {
+        Some(tags) => tags.split(',').any(|t| {
+            let t = t.trim().trim_start_matches("W/");
+            t == "*" || t == artifact.etag || t == artifact.gzip_etag()
+        }),
+        None => request_header(request, "If-Modified-Since")
+            .and_then(parse_http_date)
+            .is_some_and(|since| state.build_epoch <= since),
+    };
+
+    let response = if not_modified {
+        Response::from_data(Vec::new()).with_status_code(304)
+    } else if gzip {
+        Response::from_data(artifact.gzip.clone())
+            .with_header(header("Content-Type", artifact.content_type))
+            .with_header(header("Content-Encoding", "gzip"))
+    } else {
+        Response::from_data(artifact.body.clone()).with_header(header("Content-Type", artifact.content_type))
+    };
+    response
+        .with_header(header("ETag", &etag))
+        .with_header(header("Last-Modified", &last_modified))
+        .with_header(header("Cache-Control", "no-cache"))
+        .with_header(header("Vary", "Accept-Encoding"))
+}
+
+fn handle(state: &ServeState, request: Request) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    let response = if *request.method() != Method::Get {
+        error_response(405, "GET のみ対応しています")
+    } else if let Some(ip) = path.strip_prefix("/lookup/") {
+        handle_lookup(state, ip)
+    } else if path == "/list" {
+        handle_list(state, &request, query)
+    } else if path == "/list.sha256" {
+        match find_artifact(state, query) {
+            Ok(artifact) => Response::from_string(checksum::line(&artifact.body, &file_name(format_name(query))))
+                .with_header(header("Content-Type", "text/plain; charset=utf-8")),
+            Err(response) => response,
+        }
+    } else if path == "/list.minisig" {
+        match find_artifact(state, query).map(|artifact| artifact.signature.as_deref()) {
+            Ok(Some(signature)) => Response::from_string(signature).with_header(header("Content-Type", "text/plain; charset=utf-8")),
+            Ok(None) => error_response(404, "署名していません (--sign-key を指定してください)"),
+            Err(response) => response,
+        }
+    } else if path == "/healthz" {
+        // 応答できる限り 200 を返す。古さで失敗させると再起動を繰り返すだけなので、振り分けは /readyz に任せる
+        json_response(200, serde_json::to_string(&state.health()).unwrap_or_default())
+    } else if path == "/readyz" {
+        let health = state.health();
+        json_response(if health.status == "ready" { 200 } else { 503 }, serde_json::to_string(&health).unwrap_or_default())
+    } else if path == "/metrics" {
+        Response::from_string(state.metrics()).with_header(header("Content-Type", "text/plain; version=0.0.4; charset=utf-8"))
+    } else {
+        error_response(404, "見つかりません")
+    };
+
+    if let Err(e) = request.respond(response) {
+        eprintln!("レスポンス送信エラー: {}", e);
+    }
+}
+
+/// REST API サーバーを起動し、終了するまでリクエストを処理する
+pub fn serve(listen: &str, state: Arc<ServeState>, workers: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let server = Arc::new(Server::http(listen).map_err(|e| format!("{} で待ち受けできません: {}", listen, e))?);
+    println!("HTTP サーバー起動: http://{}", listen);
+    systemd::ready(&format!("http://{} で待ち受けています", listen));
+    // 応答できなくなったら watchdog への通知を止め、systemd に再起動させる
+    let health_url = format!("http://{}/healthz", listen);
+    systemd::spawn_watchdog(move || ureq::get(&health_url).timeout(std::time::Duration::from_secs(5)).call().is_ok());
+
+    let workers: Vec<_> = (0..workers)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(&state, request);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+#[test]
+fn test_http_date_roundtrip() {
+    assert_eq!(http_date(784111777), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784111777));
+    assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+}
+
+#[test]
+fn test_readiness() {
+    assert_eq!(readiness(10, 86400 * 40, None), "ready");
+    assert_eq!(readiness(10, 86400 * 40, Some(86400 * 30)), "stale");
+    assert_eq!(readiness(10, 86400, Some(86400 * 30)), "ready");
+    assert_eq!(readiness(0, 0, None), "empty");
+}
+
+#[test]
+fn test_artifact_etag() {
+    // ETag は本文の SHA256 なので、再起動やツールチェーンの更新で変わらない
+    let artifact = Artifact::new(b"1.0.0.0/24\n".to_vec(), "text/plain").unwrap();
+    assert_eq!(artifact.etag, format!("\"{}\"", sha256_hex(b"1.0.0.0/24\n")));
+    assert_eq!(artifact.gzip_etag(), format!("\"{}-gzip\"", sha256_hex(b"1.0.0.0/24\n")));
+}
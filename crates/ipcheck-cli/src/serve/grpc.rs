@@ -0,0 +1,137 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use tonic::codegen::tokio_stream::StreamExt;
+use tonic::codegen::{Arc, Body, BoxFuture, BoxStream, Context, Poll, Service, StdError, empty_body, http};
+use tonic::{Request, Response, Status, Streaming};
+
+use super::ServeState;
+use crate::lookup::LookupResult;
+
+const SERVICE_NAME: &str = "ipcheck.v1.IpCheck";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LookupRequest {
+    #[prost(string, tag = "1")]
+    pub ip: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LookupResponse {
+    #[prost(string, tag = "1")]
+    pub ip: String,
+    #[prost(string, tag = "2")]
+    pub network: String,
+    #[prost(string, tag = "3")]
+    pub country: String,
+    #[prost(bool, tag = "4")]
+    pub foreign: bool,
+}
+
+impl From<LookupResult> for LookupResponse {
+    fn from(result: LookupResult) -> Self {
+        LookupResponse {
+            ip: result.ip,
+            network: result.network.unwrap_or_default(),
+            country: result.country.unwrap_or_default(),
+            foreign: result.foreign.unwrap_or(false),
+        }
+    }
+}
+
+// tonic::Status は大きいが、そのまま返すのが tonic の流儀
+#[allow(clippy::result_large_err)]
+fn lookup(state: &ServeState, request: &LookupRequest) -> Result<LookupResponse, Status> {
+    let ip: Ipv4Addr = request
+        .ip
+        .parse()
+        .map_err(|_| Status::invalid_argument(format!("IPv4 アドレスとして解釈できません: {}", request.ip)))?;
+    state.lookup(ip).map(LookupResponse::from).map_err(|e| Status::internal(e.to_string()))
+}
+
+struct LookupSvc(Arc<ServeState>);
+
+impl tonic::server::UnaryService<LookupRequest> for LookupSvc {
+    type Response = LookupResponse;
+    type Future = BoxFuture<Response<LookupResponse>, Status>;
+
+    fn call(&mut self, request: Request<LookupRequest>) -> Self::Future {
+        let state = Arc::clone(&self.0);
+        Box::pin(async move { lookup(&state, request.get_ref()).map(Response::new) })
+    }
+}
+
+struct BatchLookupSvc(Arc<ServeState>);
+
+impl tonic::server::StreamingService<LookupRequest> for BatchLookupSvc {
+    type Response = LookupResponse;
+    type ResponseStream = BoxStream<LookupResponse>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    #[allow(clippy::result_large_err)]
+    fn call(&mut self, request: Request<Streaming<LookupRequest>>) -> Self::Future {
+        let state = Arc::clone(&self.0);
+        let stream = request.into_inner().map(move |item| lookup(&state, &item?));
+        Box::pin(async move { Ok(Response::new(Box::pin(stream) as Self::ResponseStream)) })
+    }
+}
+
+/// tonic-build が生成するサーバー実装に相当するもの (protoc を必要としないよう手書きしている)
+#[derive(Clone)]
+pub struct IpCheckServer(Arc<ServeState>);
+
+impl tonic::server::NamedService for IpCheckServer {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl<B> Service<http::Request<B>> for IpCheckServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let state = Arc::clone(&self.0);
+        match request.uri().path() {
+            "/ipcheck.v1.IpCheck/Lookup" => Box::pin(async move {
+                let mut grpc = tonic::server::Grpc::new(tonic::codec::ProstCodec::default());
+                Ok(grpc.unary(LookupSvc(state), request).await)
+            }),
+            "/ipcheck.v1.IpCheck/BatchLookup" => Box::pin(async move {
+                let mut grpc = tonic::server::Grpc::new(tonic::codec::ProstCodec::default());
+                Ok(grpc.streaming(BatchLookupSvc(state), request).await)
+            }),
+            _ => Box::pin(async move {
+                let mut response = http::Response::new(empty_body());
+                let headers = response.headers_mut();
+                headers.insert(Status::GRPC_STATUS, (tonic::Code::Unimplemented as i32).into());
+                headers.insert(http::header::CONTENT_TYPE, tonic::metadata::GRPC_CONTENT_TYPE);
+                Ok(response)
+            }),
+        }
+    }
+}
+
+/// 別スレッドで gRPC サーバーを起動する。threads を省略するとランタイムの既定 (CPU コア数) のワーカーを使う
+pub fn spawn(listen: &str, state: Arc<ServeState>, threads: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let addr: SocketAddr = listen.parse()?;
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if let Some(threads) = threads {
+        builder.worker_threads(threads);
+    }
+    let runtime = builder.enable_all().build()?;
+    println!("gRPC サーバー起動: {}", addr);
+    std::thread::spawn(move || {
+        let server = tonic::transport::Server::builder().add_service(IpCheckServer(state)).serve(addr);
+        if let Err(e) = runtime.block_on(server) {
+            eprintln!("gRPC サーバーエラー: {}", e);
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,181 @@
+//! minisign 互換の ed25519 署名 (BLAKE2b の事前ハッシュを使わない従来形式の "Ed" 署名)
+
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+
+const ALGORITHM: &[u8; 2] = b"Ed";
+/// BLAKE2b-512 で事前ハッシュした署名 (minisign 0.11 以降の既定)
+const ALGORITHM_HASHED: &[u8; 2] = b"ED";
+/// minisign -G -W で作ったパスワードなしの秘密鍵の長さ
+const MINISIGN_SECRET_LEN: usize = 158;
+
+fn decode(line: &str) -> Result<Vec<u8>, String> {
+    STANDARD.decode(line.trim()).map_err(|e| format!("base64 として解釈できません: {}", e))
+}
+
+/// comment 行を飛ばして最初の行を返す
+fn key_line(text: &str) -> &str {
+    text.lines().find(|l| !l.trim().is_empty() && !l.starts_with("untrusted comment:")).unwrap_or("")
+}
+
+pub fn key_id_hex(key_id: &[u8; 8]) -> String {
+    format!("{:016X}", u64::from_le_bytes(*key_id))
+}
+
+pub struct SecretKey {
+    key_id: [u8; 8],
+    seed: [u8; 32],
+    pair: Ed25519KeyPair,
+}
+
+impl SecretKey {
+    pub fn generate() -> Result<Self, String> {
+        let rng = SystemRandom::new();
+        let mut key_id = [0u8; 8];
+        let mut seed = [0u8; 32];
+        rng.fill(&mut key_id).and_then(|_| rng.fill(&mut seed)).map_err(|_| "乱数を取得できません")?;
+        Self::from_seed(key_id, &seed)
+    }
+
+    fn from_seed(key_id: [u8; 8], seed: &[u8]) -> Result<Self, String> {
+        let pair = Ed25519KeyPair::from_seed_unchecked(seed).map_err(|e| format!("秘密鍵が不正です: {}", e))?;
+        Ok(SecretKey { key_id, seed: seed.try_into().unwrap(), pair })
+    }
+
+    /// ipcheck keygen の秘密鍵、または minisign -G -W で作ったパスワードなしの秘密鍵を読む
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let bytes = decode(key_line(text))?;
+        match bytes.len() {
+            42 if &bytes[..2] == ALGORITHM => Self::from_seed(bytes[2..10].try_into().unwrap(), &bytes[10..]),
+            MINISIGN_SECRET_LEN if &bytes[..2] == ALGORITHM => {
+                if bytes[2..4] != [0, 0] {
+                    return Err("パスワードで暗号化された minisign の秘密鍵は使えません (minisign -G -W で作り直してください)".into());
+                }
+                // アルゴリズム 2 + KDF 2 + チェックサム 2 + salt 32 + opslimit 8 + memlimit 8 の後に鍵 ID と秘密鍵が続く
+                Self::from_seed(bytes[54..62].try_into().unwrap(), &bytes[62..94])
+            }
+            _ => Err("ed25519 の秘密鍵ではありません".into()),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// minisign と違い暗号化しないので、ファイルの権限で保護する
+    pub fn to_text(&self) -> String {
+        let mut bytes = ALGORITHM.to_vec();
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(&self.seed);
+        format!("untrusted comment: ipcheck secret key {}\n{}\n", key_id_hex(&self.key_id), STANDARD.encode(bytes))
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey { key_id: self.key_id, key: self.pair.public_key().as_ref().try_into().unwrap() }
+    }
+
+    /// data の .minisig の中身。trusted comment も署名に含まれる
+    pub fn sign(&self, data: &[u8], trusted_comment: &str) -> String {
+        let signature = self.pair.sign(data);
+        let mut global = signature.as_ref().to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+        let global = self.pair.sign(&global);
+
+        let mut line = ALGORITHM.to_vec();
+        line.extend_from_slice(&self.key_id);
+        line.extend_from_slice(signature.as_ref());
+        format!(
+            "untrusted comment: signature from ipcheck secret key {}\n{}\ntrusted comment: {}\n{}\n",
+            key_id_hex(&self.key_id),
+            STANDARD.encode(line),
+            trusted_comment,
+            STANDARD.encode(global.as_ref())
+        )
+    }
+}
+
+pub struct PublicKey {
+    key_id: [u8; 8],
+    key: [u8; 32],
+}
+
+impl PublicKey {
+    /// minisign.pub の中身、または minisign -P に渡す base64 の 1 行
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let bytes = decode(key_line(text))?;
+        if bytes.len() != 42 || &bytes[..2] != ALGORITHM {
+            return Err("ed25519 の公開鍵ではありません".into());
+        }
+        Ok(PublicKey { key_id: bytes[2..10].try_into().unwrap(), key: bytes[10..].try_into().unwrap() })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut bytes = ALGORITHM.to_vec();
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(&self.key);
+        format!("untrusted comment: minisign public key {}\n{}\n", key_id_hex(&self.key_id), STANDARD.encode(bytes))
+    }
+
+    /// 署名を検証し、trusted comment を返す
+    pub fn verify(&self, data: &[u8], minisig: &str) -> Result<String, String> {
+        let mut lines = minisig.lines().filter(|l| !l.starts_with("untrusted comment:"));
+        let signature = decode(lines.next().unwrap_or(""))?;
+        let trusted_comment = lines
+            .next()
+            .and_then(|l| l.strip_prefix("trusted comment: "))
+            .ok_or("trusted comment がありません")?;
+        let global = decode(lines.next().unwrap_or(""))?;
+
+        if signature.len() != 74 {
+            return Err("署名の長さが不正です".into());
+        }
+        if &signature[..2] == ALGORITHM_HASHED {
+            return Err("BLAKE2b で事前ハッシュした署名には対応していません (minisign -V で検証してください)".into());
+        }
+        if &signature[..2] != ALGORITHM {
+            return Err("ed25519 の署名ではありません".into());
+        }
+        if signature[2..10] != self.key_id {
+            return Err(format!(
+                "署名の鍵 ID {} が公開鍵の鍵 ID {} と一致しません",
+                key_id_hex(signature[2..10].try_into().unwrap()),
+                key_id_hex(&self.key_id)
+            ));
+        }
+        let key = UnparsedPublicKey::new(&ED25519, &self.key);
+        key.verify(data, &signature[10..]).map_err(|_| "署名が一致しません (ファイルが改変されています)")?;
+        let mut signed = signature[10..].to_vec();
+        signed.extend_from_slice(trusted_comment.as_bytes());
+        key.verify(&signed, &global).map_err(|_| "trusted comment の署名が一致しません")?;
+        Ok(trusted_comment.to_string())
+    }
+}
+
+/// minisign と同じ形式の trusted comment
+pub fn trusted_comment(epoch: u64, file_name: &str) -> String {
+    format!("timestamp:{}\tfile:{}", epoch, file_name)
+}
+
+#[test]
+fn test_sign_and_verify() {
+    let key = SecretKey::parse(&SecretKey::generate().unwrap().to_text()).unwrap();
+    let public = PublicKey::parse(&key.public_key().to_text()).unwrap();
+    let minisig = key.sign(b"1.0.0.0/24\n", &trusted_comment(1700000000, "out.txt"));
+    assert_eq!(public.verify(b"1.0.0.0/24\n", &minisig).unwrap(), "timestamp:1700000000\tfile:out.txt");
+    assert!(public.verify(b"1.0.0.0/23\n", &minisig).is_err());
+    assert!(public.verify(b"1.0.0.0/24\n", &minisig.replace("out.txt", "other.txt")).is_err());
+
+    // minisign -G -W の秘密鍵からは同じ鍵 ID と鍵を取り出す
+    let mut minisign = b"Ed\0\0B2".to_vec();
+    minisign.extend_from_slice(&[0; 48]);
+    minisign.extend_from_slice(&key.key_id);
+    minisign.extend_from_slice(&key.seed);
+    minisign.extend_from_slice(&[0; 64]);
+    let minisign = SecretKey::parse(&format!("untrusted comment: minisign secret key\n{}\n", STANDARD.encode(minisign))).unwrap();
+    assert_eq!(minisign.public_key().to_text(), key.public_key().to_text());
+}
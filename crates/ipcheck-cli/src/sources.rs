@@ -0,0 +1,751 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+
+use crate::NetworkBlock;
+use crate::rules::Rules;
+
+/// 入力元から読み込んだ IPv4 ネットワークと国コード
+pub type Networks = Vec<(NetworkBlock, Option<String>)>;
+
+/// --source-format の既定値。登録されている入力元の detect で判定する
+pub const AUTO: &str = "auto";
+
+/// --db にこれを指定すると 5 つの RIR から最新の delegated-extended を取得する
+pub const RIR_LATEST: &str = "rir";
+
+pub const RIR_URLS: [&str; 5] = [
+    "https://ftp.apnic.net/stats/apnic/delegated-apnic-extended-latest",
+    "https://ftp.ripe.net/pub/stats/ripencc/delegated-ripencc-extended-latest",
+    "https://ftp.arin.net/pub/stats/arin/delegated-arin-extended-latest",
+    "https://ftp.lacnic.net/pub/stats/lacnic/delegated-lacnic-extended-latest",
+    "https://ftp.afrinic.net/pub/stats/afrinic/delegated-afrinic-extended-latest",
+];
+
+fn is_rir_file(name: &str) -> bool {
+    name.starts_with("delegated-")
+}
+
+/// 入力元が列挙するネットワークと位置コード
+pub type NetworkIter = Box<dyn Iterator<Item = (NetworkBlock, Option<String>)>>;
+
+/// URL の入力をキャッシュへダウンロードする
+pub struct Fetcher<'a> {
+    pub cache_dir: PathBuf,
+    /// --db の URL に対してだけ検証する
+    pub sha256: Option<&'a str>,
+    /// 真ならダウンロードせず、既存のキャッシュだけを使う
+    pub dry_run: bool,
+}
+
+impl Fetcher<'_> {
+    /// URL (s3://, gs:// を含む) ならダウンロードしたキャッシュのパスを、それ以外はそのままのパスを返す
+    pub fn fetch(&self, location: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        if crate::is_remote(location) && self.dry_run {
+            crate::download::cached(location, &self.cache_dir, self.sha256)
+        } else if crate::is_remote(location) {
+            crate::download::fetch_cached(location, &self.cache_dir, self.sha256)
+        } else {
+            Ok(PathBuf::from(location))
+        }
+    }
+}
+
+/// 入力元の形式。新しいフィードはこれを実装して registry に加えれば --db や --source で使える
+pub trait Source {
+    /// --source-format と "形式:パス" で指定する名前
+    fn name(&self) -> &'static str;
+
+    /// 自動判定でこの形式とみなすか。location は --db の値か、URL ならダウンロード済みのパス
+    fn detect(&self, _location: &str) -> bool {
+        false
+    }
+
+    /// 入力をローカルに用意し、読み込むファイルを返す
+    fn fetch(&self, location: &str, fetcher: &Fetcher) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        Ok(vec![fetcher.fetch(location)?])
+    }
+
+    /// 用意したファイルのネットワークと位置コード、作成日時 (UNIX 時刻)。位置コードに地域を付けるかは rules に従う
+    fn iterate(&self, paths: &[PathBuf], rules: &Rules) -> Result<(NetworkIter, u64), Box<dyn std::error::Error>>;
+}
+
+type ReadFn = fn(&str) -> Result<(Networks, u64), Box<dyn std::error::Error>>;
+
+/// 1 ファイルを読む関数を Source にする
+fn single_file(paths: &[PathBuf], read: ReadFn) -> Result<(NetworkIter, u64), Box<dyn std::error::Error>> {
+    let path = paths.first().ok_or("入力ファイルがありません")?;
+    let (networks, build_epoch) = read(&path.to_string_lossy())?;
+    Ok((Box::new(networks.into_iter()), build_epoch))
+}
+
+/// CSV の最初の列。IP2Location と DB-IP はヘッダーがなく、最初の列がそれぞれ 10 進数と表記どおりの IP アドレス
+fn csv_first_field(location: &str) -> Option<String> {
+    let lower = location.to_ascii_lowercase();
+    if !lower.ends_with(".csv") && !lower.ends_with(".csv.gz") {
+        return None;
+    }
+    split_csv(&first_line(location)?).into_iter().next()
+}
+
+/// MaxMind DB 形式 (GeoLite2 / GeoIP2 / DB-IP)。どの形式にも当たらなければこれとみなす
+struct MmdbSource;
+
+impl Source for MmdbSource {
+    fn name(&self) -> &'static str {
+        "mmdb"
+    }
+
+    fn detect(&self, _location: &str) -> bool {
+        true
+    }
+
+    fn iterate(&self, paths: &[PathBuf], rules: &Rules) -> Result<(NetworkIter, u64), Box<dyn std::error::Error>> {
+        let path = paths.first().ok_or("入力ファイルがありません")?;
+        let reader = crate::open_mmdb(&path.to_string_lossy())?;
+        let networks = ipcheck_core::networks(&reader, rules)?;
+        Ok((Box::new(networks.into_iter()), reader.metadata.build_epoch))
+    }
+}
+
+/// GeoLite2-Country / City の CSV 版 (Blocks-IPv4 と Locations のあるディレクトリ、または Blocks-IPv4 ファイル)
+struct Geolite2CsvSource;
+
+impl Source for Geolite2CsvSource {
+    fn name(&self) -> &'static str {
+        "geolite2-csv"
+    }
+
+    fn detect(&self, location: &str) -> bool {
+        Path::new(location).is_dir() || csv_first_field(location).is_some()
+    }
+
+    fn iterate(&self, paths: &[PathBuf], rules: &Rules) -> Result<(NetworkIter, u64), Box<dyn std::error::Error>> {
+        let path = paths.first().ok_or("入力ファイルがありません")?;
+        let (networks, build_epoch) = read_geolite2_csv(&path.to_string_lossy(), rules)?;
+        Ok((Box::new(networks.into_iter()), build_epoch))
+    }
+}
+
+/// IP2Location LITE DB1 の CSV (IPv4 版または IPv6 版)
+struct Ip2locationSource;
+
+impl Source for Ip2locationSource {
+    fn name(&self) -> &'static str {
+        "ip2location"
+    }
+
+    fn detect(&self, location: &str) -> bool {
+        csv_first_field(location).is_some_and(|f| !f.is_empty() && f.bytes().all(|b| b.is_ascii_digit()))
+    }
+
+    fn iterate(&self, paths: &[PathBuf], _rules: &Rules) -> Result<(NetworkIter, u64), Box<dyn std::error::Error>> {
+        single_file(paths, read_ip2location_csv)
+    }
+}
+
+/// DB-IP の国別 CSV (dbip-country-lite など。.csv.gz のままでもよい)
+struct DbipCsvSource;
+
+impl Source for DbipCsvSource {
+    fn name(&self) -> &'static str {
+        "dbip-csv"
+    }
+
+    fn detect(&self, location: &str) -> bool {
+        csv_first_field(location).is_some_and(|f| f.parse::<IpAddr>().is_ok())
+    }
+
+    fn iterate(&self, paths: &[PathBuf], _rules: &Rules) -> Result<(NetworkIter, u64), Box<dyn std::error::Error>> {
+        single_file(paths, read_dbip_csv)
+    }
+}
+
+/// RIR の delegated-extended 統計 (ファイル、それらを置いたディレクトリ、または最新版を取得する "rir")
+struct RirSource;
+
+impl Source for RirSource {
+    fn name(&self) -> &'static str {
+        "rir"
+    }
+
+    fn detect(&self, location: &str) -> bool {
+        let path = Path::new(location);
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        location == RIR_LATEST || is_rir_file(file_name) || (path.is_dir() && find_file(path, is_rir_file).is_some())
+    }
+
+    fn fetch(&self, location: &str, fetcher: &Fetcher) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        if location == RIR_LATEST {
+            return RIR_URLS.iter().map(|url| fetcher.fetch(url)).collect();
+        }
+        Ok(rir_files(&fetcher.fetch(location)?.to_string_lossy())?)
+    }
+
+    fn iterate(&self, paths: &[PathBuf], _rules: &Rules) -> Result<(NetworkIter, u64), Box<dyn std::error::Error>> {
+        let (networks, build_epoch) = read_rir(paths)?;
+        Ok((Box::new(networks.into_iter()), build_epoch))
+    }
+}
+
+/// 登録されている入力元。自動判定ではこの順に detect を試すので、判定のゆるいものほど後ろに置く
+pub fn registry() -> Vec<Box<dyn Source>> {
+    vec![Box::new(RirSource), Box::new(Ip2locationSource), Box::new(DbipCsvSource), Box::new(Geolite2CsvSource), Box::new(MmdbSource)]
+}
+
+/// --source-format に指定できる名前
+pub fn names() -> Vec<&'static str> {
+    std::iter::once(AUTO).chain(registry().iter().map(|s| s.name())).collect()
+}
+
+fn find(name: &str) -> Option<Box<dyn Source>> {
+    registry().into_iter().find(|s| s.name().eq_ignore_ascii_case(name))
+}
+
+fn detect(location: &str) -> Box<dyn Source> {
+    registry().into_iter().find(|s| s.detect(location)).unwrap_or_else(|| Box::new(MmdbSource))
+}
+
+/// location を mmdb として読むか (format は名前または "auto")
+pub fn is_mmdb(format: &str, location: &str) -> bool {
+    match format {
+        AUTO => detect(location).name() == "mmdb",
+        format => format == "mmdb",
+    }
+}
+
+/// format (名前または "auto") の入力元から location を読み込む
+pub fn load(format: &str, location: &str, fetcher: &Fetcher, rules: &Rules) -> Result<(Networks, u64), Box<dyn std::error::Error>> {
+    let (source, paths) = if format != AUTO {
+        let source = find(format).ok_or_else(|| format!("不明な入力形式です: {}", format))?;
+        let paths = source.fetch(location, fetcher)?;
+        (source, paths)
+    } else if crate::is_remote(location) {
+        // 中身を見て判定する形式もあるので、先にダウンロードしてから判定する
+        let path = fetcher.fetch(location)?;
+        (detect(&path.to_string_lossy()), vec![path])
+    } else {
+        let source = detect(location);
+        let paths = source.fetch(location, fetcher)?;
+        (source, paths)
+    };
+    let (networks, build_epoch) = source.iterate(&paths, rules)?;
+    Ok((networks.collect(), build_epoch))
+}
+
+/// --source の値。"形式:パス" で形式を明示でき、省略すると --source-format と同じく自動判定する
+#[derive(Clone)]
+pub struct SourceSpec {
+    pub format: String,
+    pub path: String,
+}
+
+impl std::str::FromStr for SourceSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // https:// や s3:// は形式名ではないので、そのままパスとして扱う
+        match s.split_once(':').filter(|(kind, _)| names().iter().any(|n| n.eq_ignore_ascii_case(kind))) {
+            Some((_, "")) => Err(format!("パスがありません: {}", s)),
+            Some((format, path)) => Ok(SourceSpec { format: format.to_ascii_lowercase(), path: path.to_string() }),
+            None => Ok(SourceSpec { format: AUTO.to_string(), path: s.to_string() }),
+        }
+    }
+}
+
+/// 複数の入力元で国の判定が食い違ったときの扱い
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConflictPolicy {
+    /// どれか 1 つでも海外とした範囲は海外とする (国内として許可するのは全員一致のときだけ)
+    AnyForeign,
+    /// すべての入力元が海外とした範囲だけを海外とする
+    AllAgree,
+    /// --source の先頭にあるものを優先する
+    Priority,
+}
+
+impl ConflictPolicy {
+    /// その範囲を含む入力元の国コード (--source の順) から 1 つを選ぶ
+    fn resolve<'a>(self, rules: &Rules, covering: &[&'a Option<String>]) -> &'a Option<String> {
+        let pick = match self {
+            ConflictPolicy::AnyForeign => covering.iter().find(|c| rules.is_foreign(c.as_deref())),
+            ConflictPolicy::AllAgree => covering.iter().find(|c| !rules.is_foreign(c.as_deref())),
+            ConflictPolicy::Priority => None,
+        };
+        pick.unwrap_or(&covering[0])
+    }
+}
+
+/// 各入力元を [開始, 終了] の範囲に直し、開始順に並べる
+fn to_ranges(networks: &Networks) -> Vec<(u64, u64, &Option<String>)> {
+    let mut ranges: Vec<_> = networks.iter().map(|(block, iso_code)| (block.network as u64, block.last() as u64, iso_code)).collect();
+    ranges.sort_by_key(|&(start, end, _)| (start, end));
+    ranges
+}
+
+/// [開始, 終了] の区間と、各入力元 (--source の順) がその区間をどの国としたか。含まない入力元は None
+pub type Segment<'a> = (u64, u64, Vec<Option<&'a Option<String>>>);
+
+/// すべての入力元の境界で IPv4 空間を区切り、区間ごとに分ける。
+/// どの入力元にも含まれない区間は除く
+pub fn split_by_sources(sources: &[Networks]) -> Vec<Segment<'_>> {
+    let ranges: Vec<_> = sources.iter().map(to_ranges).collect();
+    let mut bounds: Vec<u64> = ranges.iter().flatten().flat_map(|&(start, end, _)| [start, end + 1]).collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut cursors = vec![0; ranges.len()];
+    let mut segments = Vec::new();
+    for pair in bounds.windows(2) {
+        let (start, end) = (pair[0], pair[1] - 1);
+        let countries: Vec<Option<&Option<String>>> = ranges
+            .iter()
+            .zip(cursors.iter_mut())
+            .map(|(ranges, cursor)| {
+                while *cursor < ranges.len() && ranges[*cursor].1 < start {
+                    *cursor += 1;
+                }
+                ranges.get(*cursor).filter(|r| r.0 <= start).map(|r| r.2)
+            })
+            .collect();
+        if countries.iter().any(Option::is_some) {
+            segments.push((start, end, countries));
+        }
+    }
+    segments
+}
+
+/// 複数の入力元を policy に従って 1 つにまとめる。隣接する同じ国の区間は CIDR にまとめ直す
+pub fn merge(sources: &[Networks], policy: ConflictPolicy, rules: &Rules) -> Networks {
+    let mut merged: Vec<(u64, u64, Option<String>)> = Vec::new();
+    for (start, end, countries) in split_by_sources(sources) {
+        let covering: Vec<&Option<String>> = countries.into_iter().flatten().collect();
+        let iso_code = policy.resolve(rules, &covering);
+        match merged.last_mut() {
+            Some(last) if last.1 + 1 == start && last.2 == *iso_code => last.1 = end,
+            _ => merged.push((start, end, iso_code.clone())),
+        }
+    }
+    merged
+        .into_iter()
+        .flat_map(|(start, end, iso_code)| crate::range_to_blocks(start as u32, end as u32).into_iter().map(move |block| (block, iso_code.clone())))
+        .collect()
+}
+
+/// 入力元の間で海外・国内の判定が食い違う範囲の CSV レポート。
+/// 各入力元の国コード (不明は "--"、その範囲を含まなければ空) と、policy でまとめた結果を並べる。
+/// (レポート, 食い違う範囲の数, アドレス数) を返す
+pub fn discrepancy_report(labels: &[&str], sources: &[Networks], policy: ConflictPolicy, rules: &Rules) -> (String, usize, u64) {
+    let mut ranges: Vec<Segment> = Vec::new();
+    for (start, end, countries) in split_by_sources(sources) {
+        let mut covering = countries.iter().flatten().map(|c| rules.is_foreign(c.as_deref()));
+        let first = covering.next();
+        if covering.all(|foreign| Some(foreign) == first) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if last.1 + 1 == start && last.2 == countries => last.1 = end,
+            _ => ranges.push((start, end, countries)),
+        }
+    }
+
+    let mut out = format!("network,addresses,{},merged,foreign\n", labels.join(","));
+    let mut addresses = 0;
+    for (start, end, countries) in &ranges {
+        let codes: Vec<&str> = countries.iter().map(|c| c.map_or("", |c| c.as_deref().unwrap_or("--"))).collect();
+        let covering: Vec<&Option<String>> = countries.iter().flatten().copied().collect();
+        let merged = policy.resolve(rules, &covering);
+        for block in crate::range_to_blocks(*start as u32, *end as u32) {
+            let size = block.last() as u64 - block.network as u64 + 1;
+            addresses += size;
+            out.push_str(&format!(
+                "{},{},{},{},{}\n",
+                block.to_string(),
+                size,
+                codes.join(","),
+                merged.as_deref().unwrap_or("--"),
+                rules.is_foreign(merged.as_deref())
+            ));
+        }
+    }
+    (out, ranges.len(), addresses)
+}
+
+/// RFC 8805 geofeed ("prefix,国コード,地域,都市,郵便番号") の IPv4 プレフィックス。
+/// 国コードが空の行と解釈できない行は読み飛ばし、その数も返す
+pub fn read_geofeed(path: &str) -> Result<(Networks, usize), Box<dyn std::error::Error>> {
+    let mut networks = Vec::new();
+    let mut skipped = 0;
+    for line in open_lines(Path::new(path))?.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = split_csv(line);
+        let (Some(prefix), Some(country)) = (fields.first().map(|f| f.trim()), fields.get(1).map(|f| f.trim())) else {
+            skipped += 1;
+            continue;
+        };
+        if prefix.contains(':') {
+            continue;
+        }
+        match prefix.parse::<NetworkBlock>() {
+            Ok(block) if country.len() == 2 => networks.push((block, Some(country.to_ascii_uppercase()))),
+            _ => skipped += 1,
+        }
+    }
+    Ok((networks, skipped))
+}
+
+/// overrides (先頭ほど優先) に含まれる範囲の国を base より優先する。
+/// 1 つの geofeed の中で重なるプレフィックスは長い (より具体的な) ほうを優先する
+pub fn apply_overrides(base: Networks, overrides: Vec<Networks>) -> Networks {
+    let mut layers = Vec::new();
+    for networks in overrides {
+        let mut lengths: Vec<u8> = networks.iter().map(|(block, _)| block.prefix_len).collect();
+        lengths.sort_unstable_by(|a, b| b.cmp(a));
+        lengths.dedup();
+        for len in lengths {
+            let mut layer: Networks = networks.iter().filter(|(block, _)| block.prefix_len == len).cloned().collect();
+            layer.sort_by_key(|(block, _)| block.network);
+            layer.dedup_by_key(|(block, _)| block.network);
+            layers.push(layer);
+        }
+    }
+    layers.push(base);
+    // Priority は海外・国内の判定を使わないので、規則は何でもよい
+    merge(&layers, ConflictPolicy::Priority, &Rules::default())
+}
+
+/// テキストファイルを行単位で読む。.gz なら展開しながら読む
+fn open_lines(path: &Path) -> std::io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gz")) {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+fn first_line(path: &str) -> Option<String> {
+    open_lines(Path::new(path)).ok()?.lines().next()?.ok()
+}
+
+/// 1 行分の CSV を分割する。ダブルクォートで囲まれたフィールドと "" のエスケープに対応する
+pub fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches(['\r', '\n']).chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// ヘッダー行から列名の位置を引けるようにする
+fn header_index(header: &str) -> HashMap<String, usize> {
+    split_csv(header).into_iter().enumerate().map(|(i, name)| (name.trim().to_string(), i)).collect()
+}
+
+fn column(index: &HashMap<String, usize>, name: &str, path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    index.get(name).copied().ok_or_else(|| format!("{} に {} 列がありません", path.display(), name).into())
+}
+
+fn find_file(dir: &Path, matches: impl Fn(&str) -> bool) -> Option<PathBuf> {
+    let mut candidates: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.file_name().and_then(|n| n.to_str()).is_some_and(&matches))
+        .collect();
+    candidates.sort();
+    candidates.into_iter().next()
+}
+
+/// GeoLite2-Country (または City) CSV 版の IPv4 ネットワークと国コード。build_epoch の代わりに Blocks ファイルの更新時刻を返す
+pub fn read_geolite2_csv(path: &str, rules: &Rules) -> Result<(Networks, u64), Box<dyn std::error::Error>> {
+    let path = Path::new(path);
+    let (dir, blocks_path) = if path.is_dir() {
+        let blocks = find_file(path, |name| name.ends_with("-Blocks-IPv4.csv"))
+            .ok_or_else(|| format!("{} に *-Blocks-IPv4.csv がありません", path.display()))?;
+        (path.to_path_buf(), blocks)
+    } else {
+        (path.parent().map(Path::to_path_buf).unwrap_or_default(), path.to_path_buf())
+    };
+    // 英語版があればそれを、なければ任意の言語の Locations を使う (国コードは言語に依存しない)
+    let locations_path = find_file(&dir, |name| name.ends_with("-Locations-en.csv"))
+        .or_else(|| find_file(&dir, |name| name.contains("-Locations-") && name.ends_with(".csv")))
+        .ok_or_else(|| format!("{} に *-Locations-*.csv がありません", dir.display()))?;
+
+    let mut countries = HashMap::new();
+    let mut lines = BufReader::new(File::open(&locations_path)?).lines();
+    let index = header_index(&lines.next().ok_or("Locations ファイルが空です")??);
+    let (id_col, iso_col) = (column(&index, "geoname_id", &locations_path)?, column(&index, "country_iso_code", &locations_path)?);
+    // City 版の Locations には地域コードがある。地域で判定するときだけ "JP-13" のように付ける
+    let subdivision_col = index.get("subdivision_1_iso_code").copied().filter(|_| rules.uses_subdivisions());
+    for line in lines {
+        let fields = split_csv(&line?);
+        if let (Some(id), Some(iso)) = (fields.get(id_col), fields.get(iso_col))
+            && !iso.is_empty()
+        {
+            let location = match subdivision_col.and_then(|col| fields.get(col)).filter(|s| !s.is_empty()) {
+                Some(subdivision) => format!("{}-{}", iso, subdivision),
+                None => iso.clone(),
+            };
+            countries.insert(id.clone(), location);
+        }
+    }
+
+    let mut networks = Vec::new();
+    let mut lines = BufReader::new(File::open(&blocks_path)?).lines();
+    let index = header_index(&lines.next().ok_or("Blocks ファイルが空です")??);
+    let (network_col, id_col) = (column(&index, "network", &blocks_path)?, column(&index, "geoname_id", &blocks_path)?);
+    for line in lines {
+        let fields = split_csv(&line?);
+        let Some(network) = fields.get(network_col) else { continue };
+        // mmdb の country と同じく geoname_id (registered_country ではない) で国を決める
+        let iso_code = fields.get(id_col).and_then(|id| countries.get(id)).cloned();
+        networks.push((network.parse::<NetworkBlock>()?, iso_code));
+    }
+
+    Ok((networks, modified_epoch(&blocks_path)?))
+}
+
+/// CSV には作成日時がないので、ファイルの更新時刻を build_epoch の代わりにする
+fn modified_epoch(path: &Path) -> std::io::Result<u64> {
+    Ok(std::fs::metadata(path)?
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0))
+}
+
+/// IP2Location の 10 進数アドレスを IPv4 に戻す。IPv6 版の IPv4 部分は ::ffff:0:0/96 に置かれている
+fn ip2location_v4(value: u128) -> Option<u32> {
+    const MAPPED: u128 = 0xffff_0000_0000;
+    match value {
+        0..=0xffff_ffff => Some(value as u32),
+        MAPPED..=0xffff_ffff_ffff => Some((value - MAPPED) as u32),
+        _ => None,
+    }
+}
+
+/// IP2Location LITE DB1 ("開始","終了","国コード","国名") の範囲を CIDR に分解して読み込む。
+/// 国コードが "-" の範囲 (予約済みなど) は国なしとして扱う
+pub fn read_ip2location_csv(path: &str) -> Result<(Networks, u64), Box<dyn std::error::Error>> {
+    let path = Path::new(path);
+    let mut networks = Vec::new();
+    for (i, line) in open_lines(path)?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_csv(&line);
+        let [start, end, code, ..] = fields.as_slice() else {
+            return Err(format!("{}:{} の列が足りません", path.display(), i + 1).into());
+        };
+        let parse = |s: &str| s.parse::<u128>().map_err(|_| format!("{}:{} の IP アドレスが不正です: {}", path.display(), i + 1, s));
+        let (start, end) = (parse(start)?, parse(end)?);
+        // IPv6 版の IPv4 以外の範囲は読み飛ばす
+        let (Some(start), Some(end)) = (ip2location_v4(start), ip2location_v4(end)) else { continue };
+        let iso_code = Some(code.to_string()).filter(|c| c != "-" && !c.is_empty());
+        for block in crate::range_to_blocks(start, end) {
+            networks.push((block, iso_code.clone()));
+        }
+    }
+    Ok((networks, modified_epoch(path)?))
+}
+
+/// DB-IP の国別 CSV ("開始,終了,国コード"、有償版は "開始,終了,大陸,国コード,...") の IPv4 範囲を読み込む
+pub fn read_dbip_csv(path: &str) -> Result<(Networks, u64), Box<dyn std::error::Error>> {
+    let path = Path::new(path);
+    let mut networks = Vec::new();
+    for (i, line) in open_lines(path)?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_csv(&line);
+        let parse = |s: &str| s.parse::<IpAddr>().map_err(|_| format!("{}:{} の IP アドレスが不正です: {}", path.display(), i + 1, s));
+        let (Some(start), Some(end)) = (fields.first(), fields.get(1)) else {
+            return Err(format!("{}:{} の列が足りません", path.display(), i + 1).into());
+        };
+        let (IpAddr::V4(start), IpAddr::V4(end)) = (parse(start)?, parse(end)?) else { continue };
+        // 大陸コードも 2 文字なので、3・4 列目のうち後ろにある 2 文字の大文字を国コードとする
+        let iso_code = fields
+            .iter()
+            .skip(2)
+            .take(2)
+            .rfind(|f| f.len() == 2 && f.bytes().all(|b| b.is_ascii_uppercase()))
+            .filter(|code| *code != "ZZ")
+            .cloned();
+        for block in crate::range_to_blocks(u32::from(start), u32::from(end)) {
+            networks.push((block, iso_code.clone()));
+        }
+    }
+    Ok((networks, modified_epoch(path)?))
+}
+
+/// ディレクトリなら中の delegated-* をすべて、ファイルならそれだけを読む
+pub fn rir_files(path: &str) -> std::io::Result<Vec<PathBuf>> {
+    let path = Path::new(path);
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.file_name().and_then(|n| n.to_str()).is_some_and(is_rir_file))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// "YYYYMMDD" を UNIX 時刻にする
+fn parse_yyyymmdd(s: &str) -> Option<u64> {
+    if s.len() != 8 || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (year, month, day) = (s[..4].parse().ok()?, s[4..6].parse().ok()?, s[6..].parse().ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    crate::output::epoch_from_civil(year, month, day)
+}
+
+/// RIR の delegated-extended 統計から、割り振り・割り当て済みの IPv4 範囲を国コード付きで読み込む。
+/// build_epoch にはヘッダーの日付 (複数ファイルなら最も古いもの) を使う
+pub fn read_rir(paths: &[PathBuf]) -> Result<(Networks, u64), Box<dyn std::error::Error>> {
+    let mut networks = Vec::new();
+    let mut build_epoch: Option<u64> = None;
+    for path in paths {
+        let mut file_epoch = None;
+        for (i, line) in open_lines(path)?.lines().enumerate() {
+            let line = line?;
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            // registry|cc|type|start|value|date|status[|opaque-id[|extensions]]
+            let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+            // バージョン行: version|registry|serial|records|startdate|enddate|UTCoffset
+            if fields[0].starts_with(|c: char| c.is_ascii_digit()) {
+                file_epoch = fields.get(2).and_then(|s| parse_yyyymmdd(s)).or_else(|| fields.get(5).and_then(|s| parse_yyyymmdd(s)));
+                continue;
+            }
+            let [_, cc, "ipv4", start, count, _, status, ..] = fields.as_slice() else { continue };
+            // 未割り振り (available) と予約 (reserved) は国を持たないので含めない
+            if !matches!(*status, "allocated" | "assigned") || cc.is_empty() || *cc == "ZZ" {
+                continue;
+            }
+            let invalid = || format!("{}:{} の行が不正です: {}", path.display(), i + 1, line);
+            let start = u32::from(start.parse::<std::net::Ipv4Addr>().map_err(|_| invalid())?);
+            let count: u64 = count.parse().map_err(|_| invalid())?;
+            let end = (start as u64 + count).checked_sub(1).filter(|&end| count > 0 && end <= u32::MAX as u64).ok_or_else(invalid)?;
+            for block in crate::range_to_blocks(start, end as u32) {
+                networks.push((block, Some(cc.to_string())));
+            }
+        }
+        let file_epoch = match file_epoch {
+            Some(epoch) => epoch,
+            None => modified_epoch(path)?,
+        };
+        build_epoch = Some(build_epoch.map_or(file_epoch, |e| e.min(file_epoch)));
+    }
+    Ok((networks, build_epoch.unwrap_or(0)))
+}
+
+#[test]
+fn test_split_csv() {
+    assert_eq!(split_csv("1,\"Korea, Republic of\",KR"), vec!["1", "Korea, Republic of", "KR"]);
+    assert_eq!(split_csv("\"a \"\"b\"\"\",,c\r"), vec!["a \"b\"", "", "c"]);
+}
+
+#[test]
+fn test_merge_policies() {
+    let network = |cidr: &str, code: &str| (cidr.parse::<NetworkBlock>().unwrap(), Some(code.to_string()));
+    // 1 つ目は /23 全体を JP、2 つ目は後半の /24 だけを US とする
+    let sources = vec![vec![network("10.0.0.0/23", "JP")], vec![network("10.0.1.0/24", "US")]];
+    let codes = |policy| merge(&sources, policy, &Rules::default()).into_iter().map(|(block, code)| format!("{} {}", block.to_string(), code.unwrap())).collect::<Vec<_>>();
+    assert_eq!(codes(ConflictPolicy::AnyForeign), ["10.0.0.0/24 JP", "10.0.1.0/24 US"]);
+    assert_eq!(codes(ConflictPolicy::AllAgree), ["10.0.0.0/23 JP"]);
+    assert_eq!(codes(ConflictPolicy::Priority), ["10.0.0.0/23 JP"]);
+}
+
+#[test]
+fn test_read_ip2location_csv() {
+    let path = std::env::temp_dir().join(format!("ipcheck-ip2location-{}.csv", std::process::id()));
+    // 16777216-16777471 = 1.0.0.0-1.0.0.255、16777472-16778239 = 1.0.1.0-1.0.3.255 (/24 と /23 に分かれる)。
+    // IPv6 版の ::ffff:2.0.0.0/24 は IPv4 に戻し、IPv4 以外の範囲は読み飛ばす
+    std::fs::write(
+        &path,
+        "\"16777216\",\"16777471\",\"AU\",\"Australia\"\n\
+         \"16777472\",\"16778239\",\"CN\",\"China\"\n\
+         \"16843008\",\"16843263\",\"-\",\"-\"\n\
+         \"281470715297792\",\"281470715298047\",\"JP\",\"Japan\"\n\
+         \"58569071813452613185929873510317667680\",\"58569071813452613185929873510317667681\",\"DE\",\"Germany\"\n",
+    )
+    .unwrap();
+    let (networks, _) = read_ip2location_csv(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let rows: Vec<String> = networks.iter().map(|(block, code)| format!("{} {}", block.to_string(), code.as_deref().unwrap_or("-"))).collect();
+    assert_eq!(rows, ["1.0.0.0/24 AU", "1.0.1.0/24 CN", "1.0.2.0/23 CN", "1.1.1.0/24 -", "2.0.0.0/24 JP"]);
+}
+
+#[test]
+fn test_read_dbip_csv() {
+    let path = std::env::temp_dir().join(format!("ipcheck-dbip-{}.csv", std::process::id()));
+    // 無償版 (開始,終了,国コード) と有償版 (開始,終了,大陸,国コード,...) の行が混ざっても読める。IPv6 の範囲は読み飛ばし、ZZ は国なしにする
+    std::fs::write(
+        &path,
+        "1.0.0.0,1.0.0.255,AU\n\
+         1.0.1.0,1.0.3.255,AS,CN,China\n\
+         1.1.1.0,1.1.1.255,ZZ\n\
+         2001:200::,2001:200:ffff:ffff:ffff:ffff:ffff:ffff,JP\n",
+    )
+    .unwrap();
+    let (networks, _) = read_dbip_csv(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let rows: Vec<String> = networks.iter().map(|(block, code)| format!("{} {}", block.to_string(), code.as_deref().unwrap_or("-"))).collect();
+    assert_eq!(rows, ["1.0.0.0/24 AU", "1.0.1.0/24 CN", "1.0.2.0/23 CN", "1.1.1.0/24 -"]);
+}
+
+#[test]
+fn test_read_rir() {
+    let dir = std::env::temp_dir().join(format!("ipcheck-rir-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (apnic, ripe) = (dir.join("delegated-apnic-extended-latest"), dir.join("delegated-ripencc-extended-latest"));
+    // 件数 768 は /23 と /24 に分かれる。summary 行・IPv6・未割り振り・予約は含めない
+    std::fs::write(
+        &apnic,
+        "# コメント\n\
+         2|apnic|20240115|4|19830613|20240114|+1000\n\
+         apnic|*|ipv4|*|3|summary\n\
+         apnic|JP|ipv4|1.0.0.0|768|20110412|allocated|A91872ED\n\
+         apnic|CN|ipv4|1.0.4.0|256|20110414|assigned\n\
+         apnic|JP|ipv6|2001:200::|35|19990813|allocated\n\
+         apnic||ipv4|1.0.5.0|256||available\n\
+         apnic|ZZ|ipv4|1.0.6.0|256||reserved\n",
+    )
+    .unwrap();
+    // シリアルが日付でなければ終了日を使い、複数ファイルでは最も古い日付を build_epoch にする
+    std::fs::write(&ripe, "2.3|ripencc|1704844800|2|19830705|20240110|+0100\nripencc|DE|ipv4|2.0.0.0|1024|20100712|allocated\n").unwrap();
+    let (networks, build_epoch) = read_rir(&rir_files(dir.to_str().unwrap()).unwrap()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let rows: Vec<String> = networks.iter().map(|(block, code)| format!("{} {}", block.to_string(), code.as_deref().unwrap_or("-"))).collect();
+    assert_eq!(rows, ["1.0.0.0/23 JP", "1.0.2.0/24 JP", "1.0.4.0/24 CN", "2.0.0.0/22 DE"]);
+    assert_eq!(build_epoch, crate::output::epoch_from_civil(2024, 1, 10).unwrap());
+}
@@ -0,0 +1,119 @@
+//! --split-by-country: 海外リストを国 (または --country-group のグループ) ごとのファイルに分ける
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use ipcheck_core::binary::{ranges, subtract};
+use ipcheck_core::rules::{Rules, split_location};
+
+use crate::{NetworkBlock, range_to_blocks};
+
+/// データベース上の国が分からない範囲 (フィードや例外リストで加えたものなど) の名前
+pub const UNKNOWN: &str = "unknown";
+
+/// NAME=CC,CC,...
+#[derive(Clone)]
+pub struct Group {
+    pub name: String,
+    codes: Vec<String>,
+}
+
+impl std::str::FromStr for Group {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, codes) = s.split_once('=').ok_or_else(|| format!("NAME=CC,CC,... の形式で指定してください: {}", s))?;
+        if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+            return Err(format!("グループ名には英数字・-・_ だけを使ってください: {}", name));
+        }
+        let codes: Vec<String> = codes.split(',').map(|c| c.trim().to_ascii_uppercase()).filter(|c| !c.is_empty()).collect();
+        if codes.is_empty() {
+            return Err(format!("グループ {} に国コードがありません", name));
+        }
+        Ok(Group { name: name.to_string(), codes })
+    }
+}
+
+fn to_blocks(ranges: Vec<(u32, u32)>) -> Vec<NetworkBlock> {
+    ranges.into_iter().flat_map(|(start, end)| range_to_blocks(start, end)).collect()
+}
+
+/// 海外リストを国コードごとに分ける。各国の範囲は海外リストと重なる部分だけにするので、例外リストや --range も反映される
+pub fn by_country(networks: &[(NetworkBlock, Option<String>)], rules: &Rules, foreign: &[NetworkBlock]) -> BTreeMap<String, Vec<NetworkBlock>> {
+    let mut countries: BTreeMap<&str, Vec<NetworkBlock>> = BTreeMap::new();
+    for (block, location) in networks.iter().filter(|(_, location)| rules.is_foreign(location.as_deref())) {
+        let code = location.as_deref().map_or(UNKNOWN, |location| split_location(location).0);
+        countries.entry(code).or_default().push(*block);
+    }
+    let foreign = ranges(foreign);
+    let mut covered = Vec::new();
+    let mut split = BTreeMap::new();
+    for (code, blocks) in countries {
+        let country = ranges(&blocks);
+        let part = subtract(&country, &subtract(&country, &foreign));
+        if !part.is_empty() {
+            covered.extend(blocks);
+            split.insert(code.to_string(), to_blocks(part));
+        }
+    }
+    let rest = subtract(&foreign, &ranges(&covered));
+    if !rest.is_empty() {
+        let unknown = split.entry(UNKNOWN.to_string()).or_insert_with(Vec::new);
+        unknown.extend(to_blocks(rest));
+        *unknown = to_blocks(ranges(unknown));
+    }
+    split
+}
+
+/// 国ごとの範囲をグループごとにまとめ直す。どのグループにも入らない国は含めない
+pub fn by_group(countries: &BTreeMap<String, Vec<NetworkBlock>>, groups: &[Group]) -> BTreeMap<String, Vec<NetworkBlock>> {
+    groups
+        .iter()
+        .map(|group| {
+            let blocks: Vec<NetworkBlock> = group.codes.iter().filter_map(|code| countries.get(code)).flatten().copied().collect();
+            (group.name.clone(), to_blocks(ranges(&blocks)))
+        })
+        .collect()
+}
+
+/// 出力ファイルの名前に国コード (グループ名) を挟む (foreign_ip_cidrs.json -> foreign_ip_cidrs.CN.json)
+pub fn path(output_path: &str, name: &str) -> String {
+    let path = Path::new(output_path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, name, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, name),
+    };
+    path.with_file_name(file_name).display().to_string()
+}
+
+#[test]
+fn test_split_by_country() {
+    let block = |cidr: &str| -> NetworkBlock { cidr.parse().unwrap() };
+    let networks = vec![
+        (block("1.0.0.0/24"), Some("CN".to_string())),
+        (block("1.0.1.0/24"), Some("JP".to_string())),
+        (block("2.0.0.0/16"), Some("US".to_string())),
+        (block("3.0.0.0/16"), Some("KR".to_string())),
+    ];
+    // 2.0.0.0/16 の半分は例外で外れ、9.9.9.0/24 はフィードで加わった想定
+    let foreign = vec![block("1.0.0.0/24"), block("2.0.0.0/17"), block("3.0.0.0/16"), block("9.9.9.0/24")];
+    let countries = by_country(&networks, &Rules::default(), &foreign);
+    let names: Vec<(&str, Vec<String>)> = countries.iter().map(|(code, blocks)| (code.as_str(), blocks.iter().map(|b| b.to_string()).collect())).collect();
+    assert_eq!(
+        names,
+        [
+            ("CN", vec!["1.0.0.0/24".to_string()]),
+            ("KR", vec!["3.0.0.0/16".to_string()]),
+            ("US", vec!["2.0.0.0/17".to_string()]),
+            ("unknown", vec!["9.9.9.0/24".to_string()]),
+        ]
+    );
+
+    let groups = by_group(&countries, &["asia=CN,KR".parse().unwrap(), "na=us,ca".parse().unwrap()]);
+    assert_eq!(groups["asia"].len(), 2);
+    assert_eq!(groups["na"][0].to_string(), "2.0.0.0/17");
+    assert!("bad name=CN".parse::<Group>().is_err());
+
+    assert_eq!(path("out/foreign_ip_cidrs.json", "CN"), "out/foreign_ip_cidrs.CN.json");
+}
@@ -0,0 +1,138 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+
+use ipnetwork::IpNetwork;
+use ipcheck_core::aggregate_blocks;
+use maxminddb::{Reader, Within};
+
+use crate::rules::{Rules, split_location};
+use crate::{CountryRecord, NetworkBlock};
+
+#[derive(Default)]
+pub struct CountryStats {
+    pub networks_v4: usize,
+    pub addresses_v4: u64,
+    pub networks_v6: usize,
+    pub addresses_v6: u128,
+}
+
+#[derive(Default)]
+pub struct DbStats {
+    /// 国コードごとの集計 (国情報がないネットワークは "--")
+    pub countries: BTreeMap<String, CountryStats>,
+    pub networks_v4: usize,
+    pub addresses_v4: u64,
+    pub networks_v6: usize,
+    pub addresses_v6: u128,
+}
+
+// IPv4 の部分木を指す IPv6 側の別名領域。二重計上を避けるため IPv6 の集計から除く
+const IPV4_ALIASES: [&str; 4] = ["::/96", "::ffff:0:0/96", "2001::/32", "2002::/16"];
+
+fn country_key(record: CountryRecord) -> String {
+    record.iso_code().unwrap_or_else(|| "--".to_string())
+}
+
+/// データベース全体を走査し、国別のネットワーク数・アドレス数を集計する
+pub fn collect<S: AsRef<[u8]>>(reader: &Reader<S>) -> Result<DbStats, Box<dyn std::error::Error>> {
+    let mut stats = DbStats::default();
+
+    let iter: Within<CountryRecord, _> = reader.within(IpNetwork::V4("0.0.0.0/0".parse().unwrap()))?;
+    for item in iter {
+        let item = item?;
+        let addresses = 1u64 << (32 - item.ip_net.prefix());
+        let entry = stats.countries.entry(country_key(item.info)).or_default();
+        entry.networks_v4 += 1;
+        entry.addresses_v4 += addresses;
+        stats.networks_v4 += 1;
+        stats.addresses_v4 += addresses;
+    }
+
+    if reader.metadata.ip_version == 6 {
+        let aliases: Vec<IpNetwork> = IPV4_ALIASES.iter().map(|a| a.parse().unwrap()).collect();
+        let iter: Within<CountryRecord, _> = reader.within("::/0".parse().unwrap())?;
+        for item in iter {
+            let item = item?;
+            let IpAddr::V6(_) = item.ip_net.ip() else { continue };
+            if aliases.iter().any(|alias| alias.contains(item.ip_net.ip()) && alias.prefix() <= item.ip_net.prefix()) {
+                continue;
+            }
+            let addresses = if item.ip_net.prefix() == 0 { u128::MAX } else { 1u128 << (128 - item.ip_net.prefix()) };
+            let entry = stats.countries.entry(country_key(item.info)).or_default();
+            entry.networks_v6 += 1;
+            entry.addresses_v6 += addresses;
+            stats.networks_v6 += 1;
+            stats.addresses_v6 += addresses;
+        }
+    }
+
+    Ok(stats)
+}
+
+pub fn coverage_v4(addresses: u64) -> f64 {
+    addresses as f64 * 100.0 / (1u64 << 32) as f64
+}
+
+pub fn coverage_v6(addresses: u128) -> f64 {
+    addresses as f64 * 100.0 / 2f64.powi(128)
+}
+
+pub struct TopCountry {
+    pub code: String,
+    pub networks: usize,
+    pub addresses: u64,
+    /// 海外アドレス全体に占める割合 (%)
+    pub share: f64,
+    /// その国だけを集約した場合のエントリ数
+    pub entries: usize,
+    /// その国を許可リストに加えた場合に減るエントリ数
+    pub savings: usize,
+}
+
+/// 海外判定された国をアドレス数で順位付けし、ブロックリストの大きさへの寄与を計算する
+pub fn top_countries<S: AsRef<[u8]>>(reader: &Reader<S>, rules: &Rules, n: usize) -> Result<(usize, Vec<TopCountry>), Box<dyn std::error::Error>> {
+    let mut by_country: HashMap<String, Vec<NetworkBlock>> = HashMap::new();
+    let iter: Within<CountryRecord, _> = reader.within(IpNetwork::V4("0.0.0.0/0".parse().unwrap()))?;
+    for item in iter {
+        let item = item?;
+        let IpAddr::V4(ip) = item.ip_net.ip() else { continue };
+        // --domestic-subdivision のときは地域まで見て判定し、集計は国ごとにまとめる
+        let location = item.info.location(rules);
+        if rules.is_foreign(location.as_deref()) {
+            let code = location.as_deref().map_or("--", |location| split_location(location).0);
+            by_country.entry(code.to_string()).or_default().push(NetworkBlock::new(u32::from(ip), item.ip_net.prefix()));
+        }
+    }
+
+    let all: Vec<NetworkBlock> = by_country.values().flatten().copied().collect();
+    let total_addresses: u64 = all.iter().map(|b| 1u64 << (32 - b.prefix_len)).sum();
+    let total_entries = aggregate_blocks(all).len();
+
+    let mut ranked: Vec<(&String, &Vec<NetworkBlock>, u64)> = by_country
+        .iter()
+        .map(|(code, blocks)| (code, blocks, blocks.iter().map(|b| 1u64 << (32 - b.prefix_len)).sum()))
+        .collect();
+    ranked.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)));
+
+    let top = ranked
+        .into_iter()
+        .take(n)
+        .map(|(code, blocks, addresses)| {
+            let others: Vec<NetworkBlock> = by_country
+                .iter()
+                .filter(|(other, _)| *other != code)
+                .flat_map(|(_, blocks)| blocks.iter().copied())
+                .collect();
+            TopCountry {
+                code: code.clone(),
+                networks: blocks.len(),
+                addresses,
+                share: addresses as f64 * 100.0 / total_addresses.max(1) as f64,
+                entries: aggregate_blocks(blocks.clone()).len(),
+                savings: total_entries.saturating_sub(aggregate_blocks(others).len()),
+            }
+        })
+        .collect();
+
+    Ok((total_entries, top))
+}
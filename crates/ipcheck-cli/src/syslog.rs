@@ -0,0 +1,147 @@
+//! RFC 5424 形式で syslog へ送るイベントログ
+
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+use std::sync::{Mutex, OnceLock};
+
+use crate::output::civil_from_epoch;
+use crate::schedule;
+
+/// facility は daemon (3)
+const FACILITY: u8 = 3;
+
+#[derive(Clone, Copy)]
+pub enum Severity {
+    Error = 3,
+    Warning = 4,
+    Info = 6,
+}
+
+/// 送り先。local は /dev/log、それ以外は udp://ホスト:ポート、tcp://ホスト:ポート、または UNIX ソケットのパス
+#[derive(Clone)]
+pub enum Target {
+    Unix(String),
+    Udp(String),
+    Tcp(String),
+}
+
+impl std::str::FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let with_port = |addr: &str| if addr.contains(':') { addr.to_string() } else { format!("{}:514", addr) };
+        if s == "local" {
+            Ok(Target::Unix("/dev/log".to_string()))
+        } else if let Some(addr) = s.strip_prefix("udp://") {
+            Ok(Target::Udp(with_port(addr)))
+        } else if let Some(addr) = s.strip_prefix("tcp://") {
+            Ok(Target::Tcp(with_port(addr)))
+        } else if s.starts_with('/') {
+            Ok(Target::Unix(s.to_string()))
+        } else {
+            Err(format!("local, udp://ホスト[:ポート], tcp://ホスト[:ポート] または UNIX ソケットのパスを指定してください: {}", s))
+        }
+    }
+}
+
+enum Sender {
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram, String),
+    Udp(UdpSocket),
+    /// RFC 6587 の octet counting で区切る
+    Tcp(TcpStream),
+}
+
+struct Logger {
+    sender: Sender,
+    hostname: String,
+}
+
+static LOGGER: OnceLock<Mutex<Logger>> = OnceLock::new();
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty() && h.is_ascii() && !h.contains(' '))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// 以降の log を target へ送るようにする
+pub fn init(target: &Target) -> std::io::Result<()> {
+    let sender = match target {
+        #[cfg(unix)]
+        Target::Unix(path) => Sender::Unix(std::os::unix::net::UnixDatagram::unbound()?, path.clone()),
+        #[cfg(not(unix))]
+        Target::Unix(_) => return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "UNIX ソケットはこの環境では使えません")),
+        Target::Udp(addr) => {
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
+            socket.connect(addr)?;
+            Sender::Udp(socket)
+        }
+        Target::Tcp(addr) => Sender::Tcp(TcpStream::connect(addr)?),
+    };
+    let _ = LOGGER.set(Mutex::new(Logger { sender, hostname: hostname() }));
+    Ok(())
+}
+
+/// 1 行の syslog メッセージ。ASCII 以外を含む本文には BOM を付けて UTF-8 であることを示す
+fn format(severity: Severity, msgid: &str, message: &str, epoch: u64, hostname: &str) -> String {
+    let (year, month, day, secs) = civil_from_epoch(epoch);
+    let bom = if message.is_ascii() { "" } else { "\u{feff}" };
+    format!(
+        "<{}>1 {:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z {} ipcheck {} {} - {}{}",
+        FACILITY * 8 + severity as u8,
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        hostname,
+        std::process::id(),
+        msgid,
+        bom,
+        message.replace('\n', " ")
+    )
+}
+
+/// --syslog を指定していなければ何もしない。送れなくても処理は止めない
+pub fn log(severity: Severity, msgid: &str, message: &str) {
+    let Some(logger) = LOGGER.get() else { return };
+    let mut logger = logger.lock().unwrap();
+    let line = format(severity, msgid, message, schedule::now_epoch(), &logger.hostname);
+    let result = match &mut logger.sender {
+        #[cfg(unix)]
+        Sender::Unix(socket, path) => socket.send_to(line.as_bytes(), path.as_str()).map(|_| ()),
+        Sender::Udp(socket) => socket.send(line.as_bytes()).map(|_| ()),
+        Sender::Tcp(stream) => stream.write_all(format!("{} {}", line.len(), line).as_bytes()),
+    };
+    if let Err(e) = result {
+        eprintln!("警告: syslog へ送れませんでした: {}", e);
+    }
+}
+
+pub fn info(msgid: &str, message: &str) {
+    log(Severity::Info, msgid, message);
+}
+
+pub fn warning(msgid: &str, message: &str) {
+    log(Severity::Warning, msgid, message);
+}
+
+pub fn error(msgid: &str, message: &str) {
+    log(Severity::Error, msgid, message);
+}
+
+#[test]
+fn test_format() {
+    assert_eq!(
+        format(Severity::Info, "generate", "6 CIDR", 784111777, "router"),
+        format!("<30>1 1994-11-06T08:49:37Z router ipcheck {} generate - 6 CIDR", std::process::id())
+    );
+    assert!(format(Severity::Error, "generate", "失敗\n詳細", 0, "-").ends_with("generate - \u{feff}失敗 詳細"));
+    assert!(matches!("udp://10.0.0.1".parse::<Target>(), Ok(Target::Udp(addr)) if addr == "10.0.0.1:514"));
+    assert!("10.0.0.1".parse::<Target>().is_err());
+}
@@ -0,0 +1,106 @@
+//! systemd の Type=notify と WatchdogSec= への対応 (sd_notify のプロトコルを直接話す)
+
+use std::time::{Duration, Instant};
+
+/// NOTIFY_SOCKET へ状態を送る。systemd 配下でなければ何もしない
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    if let Err(e) = send(state) {
+        eprintln!("警告: systemd への通知に失敗しました: {}", e);
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+#[cfg(unix)]
+fn send(state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else { return Ok(()) };
+    let socket = UnixDatagram::unbound()?;
+    let path = path.to_string_lossy();
+    match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), path.as_ref())?;
+        }
+    }
+    Ok(())
+}
+
+/// 起動と初回の生成が終わったことを知らせる
+pub fn ready(status: &str) {
+    notify(&format!("READY=1\nSTATUS={}", status));
+}
+
+pub fn status(status: &str) {
+    notify(&format!("STATUS={}", status));
+}
+
+/// watchdog に生存を知らせる間隔 (WatchdogSec の半分)。watchdog が無効なら None
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Some(pid) = std::env::var("WATCHDOG_PID").ok().and_then(|p| p.parse::<u32>().ok())
+        && pid != std::process::id()
+    {
+        return None;
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+pub fn watchdog() {
+    notify("WATCHDOG=1");
+}
+
+/// duration だけ待つ。watchdog が有効なら待っている間も生存を知らせ続ける
+pub fn sleep(duration: Duration) {
+    let Some(interval) = watchdog_interval() else {
+        std::thread::sleep(duration);
+        return;
+    };
+    // Instant で表せないほど先なら、期限なしで知らせ続ける
+    let deadline = Instant::now().checked_add(duration);
+    loop {
+        watchdog();
+        let now = Instant::now();
+        match deadline {
+            Some(deadline) if now >= deadline => return,
+            Some(deadline) => std::thread::sleep(interval.min(deadline - now)),
+            None => std::thread::sleep(interval),
+        }
+    }
+}
+
+/// 別スレッドから watchdog に知らせ続ける。check が false を返す間は知らせず、systemd に再起動させる
+pub fn spawn_watchdog(check: impl Fn() -> bool + Send + 'static) {
+    let Some(interval) = watchdog_interval() else { return };
+    std::thread::spawn(move || {
+        loop {
+            if check() {
+                watchdog();
+            }
+            std::thread::sleep(interval);
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_notify_socket() {
+    let path = std::env::temp_dir().join(format!("ipcheck-notify-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+    // SAFETY: このテストのほかに NOTIFY_SOCKET を読み書きするテストはない
+    unsafe { std::env::set_var("NOTIFY_SOCKET", &path) };
+    ready("生成完了");
+    let mut buf = [0u8; 256];
+    let n = listener.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..n], "READY=1\nSTATUS=生成完了".as_bytes());
+    unsafe { std::env::remove_var("NOTIFY_SOCKET") };
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,251 @@
+//! ipcheck tail: ログに追記された行から IP アドレスを取り出し、生成済みリストで海外判定する
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::net::Ipv4Addr;
+use std::path::Path;
+
+#[derive(Clone, Debug)]
+enum Atom {
+    Any,
+    Char(char),
+    /// 範囲の列と、否定 ([^...]) かどうか
+    Class(Vec<(char, char)>, bool),
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Atom::Any => true,
+            Atom::Char(expected) => c == *expected,
+            Atom::Class(ranges, negated) => ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != *negated,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Repeat {
+    One,
+    ZeroOrOne,
+    ZeroOrMore,
+    OneOrMore,
+}
+
+/// --pattern の正規表現。依存を増やさないよう、ログの絞り込みに使う範囲だけを実装している:
+/// リテラル、`.`、`[...]` (範囲と否定)、`\d` `\w` `\s`、`*` `+` `?`、先頭の `^` と末尾の `$`。
+/// グループ・選択 (`|`)・回数指定 (`{n}`) はエラーにする
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    items: Vec<(Atom, Repeat)>,
+    anchored_start: bool,
+    anchored_end: bool,
+}
+
+fn escape(c: char) -> Atom {
+    match c {
+        'd' => Atom::Class(vec![('0', '9')], false),
+        'D' => Atom::Class(vec![('0', '9')], true),
+        'w' => Atom::Class(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')], false),
+        'W' => Atom::Class(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')], true),
+        's' => Atom::Class(vec![(' ', ' '), ('\t', '\r')], false),
+        'S' => Atom::Class(vec![(' ', ' '), ('\t', '\r')], true),
+        't' => Atom::Char('\t'),
+        c => Atom::Char(c),
+    }
+}
+
+fn class_char(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<char, String> {
+    match chars.next() {
+        Some('\\') => chars.next().ok_or_else(|| "[ ] の中の \\ の後に文字がありません".to_string()),
+        Some(c) => Ok(c),
+        None => Err("[ に対応する ] がありません".to_string()),
+    }
+}
+
+impl std::str::FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (anchored_start, body) = match s.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let mut items: Vec<(Atom, Repeat)> = Vec::new();
+        let mut anchored_end = false;
+        let mut chars = body.chars().peekable();
+        while let Some(c) = chars.next() {
+            let atom = match c {
+                '.' => Atom::Any,
+                '\\' => escape(chars.next().ok_or("末尾の \\ の後に文字がありません")?),
+                '[' => {
+                    let negated = chars.next_if_eq(&'^').is_some();
+                    let mut ranges = Vec::new();
+                    // 先頭の ] は文字として扱う
+                    let mut first = true;
+                    loop {
+                        if chars.peek() == Some(&']') && !first {
+                            chars.next();
+                            break;
+                        }
+                        first = false;
+                        let lo = class_char(&mut chars)?;
+                        let hi = match chars.peek() {
+                            Some('-') => {
+                                chars.next();
+                                match chars.peek() {
+                                    Some(']') | None => {
+                                        ranges.push(('-', '-'));
+                                        lo
+                                    }
+                                    Some(_) => class_char(&mut chars)?,
+                                }
+                            }
+                            _ => lo,
+                        };
+                        ranges.push((lo, hi));
+                    }
+                    Atom::Class(ranges, negated)
+                }
+                '$' if chars.peek().is_none() => {
+                    anchored_end = true;
+                    break;
+                }
+                '*' | '+' | '?' => {
+                    let repeat = match c {
+                        '*' => Repeat::ZeroOrMore,
+                        '+' => Repeat::OneOrMore,
+                        _ => Repeat::ZeroOrOne,
+                    };
+                    match items.last_mut() {
+                        Some((_, last @ Repeat::One)) => *last = repeat,
+                        _ => return Err(format!("{} の前に繰り返す文字がありません: {}", c, s)),
+                    }
+                    continue;
+                }
+                '(' | ')' | '|' | '{' => return Err(format!("グループ・選択・回数指定 ({}) には対応していません: {}", c, s)),
+                c => Atom::Char(c),
+            };
+            items.push((atom, Repeat::One));
+        }
+        Ok(Pattern { items, anchored_start, anchored_end })
+    }
+}
+
+impl Pattern {
+    /// states に state を加え、0 回でよい繰り返しは飛ばした先も加える
+    fn add(&self, states: &mut [bool], mut state: usize) {
+        while !states[state] {
+            states[state] = true;
+            match self.items.get(state) {
+                Some((_, Repeat::ZeroOrOne | Repeat::ZeroOrMore)) => state += 1,
+                _ => break,
+            }
+        }
+    }
+
+    /// 行のどこかに一致するか (^ / $ があればその位置に固定する)。
+    /// 攻撃者が書ける行 (ssh のユーザー名など) でも遅くならないよう、バックトラックせずに
+    /// 到達しうる位置の集合を 1 文字ずつ進める (行の長さ × パターンの長さに比例する)
+    pub fn is_match(&self, line: &str) -> bool {
+        let accept = self.items.len();
+        let mut states = vec![false; accept + 1];
+        self.add(&mut states, 0);
+        for c in line.chars() {
+            if states[accept] && !self.anchored_end {
+                return true;
+            }
+            let mut next = vec![false; accept + 1];
+            for (state, (atom, repeat)) in self.items.iter().enumerate() {
+                if !states[state] || !atom.matches(c) {
+                    continue;
+                }
+                match repeat {
+                    Repeat::One | Repeat::ZeroOrOne => self.add(&mut next, state + 1),
+                    Repeat::ZeroOrMore => self.add(&mut next, state),
+                    Repeat::OneOrMore => {
+                        self.add(&mut next, state);
+                        self.add(&mut next, state + 1);
+                    }
+                }
+            }
+            if !self.anchored_start {
+                self.add(&mut next, 0);
+            }
+            states = next;
+        }
+        states[accept]
+    }
+}
+
+/// 行に含まれる IPv4 アドレス (::ffff:1.2.3.4 や "1.2.3.4:22" の形も拾う)
+pub fn addresses(line: &str) -> Vec<Ipv4Addr> {
+    line.split(|c: char| !c.is_ascii_digit() && c != '.')
+        .map(|token| token.trim_matches('.'))
+        .filter_map(|token| token.parse().ok())
+        .collect()
+}
+
+/// tail -F と同じく、ローテーションや切り詰めがあれば開き直して追いかける
+pub struct Follower {
+    reader: BufReader<File>,
+    position: u64,
+    #[cfg(unix)]
+    inode: u64,
+}
+
+impl Follower {
+    /// from_start でなければ、既存の内容は読まずに末尾から追う
+    pub fn open(path: &Path, from_start: bool) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        let position = if from_start { 0 } else { file.seek(SeekFrom::End(0))? };
+        Ok(Follower {
+            #[cfg(unix)]
+            inode: std::os::unix::fs::MetadataExt::ino(&file.metadata()?),
+            reader: BufReader::new(file),
+            position,
+        })
+    }
+
+    /// 改行まで書き込まれた行を 1 つ返す。まだなければ None
+    pub fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        let mut buf = Vec::new();
+        let read = self.reader.read_until(b'\n', &mut buf)?;
+        if read == 0 || !buf.ends_with(b"\n") {
+            // 書き込み途中の行は次回まとめて読む
+            self.reader.seek(SeekFrom::Start(self.position))?;
+            return Ok(None);
+        }
+        self.position += read as u64;
+        Ok(Some(String::from_utf8_lossy(&buf).trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    /// path が別のファイルに置き換わったか、切り詰められたか
+    pub fn rotated(&self, path: &Path) -> bool {
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return false;
+        };
+        #[cfg(unix)]
+        if std::os::unix::fs::MetadataExt::ino(&metadata) != self.inode {
+            return true;
+        }
+        metadata.len() < self.position
+    }
+}
+
+#[test]
+fn test_tail_pattern() {
+    let pattern: Pattern = r"Failed password for .+ from [0-9.]+ port \d+".parse().unwrap();
+    assert!(pattern.is_match("sshd[812]: Failed password for root from 1.0.0.1 port 52814 ssh2"));
+    assert!(!pattern.is_match("sshd[812]: Accepted publickey for admin from 1.0.0.1 port 52814 ssh2"));
+    let anchored: Pattern = "^sshd: ab?c*$".parse().unwrap();
+    assert!(anchored.is_match("sshd: a") && anchored.is_match("sshd: abccc"));
+    assert!(!anchored.is_match("x sshd: ac") && !anchored.is_match("sshd: acd"));
+    assert!("[^a-]x".parse::<Pattern>().unwrap().is_match("bx"));
+    assert!("(a|b)".parse::<Pattern>().is_err() && "*a".parse::<Pattern>().is_err());
+    // 一致しない長い行でもバックトラックで指数的に遅くならない
+    let nested: Pattern = ".+ from .+ from .+ port".parse().unwrap();
+    assert!(!nested.is_match(&"x from ".repeat(5000)));
+    assert!(nested.is_match("user x from y from 1.0.0.1 port 22"));
+
+    assert_eq!(addresses("from ::ffff:1.2.3.4 port 22, via 10.0.0.1:8080. 1.2.3 999.1.1.1"), [Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::new(10, 0, 0, 1)]);
+}
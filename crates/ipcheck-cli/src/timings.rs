@@ -0,0 +1,52 @@
+//! --timings で表示する工程別の所要時間
+
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+pub struct Timings {
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    /// 同じ工程は合計する
+    pub fn add(&mut self, phase: &'static str, duration: Duration) {
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += duration,
+            None => self.phases.push((phase, duration)),
+        }
+    }
+
+    pub fn time<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.add(phase, start.elapsed());
+        result
+    }
+
+    /// 計測した順に工程ごとの時間と全体に占める割合を並べる。どの工程にも入らない時間は「その他」にまとめる
+    pub fn report(&self, total: Duration) -> Vec<String> {
+        let measured: Duration = self.phases.iter().map(|(_, d)| *d).sum();
+        let other = total.saturating_sub(measured);
+        let percent = |d: Duration| if total.is_zero() { 0.0 } else { d.as_secs_f64() * 100.0 / total.as_secs_f64() };
+        self.phases
+            .iter()
+            .copied()
+            .chain([("その他", other)])
+            // 全角の工程名で列がずれないよう、名前は行末に置く
+            .map(|(name, d)| format!("{:>10.3}ms {:>5.1}%  {}", d.as_secs_f64() * 1000.0, percent(d), name))
+            .chain([format!("{:>10.3}ms {:>6}  合計", total.as_secs_f64() * 1000.0, "")])
+            .collect()
+    }
+}
+
+#[test]
+fn test_timings_report() {
+    let mut timings = Timings::default();
+    timings.add("sort", Duration::from_millis(10));
+    timings.add("aggregate", Duration::from_millis(20));
+    timings.add("sort", Duration::from_millis(10));
+    let lines = timings.report(Duration::from_millis(50));
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "    20.000ms  40.0%  sort");
+    assert_eq!(lines[2], "    10.000ms  20.0%  その他");
+}
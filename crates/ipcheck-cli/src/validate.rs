@@ -0,0 +1,122 @@
+use std::net::Ipv4Addr;
+
+use crate::binary::ranges;
+use crate::{NetworkBlock, range_to_blocks};
+
+pub enum Issue {
+    Malformed { line: usize, entry: String, reason: String },
+    HostBits { line: usize, entry: String, canonical: NetworkBlock },
+    Duplicate { line: usize, entry: String, first_line: usize },
+    Overlap { line: usize, entry: String, container: NetworkBlock, container_line: usize },
+    Mergeable { lines: (usize, usize), parent: NetworkBlock },
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Issue::Malformed { line, entry, reason } => write!(f, "{}行目: 不正な CIDR '{}' ({})", line, entry, reason),
+            Issue::HostBits { line, entry, canonical } => {
+                write!(f, "{}行目: ホスト部が 0 ではありません '{}' (正規形: {})", line, entry, canonical.to_string())
+            }
+            Issue::Duplicate { line, entry, first_line } => write!(f, "{}行目: '{}' は {}行目と重複しています", line, entry, first_line),
+            Issue::Overlap { line, entry, container, container_line } => write!(
+                f,
+                "{}行目: '{}' は {}行目の {} に含まれています",
+                line,
+                entry,
+                container_line,
+                container.to_string()
+            ),
+            Issue::Mergeable { lines, parent } => {
+                write!(f, "{}行目と{}行目: 隣接しているため {} に結合できます", lines.0, lines.1, parent.to_string())
+            }
+        }
+    }
+}
+
+fn parse_strict(entry: &str) -> Result<(Ipv4Addr, u8), String> {
+    let (ip, prefix) = entry.split_once('/').unwrap_or((entry, "32"));
+    let ip: Ipv4Addr = ip.parse().map_err(|e| format!("{}", e))?;
+    let prefix: u8 = prefix.parse().map_err(|e| format!("{}", e))?;
+    if prefix > 32 {
+        return Err("プレフィックス長が 32 を超えています".to_string());
+    }
+    Ok((ip, prefix))
+}
+
+/// リストの問題点を列挙し、あわせて正規化した最小の CIDR 列を返す
+pub fn validate(entries: &[(usize, String)]) -> (Vec<Issue>, Vec<NetworkBlock>) {
+    let mut issues = Vec::new();
+    let mut blocks: Vec<(NetworkBlock, usize, &str)> = Vec::new();
+
+    for (line, entry) in entries {
+        match parse_strict(entry) {
+            Ok((ip, prefix)) => {
+                let block = NetworkBlock::new(u32::from(ip), prefix);
+                if block.network != u32::from(ip) {
+                    issues.push(Issue::HostBits { line: *line, entry: entry.clone(), canonical: block });
+                }
+                blocks.push((block, *line, entry));
+            }
+            Err(reason) => issues.push(Issue::Malformed { line: *line, entry: entry.clone(), reason }),
+        }
+    }
+
+    blocks.sort_by_key(|(block, line, _)| (block.network, block.prefix_len, *line));
+    let mut kept: Vec<(NetworkBlock, usize)> = Vec::new();
+    for (block, line, entry) in &blocks {
+        match kept.last() {
+            Some((prev, first_line)) if prev == block => {
+                issues.push(Issue::Duplicate { line: *line, entry: entry.to_string(), first_line: *first_line });
+            }
+            Some((prev, prev_line)) if prev.network <= block.network && block.last() <= prev.last() => {
+                issues.push(Issue::Overlap {
+                    line: *line,
+                    entry: entry.to_string(),
+                    container: *prev,
+                    container_line: *prev_line,
+                });
+            }
+            _ => kept.push((*block, *line)),
+        }
+    }
+
+    for pair in kept.windows(2) {
+        let ((a, a_line), (b, b_line)) = (pair[0], pair[1]);
+        if a.prefix_len == b.prefix_len && a.prefix_len > 0 && a.last().wrapping_add(1) == b.network {
+            let parent = NetworkBlock::new(a.network, a.prefix_len - 1);
+            if parent.network == a.network {
+                issues.push(Issue::Mergeable { lines: (a_line, b_line), parent });
+            }
+        }
+    }
+
+    let plain: Vec<NetworkBlock> = kept.iter().map(|(block, _)| *block).collect();
+    let minimal = ranges(&plain).into_iter().flat_map(|(start, end)| range_to_blocks(start, end)).collect();
+    (issues, minimal)
+}
+
+#[test]
+fn test_validate() {
+    let entries: Vec<(usize, String)> =
+        ["10.0.0.0/24", "10.0.1.0/24", "10.0.0.0/24", "10.0.0.128/25", "192.168.1.5/24", "1.2.3", "1.0.0.0/33", "10.0.2.0/24"]
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (i + 1, entry.to_string()))
+            .collect();
+    let (issues, minimal) = validate(&entries);
+    let messages: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+    assert_eq!(
+        messages,
+        [
+            "5行目: ホスト部が 0 ではありません '192.168.1.5/24' (正規形: 192.168.1.0/24)",
+            "6行目: 不正な CIDR '1.2.3' (invalid IPv4 address syntax)",
+            "7行目: 不正な CIDR '1.0.0.0/33' (プレフィックス長が 32 を超えています)",
+            "3行目: '10.0.0.0/24' は 1行目と重複しています",
+            "4行目: '10.0.0.128/25' は 1行目の 10.0.0.0/24 に含まれています",
+            // 10.0.1.0/24 と 10.0.2.0/24 も隣接しているが、/23 の境界をまたぐので結合できない
+            "1行目と2行目: 隣接しているため 10.0.0.0/23 に結合できます",
+        ]
+    );
+    assert_eq!(minimal.iter().map(|b| b.to_string()).collect::<Vec<_>>(), ["10.0.0.0/23", "10.0.2.0/24", "192.168.1.0/24"]);
+}
@@ -0,0 +1,51 @@
+use ipnetwork::IpNetwork;
+use maxminddb::{Reader, Within};
+
+use crate::binary::ranges;
+use crate::rules::Rules;
+use crate::{CountryRecord, NetworkBlock, ip_to_u32};
+
+pub struct Report {
+    pub checked: usize,
+    /// 海外判定なのにリストで覆われていないネットワーク
+    pub missing: Vec<(NetworkBlock, Option<String>)>,
+    /// 国内判定なのにリストと重なっているネットワーク
+    pub overblocked: Vec<(NetworkBlock, Option<String>)>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.overblocked.is_empty()
+    }
+}
+
+/// データベースを再走査し、出力リストが分類結果と一致しているかを検証する
+pub fn verify<S: AsRef<[u8]>>(reader: &Reader<S>, rules: &Rules, blocks: &[NetworkBlock]) -> Result<Report, Box<dyn std::error::Error>> {
+    let ranges = ranges(blocks);
+    let mut report = Report { checked: 0, missing: Vec::new(), overblocked: Vec::new() };
+
+    let iter: Within<CountryRecord, _> = reader.within(IpNetwork::V4("0.0.0.0/0".parse().unwrap()))?;
+    for item in iter {
+        let item = item?;
+        let std::net::IpAddr::V4(ip) = item.ip_net.ip() else { continue };
+        let block = NetworkBlock::new(ip_to_u32(ip), item.ip_net.prefix());
+        let iso_code = item.info.location(rules);
+        report.checked += 1;
+
+        // block.network 以降で終わる最初の範囲
+        let idx = ranges.partition_point(|&(_, end)| end < block.network);
+        let candidate = ranges.get(idx);
+        if rules.is_foreign(iso_code.as_deref()) {
+            let covered = candidate.is_some_and(|&(start, end)| start <= block.network && block.last() <= end);
+            if !covered {
+                report.missing.push((block, iso_code));
+            }
+        } else {
+            let overlaps = candidate.is_some_and(|&(start, _)| start <= block.last());
+            if overlaps {
+                report.overblocked.push((block, iso_code));
+            }
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,108 @@
+//! 生成ごとの出力を日時のディレクトリに分けて残し、latest で最新を指す
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::output::civil_from_epoch;
+
+pub const LATEST: &str = "latest";
+
+/// 20261015T104859Z の形式 (辞書順が生成順になる)
+pub fn dir_name(epoch: u64) -> String {
+    let (year, month, day, secs) = civil_from_epoch(epoch);
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn is_version(name: &str) -> bool {
+    let stamp = name.split('-').next().unwrap_or(name);
+    stamp.len() == 16 && stamp.as_bytes()[8] == b'T' && stamp.ends_with('Z')
+}
+
+/// 今回の出力先ディレクトリを作る。同じ秒に 2 回生成した場合は -1, -2 を付ける
+pub fn create(root: &Path, epoch: u64) -> io::Result<PathBuf> {
+    std::fs::create_dir_all(root)?;
+    let name = dir_name(epoch);
+    let mut dir = root.join(&name);
+    let mut n = 0;
+    loop {
+        match std::fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                n += 1;
+                dir = root.join(format!("{}-{}", name, n));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// 古い順に並べた版の名前
+pub fn list(root: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if is_version(&name) && entry.file_type()?.is_dir() {
+            names.push(name);
+        }
+    }
+    // -10 が -9 より後になるよう、日時と連番に分けて比べる
+    names.sort_by_key(|name| {
+        let (stamp, n) = name.split_once('-').unwrap_or((name, "0"));
+        (stamp.to_string(), n.parse::<u32>().unwrap_or(0))
+    });
+    Ok(names)
+}
+
+/// latest が指している版の名前
+pub fn latest(root: &Path) -> Option<String> {
+    let target = std::fs::read_link(root.join(LATEST)).ok()?;
+    Some(target.file_name()?.to_string_lossy().into_owned())
+}
+
+/// latest を name の版に向け直す。一時的なリンクを rename で置き換えるので、読む側が途中の状態を見ることはない
+pub fn publish(root: &Path, name: &str) -> io::Result<()> {
+    let tmp = root.join(format!("{}.tmp", LATEST));
+    let _ = std::fs::remove_file(&tmp);
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(name, &tmp)?;
+    #[cfg(windows)]
+    std::os::windows::fs::symlink_dir(name, &tmp)?;
+    std::fs::rename(&tmp, root.join(LATEST))
+}
+
+/// 新しいものから keep 件を残して古い版を削除する。latest が指している版は残す
+pub fn prune(root: &Path, keep: usize) -> io::Result<Vec<String>> {
+    let names = list(root)?;
+    let current = latest(root);
+    let mut removed = Vec::new();
+    for name in &names[..names.len().saturating_sub(keep)] {
+        if current.as_deref() == Some(name.as_str()) {
+            continue;
+        }
+        std::fs::remove_dir_all(root.join(name))?;
+        removed.push(name.clone());
+    }
+    Ok(removed)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_versions() {
+    let root = std::env::temp_dir().join(format!("ipcheck-versions-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    assert_eq!(dir_name(784111777), "19941106T084937Z");
+
+    let first = create(&root, 784111777).unwrap();
+    let second = create(&root, 784111777).unwrap();
+    let third = create(&root, 784111778).unwrap();
+    assert_eq!(second.file_name().unwrap(), "19941106T084937Z-1");
+    assert_eq!(list(&root).unwrap(), ["19941106T084937Z", "19941106T084937Z-1", "19941106T084938Z"]);
+
+    // latest を古い版に戻してあれば、保持数を超えても消さない
+    publish(&root, "19941106T084937Z").unwrap();
+    assert_eq!(latest(&root).as_deref(), Some("19941106T084937Z"));
+    assert_eq!(prune(&root, 1).unwrap(), ["19941106T084937Z-1"]);
+    assert!(first.exists() && !second.exists() && third.exists());
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,147 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::NetworkBlock;
+use crate::diff::diff;
+use crate::output::{self, SCHEMA_VERSION};
+
+/// 前回の出力との差分
+#[derive(Serialize)]
+pub struct DiffStats {
+    pub added: usize,
+    pub removed: usize,
+    pub added_addresses: u64,
+    pub removed_addresses: u64,
+    pub percent_change: f64,
+}
+
+impl DiffStats {
+    pub fn new(old: &[NetworkBlock], new: &[NetworkBlock]) -> Self {
+        let d = diff(old, new);
+        DiffStats {
+            added: d.added.len(),
+            removed: d.removed.len(),
+            added_addresses: d.added_addresses,
+            removed_addresses: d.removed_addresses,
+            percent_change: d.percent_change(),
+        }
+    }
+}
+
+/// 生成後に送る通知の内容。--webhook-template を省略したときはこれをそのまま JSON で送る
+#[derive(Serialize)]
+pub struct Event<'a> {
+    pub schema_version: u32,
+    pub event: &'static str,
+    pub output: &'a str,
+    pub cidrs: usize,
+    pub build_epoch: u64,
+    pub generated_at: u64,
+    pub duration_seconds: f64,
+    /// 前回の出力から変わったか (前回の出力がなければ true)
+    pub changed: bool,
+    /// 前回の出力を読めなかった場合は null
+    pub diff: Option<DiffStats>,
+}
+
+impl<'a> Event<'a> {
+    pub fn new(output: &'a str, blocks: &[NetworkBlock], previous: Option<&[NetworkBlock]>, build_epoch: u64, generated_at: u64, duration_seconds: f64) -> Self {
+        let diff = previous.map(|previous| DiffStats::new(previous, blocks));
+        Event {
+            schema_version: SCHEMA_VERSION,
+            event: "generated",
+            output,
+            cidrs: blocks.len(),
+            build_epoch,
+            generated_at,
+            duration_seconds,
+            changed: diff.as_ref().is_none_or(|d| d.added_addresses + d.removed_addresses > 0),
+            diff,
+        }
+    }
+}
+
+/// template の {{name}} (diff の項目は {{diff.added}} のように書く) を event の値に置き換える。
+/// 文字列はエスケープだけして埋め込むので、JSON の文字列の中では "{{output}}" のように囲む
+pub fn render(template: &str, event: &Event) -> Result<String, String> {
+    let values = serde_json::to_value(event).map_err(|e| e.to_string())?;
+    let mut body = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        body.push_str(&rest[..start]);
+        let end = rest[start..].find("}}").ok_or("テンプレートの {{ が閉じられていません")? + start;
+        let name = rest[start + 2..end].trim();
+        let value = name
+            .split('.')
+            .try_fold(&values, |value, key| value.get(key))
+            .ok_or_else(|| format!("テンプレートの {{{{{}}}}} は使えません", name))?;
+        match value {
+            Value::String(s) => {
+                let quoted = serde_json::to_string(s).map_err(|e| e.to_string())?;
+                body.push_str(&quoted[1..quoted.len() - 1]);
+            }
+            value => body.push_str(&value.to_string()),
+        }
+        rest = &rest[end + 2..];
+    }
+    body.push_str(rest);
+    Ok(body)
+}
+
+/// チャットの Incoming Webhook
+#[derive(Clone, Copy)]
+pub enum Chat {
+    Slack,
+    Discord,
+}
+
+impl Chat {
+    pub fn body(self, text: &str) -> String {
+        let key = match self {
+            Chat::Slack => "text",
+            Chat::Discord => "content",
+        };
+        serde_json::json!({ key: text }).to_string()
+    }
+}
+
+/// チャットに流す生成結果の概要
+pub fn summary_text(event: &Event) -> String {
+    let delta = match &event.diff {
+        Some(d) => format!(" (+{} / -{}, {:+.2}%)", d.added, d.removed, d.percent_change),
+        None => String::new(),
+    };
+    format!(
+        "ipcheck: {} を生成しました\nCIDR数: {}{}\nデータベース: {}\n処理時間: {:.2}秒",
+        event.output,
+        event.cidrs,
+        delta,
+        output::format_epoch(event.build_epoch),
+        event.duration_seconds
+    )
+}
+
+pub fn send(url: &str, body: &str) -> Result<(), Box<dyn std::error::Error>> {
+    ureq::post(url)
+        .set("Content-Type", "application/json")
+        .set("User-Agent", concat!("ipcheck/", env!("CARGO_PKG_VERSION")))
+        .send_string(body)?;
+    Ok(())
+}
+
+#[test]
+fn test_render_template() {
+    let old: Vec<NetworkBlock> = ["1.0.0.0/24"].iter().map(|s| s.parse().unwrap()).collect();
+    let new: Vec<NetworkBlock> = ["1.0.0.0/23", "8.8.8.0/24"].iter().map(|s| s.parse().unwrap()).collect();
+    let event = Event::new("out \"a\".txt", &new, Some(&old), 1700000000, 1700000100, 1.5);
+    let body = render(r#"{"text": "{{output}}: {{cidrs}} 件 (+{{diff.added_addresses}})", "changed": {{changed}}}"#, &event).unwrap();
+    assert_eq!(body, r#"{"text": "out \"a\".txt: 2 件 (+512)", "changed": true}"#);
+    assert!(render("{{unknown}}", &event).is_err());
+
+    assert!(summary_text(&event).contains("CIDR数: 2 (+2 / -0, +200.00%)"));
+    assert_eq!(Chat::Discord.body("a\"b"), r#"{"content":"a\"b"}"#);
+
+    let event = Event::new("out.txt", &old, Some(&old), 0, 0, 0.0);
+    assert!(!event.changed);
+    assert_eq!(render("{{diff.removed}}", &event).unwrap(), "0");
+}
@@ -0,0 +1,87 @@
+//! aggregate_blocks (ソートしてスタックで結合する) 以外の集約方式。ipcheck bench で比べるためのもの。
+//! どちらも aggregate_blocks にそろえて /24 より細かいブロックを /24 に丸める
+
+use crate::binary::ranges;
+use crate::{NetworkBlock, range_to_blocks};
+
+fn round(blk: &NetworkBlock) -> NetworkBlock {
+    if blk.prefix_len > 24 { NetworkBlock::new(blk.network, 24) } else { *blk }
+}
+
+/// 範囲に直してソートし、重なりと隣接をまとめてから CIDR に戻す
+pub fn sweep(blocks: Vec<NetworkBlock>) -> Vec<NetworkBlock> {
+    let rounded: Vec<NetworkBlock> = blocks.iter().map(round).collect();
+    drop(blocks);
+    ranges(&rounded).into_iter().flat_map(|(start, end)| range_to_blocks(start, end)).collect()
+}
+
+#[derive(Default)]
+struct Node {
+    /// 子ノードの添字 (0 は子がないことを表す。0 は根なので子にはならない)
+    children: [u32; 2],
+    full: bool,
+}
+
+/// 2 分木に入れ、両方の子が埋まったノードを埋めてから埋まったノードを上から取り出す。入力のソートは不要
+pub fn trie(blocks: Vec<NetworkBlock>) -> Vec<NetworkBlock> {
+    let mut nodes = vec![Node::default()];
+    'blocks: for blk in blocks.iter().map(round) {
+        let mut i = 0;
+        for depth in 0..blk.prefix_len {
+            if nodes[i].full {
+                continue 'blocks;
+            }
+            let bit = (blk.network >> (31 - depth) & 1) as usize;
+            i = match nodes[i].children[bit] {
+                0 => {
+                    nodes.push(Node::default());
+                    let child = nodes.len() - 1;
+                    nodes[i].children[bit] = child as u32;
+                    child
+                }
+                child => child as usize,
+            };
+        }
+        // 配下のノードは以後たどらない
+        nodes[i] = Node { children: [0, 0], full: true };
+    }
+    fill(&mut nodes, 0);
+
+    let mut result = Vec::new();
+    collect(&nodes, 0, 0, 0, &mut result);
+    result
+}
+
+fn fill(nodes: &mut [Node], i: usize) -> bool {
+    if nodes[i].full {
+        return true;
+    }
+    let [left, right] = nodes[i].children;
+    let left = left != 0 && fill(nodes, left as usize);
+    let right = right != 0 && fill(nodes, right as usize);
+    nodes[i].full = left && right;
+    nodes[i].full
+}
+
+fn collect(nodes: &[Node], i: usize, network: u32, depth: u8, result: &mut Vec<NetworkBlock>) {
+    if nodes[i].full {
+        result.push(NetworkBlock::new(network, depth));
+        return;
+    }
+    for (bit, &child) in nodes[i].children.iter().enumerate() {
+        if child != 0 {
+            collect(nodes, child as usize, network | (bit as u32) << (31 - depth), depth + 1, result);
+        }
+    }
+}
+
+#[test]
+fn test_strategies_agree() {
+    let parse = |cidrs: &[&str]| cidrs.iter().map(|c| c.parse::<NetworkBlock>().unwrap()).collect::<Vec<_>>();
+    let blocks = parse(&["10.1.0.0/16", "1.0.2.0/23", "1.0.0.0/24", "1.0.3.5/32", "10.0.0.0/8", "1.0.1.0/24", "192.168.0.0/16"]);
+    let expected: Vec<String> = crate::aggregate_blocks(blocks.clone()).iter().map(|b| b.to_string()).collect();
+    assert_eq!(expected, ["1.0.0.0/22", "10.0.0.0/8", "192.168.0.0/16"]);
+    for strategy in [sweep, trie] {
+        assert_eq!(strategy(blocks.clone()).iter().map(|b| b.to_string()).collect::<Vec<_>>(), expected);
+    }
+}
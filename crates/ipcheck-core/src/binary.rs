@@ -0,0 +1,134 @@
+use std::net::Ipv4Addr;
+
+use crate::NetworkBlock;
+
+pub const MAGIC: &[u8; 8] = b"IPCKBIN\0";
+pub const VERSION: u32 = 1;
+const HEADER_LEN: usize = 16;
+
+/// ブロックを結合済みの [start, end] 範囲に変換する
+pub fn ranges(blocks: &[NetworkBlock]) -> Vec<(u32, u32)> {
+    let mut sorted: Vec<(u32, u32)> = blocks.iter().map(|b| (b.network, b.last())).collect();
+    sorted.sort_unstable();
+
+    let mut merged: Vec<(u32, u32)> = Vec::with_capacity(sorted.len());
+    for (start, end) in sorted {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// ソート・結合済みの範囲列 a から b に含まれる部分を取り除く
+pub fn subtract(a: &[(u32, u32)], b: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let mut result = Vec::new();
+    let mut j = 0;
+    for &(start, end) in a {
+        let mut current = start as u64;
+        let end = end as u64;
+        while j < b.len() && (b[j].1 as u64) < current {
+            j += 1;
+        }
+        let mut k = j;
+        while current <= end && k < b.len() && (b[k].0 as u64) <= end {
+            let (b_start, b_end) = (b[k].0 as u64, b[k].1 as u64);
+            if b_start > current {
+                result.push((current as u32, (b_start - 1) as u32));
+            }
+            current = current.max(b_end + 1);
+            k += 1;
+        }
+        if current <= end {
+            result.push((current as u32, end as u32));
+        }
+    }
+    result
+}
+
+/// ヘッダ (マジック 8 バイト, バージョン u32, 件数 u32) に続けて
+/// ソート済みの (start, end) を u32 リトルエンディアンで並べたバイト列を作る
+pub fn encode(blocks: &[NetworkBlock]) -> Vec<u8> {
+    let ranges = ranges(blocks);
+    let mut out = Vec::with_capacity(HEADER_LEN + ranges.len() * 8);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(ranges.len() as u32).to_le_bytes());
+    for (start, end) in ranges {
+        out.extend_from_slice(&start.to_le_bytes());
+        out.extend_from_slice(&end.to_le_bytes());
+    }
+    out
+}
+
+/// バイナリ形式のリストをそのまま (mmap した領域でも) 参照して判定する
+pub struct RangeSet<B: AsRef<[u8]>> {
+    data: B,
+    len: usize,
+}
+
+impl<B: AsRef<[u8]>> RangeSet<B> {
+    pub fn from_bytes(data: B) -> Result<Self, String> {
+        let bytes = data.as_ref();
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err("ipcheck のバイナリリストではありません".to_string());
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(format!("未対応のバージョンです: {}", version));
+        }
+        let len = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        if bytes.len() != HEADER_LEN + len * 8 {
+            return Err("ファイルサイズが件数と一致しません".to_string());
+        }
+        Ok(RangeSet { data, len })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn range(&self, index: usize) -> (u32, u32) {
+        let offset = HEADER_LEN + index * 8;
+        let bytes = &self.data.as_ref()[offset..offset + 8];
+        (
+            u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+            u32::from_le_bytes(bytes[4..].try_into().unwrap()),
+        )
+    }
+
+    /// 二分探索で IP を含む範囲を探す (O(log n))
+    pub fn find(&self, ip: Ipv4Addr) -> Option<(u32, u32)> {
+        let ip = u32::from(ip);
+        let (mut lo, mut hi) = (0, self.len);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.range(mid).0 <= ip { lo = mid + 1 } else { hi = mid }
+        }
+        if lo == 0 {
+            return None;
+        }
+        let range = self.range(lo - 1);
+        (ip <= range.1).then_some(range)
+    }
+}
+
+#[test]
+fn test_range_set_contains() {
+    let blocks: Vec<NetworkBlock> = ["10.0.0.0/8", "1.0.0.0/24", "1.0.1.0/24", "192.168.1.0/24"]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+    let set = RangeSet::from_bytes(encode(&blocks)).unwrap();
+    assert_eq!(set.len(), 3);
+    assert!(set.find(Ipv4Addr::new(1, 0, 1, 255)).is_some());
+    assert!(set.find(Ipv4Addr::new(10, 255, 0, 1)).is_some());
+    assert!(set.find(Ipv4Addr::new(1, 0, 2, 0)).is_none());
+    assert!(set.find(Ipv4Addr::new(0, 0, 0, 1)).is_none());
+    assert!(set.find(Ipv4Addr::new(192, 168, 2, 1)).is_none());
+}
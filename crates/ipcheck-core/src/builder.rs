@@ -0,0 +1,284 @@
+//! CLI と同じ手順で海外リストを作るための設定。
+//!
+//! ```no_run
+//! let ipcheck = ipcheck_core::IpcheckBuilder::new()
+//!     .db("GeoLite2-Country.mmdb")
+//!     .allow(["JP"])
+//!     .exclude_file("office.txt")
+//!     .build()?;
+//! assert!(!ipcheck.foreign.contains("133.0.0.1".parse()?));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use maxminddb::Reader;
+
+use crate::binary::{ranges, subtract};
+use crate::rules::{self, Rules};
+use crate::{Aggregator, CidrSet, NetworkBlock, range_to_blocks, walk};
+
+enum Input {
+    Db(String),
+    Networks(Vec<(NetworkBlock, Option<String>)>, u64),
+}
+
+#[derive(Default)]
+pub struct IpcheckBuilder {
+    input: Option<Input>,
+    allow: Option<Vec<String>>,
+    domestic_subdivisions: Vec<String>,
+    exclude: Vec<NetworkBlock>,
+    exclude_files: Vec<String>,
+    include: Vec<NetworkBlock>,
+    low_memory: bool,
+    ranges: Vec<NetworkBlock>,
+}
+
+/// 分類の結果
+pub struct Ipcheck {
+    /// データベースの全 IPv4 ネットワークと位置コード (low_memory では空)
+    pub networks: Vec<(NetworkBlock, Option<String>)>,
+    /// 元データベースのビルド日時 (UNIX 時刻)
+    pub build_epoch: u64,
+    /// 国内と判定したネットワークの数
+    pub domestic_networks: usize,
+    /// 海外と判定したネットワークの数 (集約前)
+    pub foreign_networks: usize,
+    /// 集約して例外を適用した海外リスト
+    pub foreign: CidrSet,
+    /// 分類に使った規則。照会や出力 (Metadata) にも同じものを渡す
+    pub rules: Rules,
+    pub timings: Timings,
+}
+
+/// build の工程別の所要時間。low_memory では走査しながら分類・集約するので walk にまとめる
+#[derive(Clone, Copy, Default)]
+pub struct Timings {
+    /// データベースの走査 (networks で渡した場合は 0)
+    pub walk: Duration,
+    pub classify: Duration,
+    pub sort: Duration,
+    /// 集約と例外の適用
+    pub aggregate: Duration,
+}
+
+impl IpcheckBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 読み込む .mmdb (Country または City)
+    pub fn db(mut self, path: impl Into<String>) -> Self {
+        self.input = Some(Input::Db(path.into()));
+        self
+    }
+
+    /// .mmdb 以外から読み込んだネットワークと位置コードを使う
+    pub fn networks(mut self, networks: Vec<(NetworkBlock, Option<String>)>, build_epoch: u64) -> Self {
+        self.input = Some(Input::Networks(networks, build_epoch));
+        self
+    }
+
+    /// 国内とみなす国 (省略時は JP)
+    pub fn allow<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, countries: I) -> Self {
+        self.allow = Some(countries.into_iter().map(|c| c.as_ref().trim().to_ascii_uppercase()).collect());
+        self
+    }
+
+    /// 国内のうちこれらの地域 ("13" や "JP-13") だけを国内とする (City データベースが必要)。
+    /// 地域は allow の国のものでなければならず、国コードを省けるのは国内の国が 1 つのときだけ
+    pub fn domestic_subdivisions<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, codes: I) -> Self {
+        self.domestic_subdivisions = codes.into_iter().map(|c| c.as_ref().to_string()).collect();
+        self
+    }
+
+    /// 国に関係なく海外リストから除くネットワーク
+    pub fn exclude(mut self, blocks: impl IntoIterator<Item = NetworkBlock>) -> Self {
+        self.exclude.extend(blocks);
+        self
+    }
+
+    /// 海外リストから除くネットワークの一覧 (テキストまたは .bin)
+    pub fn exclude_file(mut self, path: impl Into<String>) -> Self {
+        self.exclude_files.push(path.into());
+        self
+    }
+
+    /// データベースをメモリマップで読み、走査しながら集約する。ネットワークの一覧は持たないので Ipcheck::networks は空になる
+    pub fn low_memory(mut self, enabled: bool) -> Self {
+        self.low_memory = enabled;
+        self
+    }
+
+    /// 走査と出力をこれらの範囲に限る (省略時は IPv4 全体)
+    pub fn ranges(mut self, ranges: impl IntoIterator<Item = NetworkBlock>) -> Self {
+        self.ranges.extend(ranges);
+        self
+    }
+
+    /// 国に関係なく海外リストに加えるネットワーク (exclude より優先)
+    pub fn include(mut self, blocks: impl IntoIterator<Item = NetworkBlock>) -> Self {
+        self.include.extend(blocks);
+        self
+    }
+
+    pub fn build(self) -> Result<Ipcheck, String> {
+        let domestic_countries = self.allow.unwrap_or_else(|| Rules::default().domestic_countries);
+        let domestic_subdivisions =
+            self.domestic_subdivisions.iter().map(|s| rules::normalize_subdivision(s, &domestic_countries)).collect::<Result<Vec<_>, _>>()?;
+        let rules = Rules {
+            domestic_countries,
+            domestic_subdivisions,
+            ranges: self.ranges,
+        };
+        let scan = rules.scan_ranges();
+        let clip = |blocks: Vec<NetworkBlock>| blocks.into_iter().flat_map(|b| rules::clip(&scan, b)).collect::<Vec<_>>();
+        let mut timings = Timings::default();
+        let (networks, build_epoch) = match self.input.ok_or("データベースが指定されていません")? {
+            Input::Db(path) if self.low_memory => {
+                let start = Instant::now();
+                let (aggregated, build_epoch, domestic_networks, foreign_networks) = classify_streaming(&path, &rules)?;
+                let foreign = clip(with_exceptions(aggregated, self.exclude, &self.exclude_files, &self.include)?);
+                return Ok(Ipcheck {
+                    networks: Vec::new(),
+                    build_epoch,
+                    domestic_networks,
+                    foreign_networks,
+                    foreign: CidrSet::from_aggregated(foreign),
+                    rules,
+                    timings: Timings { walk: start.elapsed(), ..Timings::default() },
+                });
+            }
+            Input::Db(path) => {
+                let start = Instant::now();
+                let reader = Reader::open_readfile(&path).map_err(|e| format!("{}: {}", path, e))?;
+                let networks = walk(&reader, &rules)
+                    .map_err(|e| format!("{}: {}", path, e))?
+                    .flatten()
+                    .map(|item| (item.network, item.location))
+                    .collect();
+                timings.walk = start.elapsed();
+                (networks, reader.metadata.build_epoch)
+            }
+            Input::Networks(networks, build_epoch) if rules.ranges.is_empty() => (networks, build_epoch),
+            Input::Networks(networks, build_epoch) => {
+                let networks = networks
+                    .into_iter()
+                    .flat_map(|(block, location)| rules::clip(&scan, block).map(move |b| (b, location.clone())))
+                    .collect();
+                (networks, build_epoch)
+            }
+        };
+        if rules.uses_subdivisions() && !networks.iter().any(|(_, code): &(NetworkBlock, Option<String>)| code.as_deref().is_some_and(|c| rules::split_location(c).1.is_some())) {
+            return Err("データベースに地域コードがありません (国内の地域を絞るには City データベースが必要です)".to_string());
+        }
+
+        let start = Instant::now();
+        let mut foreign = HashSet::new();
+        let mut domestic_networks = 0;
+        for (block, location) in &networks {
+            if rules.is_foreign(location.as_deref()) {
+                foreign.insert(*block);
+            } else {
+                domestic_networks += 1;
+            }
+        }
+        let foreign_networks = foreign.len();
+        let mut blocks: Vec<NetworkBlock> = foreign.into_iter().collect();
+        timings.classify = start.elapsed();
+
+        let start = Instant::now();
+        blocks.sort_by_key(|b| (b.network, b.prefix_len));
+        timings.sort = start.elapsed();
+
+        let start = Instant::now();
+        let mut aggregator = Aggregator::new();
+        for blk in blocks {
+            aggregator.push(blk);
+        }
+        let foreign = clip(with_exceptions(aggregator.finish(), self.exclude, &self.exclude_files, &self.include)?);
+        timings.aggregate = start.elapsed();
+
+        Ok(Ipcheck {
+            networks,
+            build_epoch,
+            domestic_networks,
+            foreign_networks,
+            foreign: CidrSet::from_aggregated(foreign),
+            rules,
+            timings,
+        })
+    }
+}
+
+/// 集約で /24 に丸められても例外が確実に効くよう、集約の後に適用する
+fn with_exceptions(aggregated: Vec<NetworkBlock>, mut exclude: Vec<NetworkBlock>, exclude_files: &[String], include: &[NetworkBlock]) -> Result<Vec<NetworkBlock>, String> {
+    for path in exclude_files {
+        exclude.extend_from_slice(CidrSet::from_file(path)?.blocks());
+    }
+    if exclude.is_empty() && include.is_empty() {
+        return Ok(aggregated);
+    }
+    Ok(apply_exceptions(&aggregated, &exclude, include))
+}
+
+/// 走査はアドレス順なので、ソートも重複除去もせずにそのまま集約へ流す
+fn classify_streaming(path: &str, rules: &Rules) -> Result<(Vec<NetworkBlock>, u64, usize, usize), String> {
+    #[cfg(unix)]
+    let reader = Reader::from_source(crate::mmap::Mmap::open(path).map_err(|e| format!("{}: {}", path, e))?);
+    #[cfg(not(unix))]
+    let reader = Reader::open_readfile(path);
+    let reader = reader.map_err(|e| format!("{}: {}", path, e))?;
+
+    let mut aggregator = Aggregator::new();
+    let (mut domestic_networks, mut foreign_networks, mut has_subdivisions) = (0, 0, false);
+    for item in walk(&reader, rules).map_err(|e| format!("{}: {}", path, e))?.flatten() {
+        has_subdivisions |= item.location.as_deref().is_some_and(|c| rules::split_location(c).1.is_some());
+        if item.foreign {
+            foreign_networks += 1;
+            aggregator.push(item.network);
+        } else {
+            domestic_networks += 1;
+        }
+    }
+    if rules.uses_subdivisions() && !has_subdivisions {
+        return Err("データベースに地域コードがありません (国内の地域を絞るには City データベースが必要です)".to_string());
+    }
+    Ok((aggregator.finish(), reader.metadata.build_epoch, domestic_networks, foreign_networks))
+}
+
+/// 海外リストから allow の範囲を除き、block の範囲を加える。両方に含まれる範囲は block を優先する
+pub fn apply_exceptions(foreign: &[NetworkBlock], allow: &[NetworkBlock], block: &[NetworkBlock]) -> Vec<NetworkBlock> {
+    let mut blocks: Vec<NetworkBlock> = subtract(&ranges(foreign), &ranges(allow)).into_iter().flat_map(|(start, end)| range_to_blocks(start, end)).collect();
+    blocks.extend_from_slice(block);
+    ranges(&blocks).into_iter().flat_map(|(start, end)| range_to_blocks(start, end)).collect()
+}
+
+#[test]
+fn test_apply_exceptions() {
+    let parse = |cidrs: &[&str]| cidrs.iter().map(|c| c.parse::<NetworkBlock>().unwrap()).collect::<Vec<_>>();
+    let result = apply_exceptions(&parse(&["1.1.0.0/23"]), &parse(&["1.1.1.0/24", "133.0.0.0/16"]), &parse(&["8.8.8.0/24"]));
+    let cidrs: Vec<String> = result.iter().map(|b| b.to_string()).collect();
+    assert_eq!(cidrs, ["1.1.0.0/24", "8.8.8.0/24"]);
+
+    let networks = vec![
+        ("1.0.0.0/24".parse().unwrap(), Some("US".to_string())),
+        ("1.0.1.0/24".parse().unwrap(), Some("KR".to_string())),
+        ("133.0.0.0/16".parse().unwrap(), Some("JP".to_string())),
+        ("8.8.8.0/24".parse().unwrap(), None),
+    ];
+    let ipcheck = IpcheckBuilder::new()
+        .networks(networks, 0)
+        .allow(["jp", "kr"])
+        .exclude(parse(&["8.8.8.0/25"]))
+        .build()
+        .unwrap();
+    let cidrs: Vec<String> = ipcheck.foreign.blocks().iter().map(|b| b.to_string()).collect();
+    assert_eq!(cidrs, ["1.0.0.0/24", "8.8.8.128/25"]);
+    assert_eq!((ipcheck.domestic_networks, ipcheck.foreign_networks), (2, 2));
+    // 照会や出力にも同じ規則を渡せるよう、使った規則を返す
+    assert!(!ipcheck.rules.is_foreign(Some("KR")));
+    assert!(Rules::default().is_foreign(Some("KR")));
+}
@@ -0,0 +1,458 @@
+//! 国別 IP データベース (MaxMind DB 形式) から海外の IPv4 ネットワークを取り出し、CIDR にまとめるライブラリ。
+//!
+//! ```no_run
+//! let reader = maxminddb::Reader::open_readfile("GeoLite2-Country.mmdb")?;
+//! let set = ipcheck_core::classify(&reader, &ipcheck_core::rules::Rules::default())?;
+//! assert!(set.contains("8.8.8.8".parse()?));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+use ipnetwork::IpNetwork;
+use maxminddb::{MaxMindDBError, Reader, Within};
+use serde::Deserialize;
+
+pub mod aggregate;
+pub mod binary;
+pub mod builder;
+#[cfg(unix)]
+pub mod mmap;
+pub mod rules;
+
+pub use builder::{Ipcheck, IpcheckBuilder};
+
+use rules::Rules;
+
+/// GeoLite2 / GeoIP2 (Country, City) と DB-IP のレコード。DB-IP の一部の版は country.code やトップレベルの country_code に国コードを持つ
+#[derive(Deserialize)]
+pub struct CountryRecord {
+    pub country: Option<Country>,
+    pub country_code: Option<String>,
+    /// City データベースのみ。大きい区分から順に並ぶ
+    pub subdivisions: Option<Vec<Subdivision>>,
+}
+
+#[derive(Deserialize)]
+pub struct Country {
+    #[serde(alias = "code")]
+    pub iso_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Subdivision {
+    pub iso_code: Option<String>,
+}
+
+impl CountryRecord {
+    /// DB-IP は不明な国を ZZ とするので、国なしとして扱う
+    pub fn iso_code(self) -> Option<String> {
+        self.country.and_then(|c| c.iso_code).or(self.country_code).filter(|code| code != "ZZ")
+    }
+
+    /// 判定に使う位置コード。rules が地域を見る場合だけ、第 1 区分を付けて "JP-13" のようにする
+    pub fn location(mut self, rules: &Rules) -> Option<String> {
+        let subdivision = self.subdivisions.take().and_then(|s| s.into_iter().next()).and_then(|s| s.iso_code);
+        let country = self.iso_code()?;
+        match subdivision {
+            Some(subdivision) if rules.uses_subdivisions() => Some(format!("{}-{}", country, subdivision)),
+            _ => Some(country),
+        }
+    }
+}
+
+
+/// IPv4 の CIDR ブロック
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NetworkBlock {
+    /// ネットワークアドレス (ホスト部は常に 0)
+    pub network: u32,
+    pub prefix_len: u8,
+}
+
+impl NetworkBlock {
+    /// ip のホスト部を切り捨てたブロックを作る
+    pub fn new(ip: u32, prefix_len: u8) -> Self {
+        let mask = if prefix_len == 0 { 0 } else { !((1u32 << (32 - prefix_len)) - 1) };
+        let network = ip & mask;
+        NetworkBlock { network, prefix_len }
+    }
+
+    pub fn to_string(&self) -> String {
+        let ip = Ipv4Addr::from(self.network);
+        format!("{}/{}", ip, self.prefix_len)
+    }
+
+    /// other がこのブロックより長いプレフィックスで、かつ内側にあるか
+    pub fn contains(&self, other: &NetworkBlock) -> bool {
+        if self.prefix_len >= other.prefix_len {
+            return false;
+        }
+        let mask = if self.prefix_len == 0 { 0 } else { !((1u32 << (32 - self.prefix_len)) - 1) };
+        (self.network & mask) == (other.network & mask)
+    }
+    /// ブロックの最後のアドレス
+    pub fn last(&self) -> u32 {
+        let mask = if self.prefix_len == 0 { 0 } else { !((1u32 << (32 - self.prefix_len)) - 1) };
+        let last = (self.network & mask) + !mask;
+        last
+    }
+}
+
+impl FromStr for NetworkBlock {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ip, prefix) = match s.split_once('/') {
+            Some((ip, prefix)) => (ip, prefix),
+            None => (s, "32"),
+        };
+        let ip = Ipv4Addr::from_str(ip.trim()).map_err(|e| format!("{}: {}", s, e))?;
+        let prefix_len: u8 = prefix.trim().parse().map_err(|e| format!("{}: {}", s, e))?;
+        if prefix_len > 32 {
+            return Err(format!("{}: プレフィックス長が不正です", s));
+        }
+        Ok(NetworkBlock::new(ip_to_u32(ip), prefix_len))
+    }
+}
+
+pub fn ip_to_u32(ip: Ipv4Addr) -> u32 {
+    u32::from(ip)
+}
+
+fn mask(prefix: u8) -> u32 {
+    if prefix == 0 {
+        0
+    } else {
+        (!0u32) << (32 - prefix)
+    }
+}
+
+pub fn block_size(prefix: u8) -> u32 {
+    1u32 << (32 - prefix)
+}
+
+/// [start, end] の範囲を過不足なく覆う最小の CIDR 列に分解する
+pub fn range_to_blocks(start: u32, end: u32) -> Vec<NetworkBlock> {
+    let mut blocks = Vec::new();
+    let mut current = start as u64;
+    let end = end as u64;
+    while current <= end {
+        let mut prefix = if current == 0 { 0 } else { 32 - current.trailing_zeros().min(32) as u8 };
+        while current + (1u64 << (32 - prefix)) - 1 > end {
+            prefix += 1;
+        }
+        blocks.push(NetworkBlock::new(current as u32, prefix));
+        current += 1u64 << (32 - prefix);
+    }
+    blocks
+}
+
+fn try_merge(a: &NetworkBlock, b: &NetworkBlock) -> Option<NetworkBlock> {
+    if a.network % 256 == 0 && a.prefix_len > 24 {
+        Some(NetworkBlock::new(a.network, 24))
+    } else if b.network % 256 != 0 && b.prefix_len > 24 {
+        Some(*a)
+    } else if a.prefix_len == b.prefix_len && a.last() + 1 == b.network {
+        let range_size = block_size(a.prefix_len) + block_size(b.prefix_len);
+        let prefix = 32 - range_size.trailing_zeros() as u8;
+        Some(NetworkBlock::new(a.network, prefix))
+    } else {
+        None
+    }
+}
+
+//#[test]
+fn try_marge_test(){
+    let block1 = NetworkBlock::new(ip_to_u32(Ipv4Addr::from_str("1.0.1.0").unwrap()), 24);
+    let block2 = NetworkBlock::new(ip_to_u32(Ipv4Addr::from_str("1.0.2.0").unwrap()), 23);
+    let result = try_merge(&block1, &block2);
+    assert!(result.is_some());
+}
+
+
+/// ブロックをソートし、包含されるものを除いて隣り合うものを結合する。/24 より細かいブロックは /24 に丸める
+pub fn aggregate_blocks(blocks: Vec<NetworkBlock>) -> Vec<NetworkBlock> {
+    let mut sorted_blocks = blocks;
+    sorted_blocks.sort_by(|a, b| {
+        a.network.cmp(&b.network).then(a.prefix_len.cmp(&b.prefix_len))
+    });
+
+    let mut aggregator = Aggregator::new();
+    for blk in sorted_blocks {
+        aggregator.push(blk);
+    }
+    aggregator.finish()
+}
+
+/// ブロックの和集合を過不足なく覆う最小の CIDR 列にする (アドレス順)。aggregate_blocks と違い /24 に丸めない
+pub fn union_blocks(blocks: &[NetworkBlock]) -> Vec<NetworkBlock> {
+    binary::ranges(blocks).into_iter().flat_map(|(start, end)| range_to_blocks(start, end)).collect()
+}
+
+/// アドレス順 (同じアドレスならプレフィックスの短い順) に届くブロックを 1 つずつ集約する。
+/// 結果は aggregate_blocks と同じで、入力全体を溜めておく必要がない
+#[derive(Default)]
+pub struct Aggregator {
+    result: Vec<NetworkBlock>,
+}
+
+impl Aggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, blk: NetworkBlock) {
+        if self.result.last().is_some_and(|top| top.contains(&blk)) {
+            return;
+        }
+        self.result.push(blk);
+        while self.result.len() >= 2 {
+            let len = self.result.len();
+            let Some(parent) = try_merge(&self.result[len - 2], &self.result[len - 1]) else { break };
+            self.result.truncate(len - 2);
+            if self.result.last().is_some_and(|prev| prev.contains(&parent)) {
+                continue;
+            }
+            self.result.push(parent);
+        }
+    }
+
+    /// ここまでに集約したブロックの数
+    pub fn len(&self) -> usize {
+        self.result.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.result.is_empty()
+    }
+
+    pub fn finish(self) -> Vec<NetworkBlock> {
+        self.result
+    }
+}
+
+/// ip を含むネットワークと位置コード (rules に従い地域付きになる)。データベースにない場合は None
+pub fn lookup_network<S: AsRef<[u8]>>(reader: &Reader<S>, rules: &Rules, ip: Ipv4Addr) -> Result<Option<(NetworkBlock, Option<String>)>, MaxMindDBError> {
+    match reader.lookup_prefix::<CountryRecord>(std::net::IpAddr::V4(ip)) {
+        Ok((record, prefix_len)) => {
+            let iso_code = record.location(rules);
+            Ok(Some((NetworkBlock::new(ip_to_u32(ip), prefix_len as u8), iso_code)))
+        }
+        Err(MaxMindDBError::AddressNotFoundError(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// 走査で得た 1 ネットワーク分の判定結果
+pub struct Classified {
+    pub network: NetworkBlock,
+    /// 位置コード (rules に従い地域付きになる)。国のないネットワークは None
+    pub location: Option<String>,
+    pub foreign: bool,
+}
+
+/// データベースをアドレス順に走査しながら判定結果を返すイテレータ。
+/// 集約を待たずに独自の出力先へ流したいときに使う。読めないレコードは Err として返す
+///
+/// ```no_run
+/// let reader = maxminddb::Reader::open_readfile("GeoLite2-Country.mmdb")?;
+/// let rules = ipcheck_core::rules::Rules::default();
+/// for result in ipcheck_core::walk(&reader, &rules)? {
+///     let item = result?;
+///     if item.foreign {
+///         println!("{} {}", item.network.to_string(), item.location.as_deref().unwrap_or("-"));
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Walk<'a, S: AsRef<[u8]>> {
+    reader: &'a Reader<S>,
+    iter: Within<'a, CountryRecord, S>,
+    rules: &'a Rules,
+    /// 範囲全体を 1 つのレコードが覆っている場合のその範囲
+    covering: Option<Classified>,
+    /// まだ走査していない範囲 (逆順)
+    pending: Vec<NetworkBlock>,
+}
+
+type Start<'a, S> = (Within<'a, CountryRecord, S>, Option<Classified>);
+
+/// range の走査を始める。within は範囲より広いネットワークを返さないので、範囲全体が 1 つのレコードに含まれる場合は lookup で拾う
+fn start<'a, S: AsRef<[u8]>>(reader: &'a Reader<S>, rules: &Rules, range: NetworkBlock) -> Result<Start<'a, S>, MaxMindDBError> {
+    let network = ipnetwork::Ipv4Network::new(Ipv4Addr::from(range.network), range.prefix_len).expect("プレフィックス長は 32 以下");
+    let iter = reader.within(IpNetwork::V4(network))?;
+    let covering = match reader.lookup_prefix::<CountryRecord>(IpAddr::V4(Ipv4Addr::from(range.network))) {
+        Ok((record, prefix_len)) if prefix_len <= range.prefix_len as usize => {
+            let location = record.location(rules);
+            Some(Classified { network: range, foreign: rules.is_foreign(location.as_deref()), location })
+        }
+        Ok(_) | Err(MaxMindDBError::AddressNotFoundError(_)) => None,
+        Err(e) => return Err(e),
+    };
+    Ok((iter, covering))
+}
+
+impl<S: AsRef<[u8]>> Iterator for Walk<'_, S> {
+    type Item = Result<Classified, MaxMindDBError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(covering) = self.covering.take() {
+                return Some(Ok(covering));
+            }
+            let item = match self.iter.next() {
+                Some(Ok(item)) => item,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    let range = self.pending.pop()?;
+                    match start(self.reader, self.rules, range) {
+                        Ok((iter, covering)) => (self.iter, self.covering) = (iter, covering),
+                        Err(e) => return Some(Err(e)),
+                    }
+                    continue;
+                }
+            };
+            let IpAddr::V4(ip) = item.ip_net.ip() else { continue };
+            let location = item.info.location(self.rules);
+            return Some(Ok(Classified {
+                network: NetworkBlock::new(ip_to_u32(ip), item.ip_net.prefix()),
+                foreign: self.rules.is_foreign(location.as_deref()),
+                location,
+            }));
+        }
+    }
+}
+
+/// データベースの IPv4 ネットワーク (rules.ranges があればその範囲だけ) を走査するイテレータを作る
+pub fn walk<'a, S: AsRef<[u8]>>(reader: &'a Reader<S>, rules: &'a Rules) -> Result<Walk<'a, S>, MaxMindDBError> {
+    let mut pending = rules.scan_ranges();
+    pending.reverse();
+    let range = pending.pop().expect("走査範囲は空にならない");
+    let (iter, covering) = start(reader, rules, range)?;
+    Ok(Walk { reader, iter, rules, covering, pending })
+}
+
+/// データベースの全 IPv4 ネットワークと位置コード (rules に従い地域付きになる)。読めないレコードは飛ばす
+pub fn networks<S: AsRef<[u8]>>(reader: &Reader<S>, rules: &Rules) -> Result<Vec<(NetworkBlock, Option<String>)>, MaxMindDBError> {
+    Ok(walk(reader, rules)?.flatten().map(|item| (item.network, item.location)).collect())
+}
+
+/// データベースを走査し、rules で海外と判定したネットワークを集約した CidrSet を返す
+pub fn classify<S: AsRef<[u8]>>(reader: &Reader<S>, rules: &Rules) -> Result<CidrSet, MaxMindDBError> {
+    let foreign = walk(reader, rules)?.flatten().filter(|item| item.foreign).map(|item| item.network).collect();
+    Ok(CidrSet::from_aggregated(aggregate_blocks(foreign)))
+}
+
+/// 集約済みの CIDR の集合。IP の所属判定は二分探索で行う
+pub struct CidrSet {
+    blocks: Vec<NetworkBlock>,
+    ranges: Vec<(u32, u32)>,
+}
+
+impl CidrSet {
+    /// ブロックの和集合を集合にする。/24 より細かいブロックもそのまま残す
+    pub fn from_blocks(blocks: Vec<NetworkBlock>) -> Self {
+        let blocks = union_blocks(&blocks);
+        let ranges = binary::ranges(&blocks);
+        CidrSet { blocks, ranges }
+    }
+
+    /// 集約済みのブロックをそのまま集合にする。例外の適用後のように /24 より細かいブロックを残したいときに使う
+    pub fn from_aggregated(mut blocks: Vec<NetworkBlock>) -> Self {
+        blocks.sort_by_key(|b| (b.network, b.prefix_len));
+        let ranges = binary::ranges(&blocks);
+        CidrSet { blocks, ranges }
+    }
+
+    /// バイナリ形式 (.bin) か、1 行 1 CIDR のテキスト (# 以降はコメント) を読み込む
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        if bytes.starts_with(binary::MAGIC) {
+            let set = binary::RangeSet::from_bytes(bytes)?;
+            let blocks = (0..set.len()).flat_map(|i| {
+                let (start, end) = set.range(i);
+                range_to_blocks(start, end)
+            });
+            return Ok(CidrSet::from_blocks(blocks.collect()));
+        }
+        let text = String::from_utf8(bytes).map_err(|e| format!("{}: {}", path, e))?;
+        let blocks = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| line.parse::<NetworkBlock>())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CidrSet::from_blocks(blocks))
+    }
+
+    /// 集約済みのブロック (アドレス順)
+    pub fn blocks(&self) -> &[NetworkBlock] {
+        &self.blocks
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// ip を含む [start, end] 範囲 (隣接するブロックは 1 つの範囲にまとまる)
+    pub fn find(&self, ip: Ipv4Addr) -> Option<(u32, u32)> {
+        let ip = ip_to_u32(ip);
+        let index = self.ranges.partition_point(|&(start, _)| start <= ip);
+        let range = *self.ranges.get(index.checked_sub(1)?)?;
+        (ip <= range.1).then_some(range)
+    }
+
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        self.find(ip).is_some()
+    }
+
+    /// ip を含むブロック
+    pub fn find_block(&self, ip: Ipv4Addr) -> Option<NetworkBlock> {
+        self.find(ip)?;
+        let ip = ip_to_u32(ip);
+        let index = self.blocks.partition_point(|b| b.network <= ip);
+        self.blocks[..index].iter().rev().find(|b| ip <= b.last()).copied()
+    }
+
+    /// binary 形式 (.bin) のバイト列
+    pub fn to_bytes(&self) -> Vec<u8> {
+        binary::encode(&self.blocks)
+    }
+}
+
+#[test]
+fn test_cidr_set() {
+    let blocks: Vec<NetworkBlock> = ["1.0.0.0/24", "1.0.1.0/24", "1.0.0.128/25", "10.0.0.0/8"].iter().map(|s| s.parse().unwrap()).collect();
+    let set = CidrSet::from_blocks(blocks);
+    assert_eq!(set.blocks().iter().map(|b| b.to_string()).collect::<Vec<_>>(), ["1.0.0.0/23", "10.0.0.0/8"]);
+    assert!(set.contains(Ipv4Addr::new(1, 0, 1, 255)));
+    assert!(set.contains(Ipv4Addr::new(10, 1, 2, 3)));
+    assert!(!set.contains(Ipv4Addr::new(1, 0, 2, 0)));
+    assert!(!set.contains(Ipv4Addr::new(0, 0, 0, 1)));
+
+    let set = CidrSet::from_blocks(vec!["1.2.3.0/32".parse().unwrap(), "5.5.5.5/32".parse().unwrap(), "5.5.5.4/32".parse().unwrap()]);
+    assert_eq!(set.blocks().iter().map(|b| b.to_string()).collect::<Vec<_>>(), ["1.2.3.0/32", "5.5.5.4/31"]);
+    assert!(!set.contains(Ipv4Addr::new(1, 2, 3, 77)));
+    assert_eq!(set.find_block(Ipv4Addr::new(5, 5, 5, 5)).map(|b| b.to_string()).as_deref(), Some("5.5.5.4/31"));
+}
+
+#[test]
+fn test_aggregator_streaming() {
+    let blocks: Vec<NetworkBlock> = ["1.0.0.0/24", "1.0.1.0/24", "1.0.2.0/23", "1.0.2.128/25", "8.8.8.0/24", "8.8.9.1/32"]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+    let mut aggregator = Aggregator::new();
+    for block in &blocks {
+        aggregator.push(*block);
+    }
+    assert_eq!(aggregator.len(), 2);
+    let cidrs = |blocks: Vec<NetworkBlock>| blocks.iter().map(|b| b.to_string()).collect::<Vec<_>>();
+    assert_eq!(cidrs(aggregator.finish()), cidrs(aggregate_blocks(blocks)));
+}
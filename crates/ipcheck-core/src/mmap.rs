@@ -0,0 +1,46 @@
+//! 読み取り専用のメモリマップ。ページはカーネルが必要なときに読み込み、メモリが足りなければ捨てられるので、
+//! データベース全体をヒープに載せずに済む
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+pub struct Mmap {
+    ptr: *const u8,
+    len: usize,
+}
+
+// マップは読み取り専用で、Drop まで解放しない
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = usize::try_from(file.metadata()?.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "ファイルが大きすぎます"))?;
+        if len == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "空のファイルです"));
+        }
+        // SAFETY: 読み取り専用・プライベートでマップし、ファイルを閉じてもマップは有効なまま残る
+        let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap { ptr: ptr as *const u8, len })
+    }
+}
+
+impl AsRef<[u8]> for Mmap {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: ptr から len バイトは Drop までマップされている
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: open で得たマップをちょうど 1 回解放する
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
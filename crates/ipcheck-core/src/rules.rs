@@ -0,0 +1,120 @@
+use crate::binary::ranges;
+use crate::{NetworkBlock, range_to_blocks};
+
+/// 既定で国内とみなす国
+pub const DOMESTIC_COUNTRY: &str = "JP";
+
+/// 海外・国内を判定する規則。分類・照会・出力の各関数に同じものを渡す
+#[derive(Clone)]
+pub struct Rules {
+    /// 国内とみなす国 (ISO 3166-1 alpha-2)
+    pub domestic_countries: Vec<String>,
+    /// 空でなければ、国内の国のうちこれらの地域 (ISO 3166-2, 例: JP-13) だけを国内とする
+    pub domestic_subdivisions: Vec<String>,
+    /// 空でなければ、これらの範囲だけを走査して出力する
+    pub ranges: Vec<NetworkBlock>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules { domestic_countries: vec![DOMESTIC_COUNTRY.to_string()], domestic_subdivisions: Vec::new(), ranges: Vec::new() }
+    }
+}
+
+/// block のうち scan_ranges の範囲に入る部分。CIDR 同士はどちらかが他方を含むときだけ重なる
+pub fn clip(scan: &[NetworkBlock], block: NetworkBlock) -> impl Iterator<Item = NetworkBlock> + '_ {
+    scan.iter().filter_map(move |range| {
+        if *range == block || range.contains(&block) {
+            Some(block)
+        } else if block.contains(range) {
+            Some(*range)
+        } else {
+            None
+        }
+    })
+}
+
+/// 位置コード ("JP" または地域付きの "JP-13") を国コードと地域コードに分ける
+pub fn split_location(location: &str) -> (&str, Option<&str>) {
+    match location.split_once('-') {
+        Some((country, _)) => (country, Some(location)),
+        None => (location, None),
+    }
+}
+
+/// "13" や "jp-13" を "JP-13" にそろえる。国内とみなす国 (countries) の地域でなければエラー。
+/// 国コードを省けるのは国内の国が 1 つのときだけ
+pub fn normalize_subdivision(code: &str, countries: &[String]) -> Result<String, String> {
+    let example = format!("{}-13", countries.first().map_or(DOMESTIC_COUNTRY, String::as_str));
+    let code = code.trim().to_ascii_uppercase();
+    let code = match countries {
+        _ if code.contains('-') => code,
+        [country] => format!("{}-{}", country, code),
+        _ => return Err(format!("国内の国が複数あるときは国コード付きの地域コード (例: {}) を指定してください: {}", example, code)),
+    };
+    match split_location(&code) {
+        (country, Some(_)) if countries.iter().any(|c| c == country) && code.len() > country.len() + 1 => Ok(code),
+        _ => Err(format!("国内とみなす国 ({}) の地域コード (例: {}) を指定してください: {}", countries.join(","), example, code)),
+    }
+}
+
+impl Rules {
+    /// 地域まで見て判定するか (データベースから地域コードを読む必要があるか)
+    pub fn uses_subdivisions(&self) -> bool {
+        !self.domestic_subdivisions.is_empty()
+    }
+
+    /// 走査する範囲を、重なりを除いてアドレス順に返す (ranges が空なら IPv4 全体)
+    pub fn scan_ranges(&self) -> Vec<NetworkBlock> {
+        if self.ranges.is_empty() {
+            return vec![NetworkBlock::new(0, 0)];
+        }
+        ranges(&self.ranges).into_iter().flat_map(|(start, end)| range_to_blocks(start, end)).collect()
+    }
+
+    pub fn is_foreign(&self, location: Option<&str>) -> bool {
+        let Some(location) = location else { return true };
+        let (country, subdivision) = split_location(location);
+        if !self.domestic_countries.iter().any(|c| c == country) {
+            return true;
+        }
+        if !self.uses_subdivisions() {
+            return false;
+        }
+        !subdivision.is_some_and(|s| self.domestic_subdivisions.iter().any(|d| d == s))
+    }
+}
+
+#[test]
+fn test_subdivision_rules() {
+    let jp = Rules::default().domestic_countries;
+    let rules = Rules { domestic_subdivisions: vec![normalize_subdivision("13", &jp).unwrap(), normalize_subdivision("jp-27", &jp).unwrap()], ..Rules::default() };
+    assert!(!rules.is_foreign(Some("JP-13")));
+    assert!(!rules.is_foreign(Some("JP-27")));
+    assert!(rules.is_foreign(Some("JP-01")));
+    // 地域の分からない国内ネットワークは、地域を絞っているときは海外扱い
+    assert!(rules.is_foreign(Some("JP")));
+    assert!(rules.is_foreign(Some("US")));
+    assert!(!Rules::default().is_foreign(Some("JP")));
+    assert!(normalize_subdivision("US-CA", &jp).is_err());
+    let kr = vec!["KR".to_string()];
+    assert_eq!(normalize_subdivision("kr-11", &kr).unwrap(), "KR-11");
+    assert_eq!(normalize_subdivision("11", &kr).unwrap(), "KR-11");
+    assert!(normalize_subdivision("JP-13", &kr).is_err());
+    let both = vec!["JP".to_string(), "KR".to_string()];
+    assert_eq!(normalize_subdivision("KR-11", &both).unwrap(), "KR-11");
+    assert!(normalize_subdivision("13", &both).is_err());
+    let rules = Rules { domestic_countries: vec!["JP".to_string(), "KR".to_string()], ..Rules::default() };
+    assert!(!rules.is_foreign(Some("KR")));
+    assert!(rules.is_foreign(Some("CN")));
+
+    let parse = |cidrs: &[&str]| cidrs.iter().map(|c| c.parse::<NetworkBlock>().unwrap()).collect::<Vec<_>>();
+    let rules = Rules { ranges: parse(&["150.0.0.0/8", "150.1.0.0/16", "8.8.0.0/16"]), ..Rules::default() };
+    let scan = rules.scan_ranges();
+    assert_eq!(scan.iter().map(|b| b.to_string()).collect::<Vec<_>>(), ["8.8.0.0/16", "150.0.0.0/8"]);
+    let clipped = |cidr: &str| clip(&scan, cidr.parse().unwrap()).map(|b| b.to_string()).collect::<Vec<_>>();
+    assert_eq!(clipped("8.0.0.0/9"), ["8.8.0.0/16"]);
+    assert_eq!(clipped("150.2.0.0/16"), ["150.2.0.0/16"]);
+    assert!(clipped("1.0.0.0/24").is_empty());
+    assert_eq!(clip(&Rules::default().scan_ranges(), "1.0.0.0/24".parse().unwrap()).count(), 1);
+}
@@ -0,0 +1,29 @@
+/* ipcheck が生成したリスト (.bin またはテキスト) を C から判定に使うための宣言 */
+#ifndef IPCHECK_H
+#define IPCHECK_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct IpcheckSet IpcheckSet;
+
+/* リストを読み込む。読めない場合は NULL */
+IpcheckSet *ipcheck_set_from_file(const char *path);
+
+/* ip はホストバイトオーダー (ntohl 済み)。含まれていれば 1、いなければ 0、set が NULL なら -1 */
+int ipcheck_set_contains(const IpcheckSet *set, uint32_t ip);
+
+/* 集約後の CIDR の数 */
+size_t ipcheck_set_len(const IpcheckSet *set);
+
+void ipcheck_set_free(IpcheckSet *set);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,87 @@
+//! 生成済みのリストを C から読み込んで判定するための C ABI。宣言は include/ipcheck.h にある
+
+use std::ffi::{CStr, c_char, c_int};
+use std::net::Ipv4Addr;
+
+use ipcheck_core::CidrSet;
+
+/// C 側からは中身の見えないハンドル
+pub struct IpcheckSet(CidrSet);
+
+/// path のリストを読み込む。読めない場合は NULL を返す
+///
+/// # Safety
+/// path は NUL 終端の文字列であること
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ipcheck_set_from_file(path: *const c_char) -> *mut IpcheckSet {
+    if path.is_null() {
+        return std::ptr::null_mut();
+    }
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+        return std::ptr::null_mut();
+    };
+    match CidrSet::from_file(path) {
+        Ok(set) => Box::into_raw(Box::new(IpcheckSet(set))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// ip (ホストバイトオーダーの IPv4 アドレス) が含まれていれば 1、含まれていなければ 0、set が NULL なら -1
+///
+/// # Safety
+/// set は ipcheck_set_from_file が返し、まだ解放していないものであること
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ipcheck_set_contains(set: *const IpcheckSet, ip: u32) -> c_int {
+    match unsafe { set.as_ref() } {
+        Some(set) => set.0.contains(Ipv4Addr::from(ip)) as c_int,
+        None => -1,
+    }
+}
+
+/// 集約後の CIDR の数
+///
+/// # Safety
+/// ipcheck_set_contains と同じ
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ipcheck_set_len(set: *const IpcheckSet) -> usize {
+    unsafe { set.as_ref() }.map_or(0, |set| set.0.len())
+}
+
+/// # Safety
+/// set は ipcheck_set_from_file が返したもので、解放は 1 度だけ行うこと (NULL は何もしない)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ipcheck_set_free(set: *mut IpcheckSet) {
+    if !set.is_null() {
+        drop(unsafe { Box::from_raw(set) });
+    }
+}
+
+#[test]
+fn test_set_from_file() {
+    let path = std::env::temp_dir().join(format!("ipcheck-ffi-{}.txt", std::process::id()));
+    std::fs::write(&path, "# foreign\n1.0.0.0/24\n1.0.1.0/24\n10.0.0.0/8\n").unwrap();
+    let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+    unsafe {
+        let set = ipcheck_set_from_file(c_path.as_ptr());
+        assert!(!set.is_null());
+        assert_eq!(ipcheck_set_len(set), 2);
+        assert_eq!(ipcheck_set_contains(set, u32::from(Ipv4Addr::new(1, 0, 1, 1))), 1);
+        assert_eq!(ipcheck_set_contains(set, u32::from(Ipv4Addr::new(1, 0, 2, 1))), 0);
+        assert_eq!(ipcheck_set_contains(std::ptr::null(), 0), -1);
+        ipcheck_set_free(set);
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    // /24 より細かいエントリも丸めたり落としたりしない
+    std::fs::write(&path, "1.2.3.0/32\n5.5.5.5/32\n10.0.0.128/25\n").unwrap();
+    unsafe {
+        let set = ipcheck_set_from_file(c_path.as_ptr());
+        assert_eq!(ipcheck_set_len(set), 3);
+        assert_eq!(ipcheck_set_contains(set, u32::from(Ipv4Addr::new(5, 5, 5, 5))), 1);
+        assert_eq!(ipcheck_set_contains(set, u32::from(Ipv4Addr::new(10, 0, 0, 200))), 1);
+        assert_eq!(ipcheck_set_contains(set, u32::from(Ipv4Addr::new(10, 0, 0, 127))), 0);
+        assert_eq!(ipcheck_set_contains(set, u32::from(Ipv4Addr::new(1, 2, 3, 77))), 0);
+        ipcheck_set_free(set);
+    }
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// ipcheck の海外リストに含まれる送信元からの IPv4 パケットを XDP で破棄する参考実装。
+//
+// ビルドと読み込み:
+//   clang -O2 -g -target bpf -c xdp_foreign.c -o xdp_foreign.o
+//   bpftool prog load xdp_foreign.o /sys/fs/bpf/ipcheck/xdp pinmaps /sys/fs/bpf/ipcheck
+//   bpftool net attach xdp pinned /sys/fs/bpf/ipcheck/xdp dev eth0
+//
+// マップは /sys/fs/bpf/ipcheck/foreign にピン留めされるので、ipcheck --apply bpf (既定の --bpf-map)
+// か、--format bpf の出力を bpftool batch file で読み込んで中身を入れる。
+// VLAN タグ付きのフレームと IPv6 は判定せずに通す。
+
+#include <linux/bpf.h>
+#include <linux/if_ether.h>
+#include <linux/ip.h>
+#include <bpf/bpf_helpers.h>
+#include <bpf/bpf_endian.h>
+
+struct lpm_key {
+	__u32 prefixlen;
+	__u32 addr; // ネットワークバイト順
+};
+
+struct {
+	__uint(type, BPF_MAP_TYPE_LPM_TRIE);
+	__type(key, struct lpm_key);
+	__type(value, __u8);
+	__uint(max_entries, 1048576);
+	__uint(map_flags, BPF_F_NO_PREALLOC);
+} foreign SEC(".maps");
+
+SEC("xdp")
+int xdp_foreign(struct xdp_md *ctx)
+{
+	void *data = (void *)(long)ctx->data;
+	void *data_end = (void *)(long)ctx->data_end;
+
+	struct ethhdr *eth = data;
+	if ((void *)(eth + 1) > data_end || eth->h_proto != bpf_htons(ETH_P_IP))
+		return XDP_PASS;
+
+	struct iphdr *ip = (void *)(eth + 1);
+	if ((void *)(ip + 1) > data_end)
+		return XDP_PASS;
+
+	struct lpm_key key = { .prefixlen = 32, .addr = ip->saddr };
+	if (bpf_map_lookup_elem(&foreign, &key))
+		return XDP_DROP;
+	return XDP_PASS;
+}
+
+char LICENSE[] SEC("license") = "GPL";
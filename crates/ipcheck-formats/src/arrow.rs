@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, BooleanArray, Int32Array, Int64Array, RecordBatch, StringArray};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema};
+
+use ipcheck_core::rules::Rules;
+
+use crate::NetworkBlock;
+
+/// ネットワーク単位の分類結果を Arrow IPC (Feather v2) 形式で書き出す
+pub fn write(networks: &[(NetworkBlock, Option<String>)], rules: &Rules, out: &mut dyn std::io::Write) -> Result<(), Box<dyn std::error::Error>> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("network", DataType::Utf8, false),
+        Field::new("prefix_len", DataType::Int32, false),
+        Field::new("country", DataType::Utf8, true),
+        Field::new("is_foreign", DataType::Boolean, false),
+        Field::new("address_count", DataType::Int64, false),
+    ]));
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(networks.iter().map(|(block, _)| block.to_string()))),
+        Arc::new(Int32Array::from_iter_values(networks.iter().map(|(block, _)| block.prefix_len as i32))),
+        Arc::new(networks.iter().map(|(_, c)| c.as_deref()).collect::<StringArray>()),
+        Arc::new(networks.iter().map(|(_, c)| Some(rules.is_foreign(c.as_deref()))).collect::<BooleanArray>()),
+        Arc::new(Int64Array::from_iter_values(networks.iter().map(|(block, _)| 1i64 << (32 - block.prefix_len)))),
+    ];
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    let mut writer = FileWriter::try_new(out, &schema)?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    Ok(())
+}
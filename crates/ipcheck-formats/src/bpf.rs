@@ -0,0 +1,55 @@
+use std::fmt::Write;
+
+use crate::NetworkBlock;
+
+/// 海外リストで送信元を破棄する XDP プログラムの参考実装 (ipcheck xdp-program で出力する)
+pub const XDP_PROGRAM: &str = include_str!("../bpf/xdp_foreign.c");
+
+/// LPM trie のキー { u32 prefixlen; u8 addr[4]; } を bpftool の "hex" 形式で書く。
+/// prefixlen はホストのバイト順なので、XDP を使う x86_64 / arm64 に合わせてリトルエンディアンにする
+pub fn key_hex(block: &NetworkBlock) -> String {
+    let mut key = (block.prefix_len as u32).to_le_bytes().to_vec();
+    key.extend(block.network.to_be_bytes());
+    key.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// key_hex の逆。bpftool -j map dump の "key" (["0x18", "0x00", ...]) から読む
+pub fn parse_key(bytes: &[u8]) -> Option<NetworkBlock> {
+    let [p0, p1, p2, p3, a, b, c, d] = bytes else { return None };
+    let prefix_len = u32::from_le_bytes([*p0, *p1, *p2, *p3]);
+    (prefix_len <= 32).then(|| NetworkBlock::new(u32::from_be_bytes([*a, *b, *c, *d]), prefix_len as u8))
+}
+
+fn commands(out: &mut String, verb: &str, map: &str, blocks: &[NetworkBlock]) {
+    for block in blocks {
+        let value = if verb == "update" { " value hex 01" } else { "" };
+        let _ = writeln!(out, "map {} pinned {} key hex {}{}", verb, map, key_hex(block), value);
+    }
+}
+
+/// `bpftool batch file` 用の差分。追加してから削除し、ブロックが一時的に外れる時間をなくす
+pub fn delta(map: &str, added: &[NetworkBlock], removed: &[NetworkBlock]) -> String {
+    let mut out = String::new();
+    commands(&mut out, "update", map, added);
+    commands(&mut out, "delete", map, removed);
+    out
+}
+
+/// `bpftool batch file` 用に全エントリを書き込む。LPM trie は flush できないので、
+/// マップに残っている古いエントリは --apply bpf か delta で消す
+pub fn full(map: &str, blocks: &[NetworkBlock]) -> String {
+    delta(map, blocks, &[])
+}
+
+#[test]
+fn test_bpf_key() {
+    let block: NetworkBlock = "1.2.3.0/24".parse().unwrap();
+    assert_eq!(key_hex(&block), "18 00 00 00 01 02 03 00");
+    assert!(parse_key(&[0x18, 0, 0, 0, 1, 2, 3, 0]) == Some(block));
+    assert!(parse_key(&[0x21, 0, 0, 0, 1, 2, 3, 0]).is_none());
+    assert_eq!(
+        delta("/sys/fs/bpf/ipcheck/foreign", &[block], &[block]),
+        "map update pinned /sys/fs/bpf/ipcheck/foreign key hex 18 00 00 00 01 02 03 00 value hex 01\n\
+         map delete pinned /sys/fs/bpf/ipcheck/foreign key hex 18 00 00 00 01 02 03 00\n"
+    );
+}
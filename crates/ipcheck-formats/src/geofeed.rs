@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use ipcheck_core::rules::{Rules, split_location};
+use ipcheck_core::union_blocks;
+
+use super::format_epoch;
+use crate::NetworkBlock;
+
+/// 国内として許可しているネットワークの RFC 8805 geofeed。
+/// 国コードはネットワークごとの実際の値で、--domestic-subdivision 指定時は地域 (ISO 3166-2) も書く。都市・郵便番号は空にする
+pub fn render(networks: &[(NetworkBlock, Option<String>)], rules: &Rules, build_epoch: u64) -> String {
+    let mut locations: BTreeMap<&str, Vec<NetworkBlock>> = BTreeMap::new();
+    for (block, location) in networks {
+        if let Some(location) = location.as_deref().filter(|location| !rules.is_foreign(Some(location))) {
+            locations.entry(location).or_default().push(*block);
+        }
+    }
+    // 位置ごとに過不足なく結合し (/24 に丸めると国内でない範囲まで含んでしまう)、アドレス順に並べる
+    let mut domestic: Vec<(NetworkBlock, &str)> = locations
+        .iter()
+        .flat_map(|(location, blocks)| union_blocks(blocks).into_iter().map(move |block| (block, *location)))
+        .collect();
+    domestic.sort_by_key(|(block, _)| block.network);
+    let mut out = String::new();
+    let _ = writeln!(out, "# RFC 8805 geofeed (ipcheck)");
+    let _ = writeln!(out, "# データベース作成日時: {}", format_epoch(build_epoch));
+    for (block, location) in &domestic {
+        let (country, subdivision) = split_location(location);
+        let _ = writeln!(out, "{},{},{},,", block.to_string(), country, subdivision.unwrap_or(""));
+    }
+    out
+}
+
+#[test]
+fn test_geofeed_exact() {
+    let block = |cidr: &str| -> NetworkBlock { cidr.parse().unwrap() };
+    let networks = vec![
+        (block("1.0.0.0/25"), Some("JP".to_string())),
+        (block("1.0.0.128/25"), Some("CN".to_string())),
+        (block("1.0.1.5/32"), Some("JP".to_string())),
+        (block("1.0.1.4/32"), Some("JP".to_string())),
+        (block("2.0.0.0/16"), None),
+    ];
+    let feed = render(&networks, &Rules::default(), 0);
+    let rows: Vec<&str> = feed.lines().filter(|line| !line.starts_with('#')).collect();
+    assert_eq!(rows, ["1.0.0.0/25,JP,,,", "1.0.1.4/31,JP,,,"]);
+}
@@ -0,0 +1,81 @@
+use ipcheck_core::rules::Rules;
+
+use crate::NetworkBlock;
+
+const BACKGROUND: [u8; 3] = [0x10, 0x10, 0x10];
+const DOMESTIC: [u8; 3] = [0x2e, 0x7d, 0x32];
+const FOREIGN: [u8; 3] = [0xd3, 0x2f, 0x2f];
+
+/// 次数の上限。13 で 8192 四方 (画素データ約 200 MB)、1 つ上げるごとに 4 倍になる
+pub const MAX_ORDER: u32 = 13;
+
+/// Hilbert 曲線上の距離 d を (x, y) 座標に変換する
+fn d2xy(order: u32, d: u32) -> (u32, u32) {
+    let (mut x, mut y) = (0u32, 0u32);
+    let mut t = d;
+    let mut s = 1u32;
+    while s < (1 << order) {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+fn paint(pixels: &mut [u8], order: u32, block: &NetworkBlock, color: [u8; 3]) {
+    let shift = 32 - 2 * order;
+    let side = 1usize << order;
+    for d in (block.network >> shift)..=(block.last() >> shift) {
+        let (x, y) = d2xy(order, d);
+        let offset = (y as usize * side + x as usize) * 3;
+        pixels[offset..offset + 3].copy_from_slice(&color);
+    }
+}
+
+/// IPv4 空間を Hilbert 曲線で 2^order 四方の画像に配置し PNG として返す
+///
+/// 国内ネットワークは緑、ブロック対象 (海外) は赤、データなしは黒で塗る。
+pub fn render(networks: &[(NetworkBlock, Option<String>)], rules: &Rules, foreign_blocks: &[NetworkBlock], order: u32) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if !(1..=MAX_ORDER).contains(&order) {
+        return Err(format!("Hilbert 曲線の次数は 1〜{} の範囲で指定してください", MAX_ORDER).into());
+    }
+
+    let side = 1u32 << order;
+    let mut pixels = BACKGROUND.repeat((side * side) as usize);
+    for (block, iso_code) in networks {
+        if !rules.is_foreign(iso_code.as_deref()) {
+            paint(&mut pixels, order, block, DOMESTIC);
+        }
+    }
+    for block in foreign_blocks {
+        paint(&mut pixels, order, block, FOREIGN);
+    }
+
+    let mut buf = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut buf, side, side);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+    }
+    Ok(buf)
+}
+
+#[test]
+fn test_render_order_limit() {
+    let png = render(&[], &Rules::default(), &["1.0.0.0/8".parse().unwrap()], 4).unwrap();
+    assert_eq!(&png[1..4], b"PNG");
+    assert!(render(&[], &Rules::default(), &[], 0).is_err());
+    assert!(render(&[], &Rules::default(), &[], MAX_ORDER + 1).is_err());
+}
@@ -0,0 +1,102 @@
+use std::fmt::Write;
+
+use super::{Summary, format_epoch};
+use crate::NetworkBlock;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 4px 10px; text-align: right; }
+th { background: #f0f0f0; }
+td.label { text-align: left; }
+.bar { background: #d9534f; height: 12px; }
+#search { padding: 6px; width: 20em; margin-bottom: 1em; }
+";
+
+const SCRIPT: &str = "
+document.getElementById('search').addEventListener('input', function (e) {
+  var q = e.target.value.trim();
+  var rows = document.querySelectorAll('#cidrs tbody tr');
+  for (var i = 0; i < rows.length; i++) {
+    rows[i].style.display = rows[i].textContent.indexOf(q) === -1 ? 'none' : '';
+  }
+});
+";
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// 集計値と CIDR 一覧を 1 ファイルで完結する HTML レポートにする
+pub fn render(summary: &Summary, blocks: &[NetworkBlock]) -> String {
+    let mut html = String::new();
+    let total_space = 1u64 << 32;
+
+    html.push_str("<!DOCTYPE html>\n<html lang=\"ja\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>海外IP CIDR レポート</title>\n");
+    let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE);
+    html.push_str("<h1>海外IP CIDR レポート</h1>\n");
+
+    html.push_str("<h2>概要</h2>\n<table>\n");
+    let rows = [
+        ("データベース作成日時", format_epoch(summary.build_epoch)),
+        ("総ネットワーク数", summary.total_networks.to_string()),
+        ("日本のネットワーク", summary.domestic_networks.to_string()),
+        ("海外のネットワーク", summary.foreign_networks.to_string()),
+        ("最適化後のCIDR数", summary.foreign_cidrs.to_string()),
+        (
+            "海外アドレス数",
+            format!(
+                "{} ({:.2}%)",
+                summary.foreign_addresses,
+                summary.foreign_addresses as f64 * 100.0 / total_space as f64
+            ),
+        ),
+    ];
+    for (label, value) in rows {
+        let _ = writeln!(html, "<tr><td class=\"label\">{}</td><td>{}</td></tr>", label, escape(&value));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>プレフィックス長別統計</h2>\n<table>\n<tr><th>プレフィックス</th><th>ブロック数</th><th></th></tr>\n");
+    let max_count = summary.prefix_histogram.values().copied().max().unwrap_or(1);
+    for (prefix, count) in &summary.prefix_histogram {
+        let _ = writeln!(
+            html,
+            "<tr><td>/{}</td><td>{}</td><td class=\"label\"><div class=\"bar\" style=\"width: {}px\"></div></td></tr>",
+            prefix,
+            count,
+            count * 300 / max_count
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>国別内訳</h2>\n<table>\n<tr><th>国コード</th><th>ネットワーク数</th><th>アドレス数</th><th>割合</th></tr>\n");
+    for stat in &summary.countries {
+        let _ = writeln!(
+            html,
+            "<tr><td class=\"label\">{}</td><td>{}</td><td>{}</td><td>{:.2}%</td></tr>",
+            escape(&stat.code),
+            stat.networks,
+            stat.addresses,
+            stat.addresses as f64 * 100.0 / summary.foreign_addresses.max(1) as f64
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>CIDR 一覧</h2>\n<input id=\"search\" type=\"search\" placeholder=\"CIDR を検索\">\n");
+    html.push_str("<table id=\"cidrs\">\n<thead><tr><th>#</th><th>CIDR</th><th>アドレス数</th></tr></thead>\n<tbody>\n");
+    for (i, block) in blocks.iter().enumerate() {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"label\">{}</td><td>{}</td></tr>",
+            i + 1,
+            block.to_string(),
+            1u64 << (32 - block.prefix_len)
+        );
+    }
+    html.push_str("</tbody>\n</table>\n");
+
+    let _ = writeln!(html, "<script>{}</script>\n</body>\n</html>", SCRIPT);
+    html
+}
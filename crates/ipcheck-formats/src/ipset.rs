@@ -0,0 +1,54 @@
+use std::fmt::Write;
+
+use crate::NetworkBlock;
+
+/// `ipset restore` 用の差分。先に追加してから削除し、ブロックが一時的に外れる時間をなくす
+pub fn delta(set_name: &str, added: &[NetworkBlock], removed: &[NetworkBlock]) -> String {
+    let mut out = String::new();
+    for block in added {
+        let _ = writeln!(out, "add {} {} -exist", set_name, block.to_string());
+    }
+    for block in removed {
+        let _ = writeln!(out, "del {} {} -exist", set_name, block.to_string());
+    }
+    out
+}
+
+/// ipset のセット名の上限 (IPSET_MAXNAMELEN から終端の NUL を除いたもの)
+const MAX_NAME_LEN: usize = 31;
+
+/// セットの最大要素数。create -exist は既存のセットとパラメータが同じときしか通らず、swap で一時セットの
+/// パラメータが稼働中のセットに移るので、件数によって変えない (上限を超えるときだけ 2 のべき乗に切り上げる)
+const MAXELEM: usize = 1 << 20;
+
+/// `ipset restore` 用に一時セットへ全エントリを入れてから swap で入れ替え、古い中身を destroy する。
+/// 稼働中のセットを flush しないので、入れ替えの途中でセットが空になったり一部だけになったりしない。
+/// 前回の適用が途中で止まって一時セットが残っていても、flush してから使う
+pub fn swap(set_name: &str, blocks: &[NetworkBlock]) -> Result<String, String> {
+    let tmp = format!("{}-tmp", set_name);
+    if tmp.len() > MAX_NAME_LEN {
+        return Err(format!("ipset のセット名が長すぎます: {} (一時セット {} を含めて {} 文字まで)", set_name, tmp, MAX_NAME_LEN));
+    }
+    let maxelem = blocks.len().next_power_of_two().max(MAXELEM);
+    let mut out = String::new();
+    let _ = writeln!(out, "create {} hash:net family inet maxelem {} -exist", set_name, maxelem);
+    let _ = writeln!(out, "create {} hash:net family inet maxelem {} -exist", tmp, maxelem);
+    let _ = writeln!(out, "flush {}", tmp);
+    for block in blocks {
+        let _ = writeln!(out, "add {} {}", tmp, block.to_string());
+    }
+    let _ = writeln!(out, "swap {} {}", tmp, set_name);
+    let _ = writeln!(out, "destroy {}", tmp);
+    Ok(out)
+}
+
+#[test]
+fn test_ipset_swap() {
+    let small = swap("foreign", &["1.0.0.0/24".parse().unwrap()]).unwrap();
+    let large = swap("foreign", &vec!["1.0.0.0/24".parse().unwrap(); 70000]).unwrap();
+    // 件数が変わっても create の行は同じなので、既存のセットがあっても -exist で通る
+    assert_eq!(small.lines().next(), large.lines().next());
+    assert_eq!(small.lines().next(), Some("create foreign hash:net family inet maxelem 1048576 -exist"));
+    assert!(swap("a-set-name-that-is-28-chars-", &[]).is_err());
+    assert!(swap("a-set-name-that-is-27-chars", &[]).is_ok());
+}
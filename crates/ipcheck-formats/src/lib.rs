@@ -0,0 +1,175 @@
+pub mod bpf;
+pub mod geofeed;
+#[cfg(feature = "png")]
+pub mod hilbert;
+pub mod html;
+pub mod ipset;
+pub mod markdown;
+pub mod minecraft;
+pub mod mmdb;
+pub mod nft;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod redis;
+pub mod rpz;
+pub mod sshd;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod writer;
+
+use std::collections::{BTreeMap, HashMap};
+
+use ipcheck_core::binary::{ranges, subtract};
+use ipcheck_core::rules::Rules;
+use ipcheck_core::{NetworkBlock, range_to_blocks};
+use serde::{Deserialize, Serialize};
+
+pub use writer::{FormatOption, Metadata, OutputWriter, find, register, render, writers};
+
+/// 機械可読な出力 (JSON / MessagePack / CBOR / Protobuf と serve の応答) のスキーマの版。
+/// フィールドの意味や型を変えるときに上げ、フィールドの追加だけなら上げない。これを持たない出力は版 0 として読む
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// JSON / MessagePack / CBOR 出力の内容
+#[derive(Serialize, Deserialize)]
+pub struct Output {
+    #[serde(default)]
+    pub schema_version: u32,
+    pub foreign: Vec<String>,
+    /// 国の判定に加えてマージした第三者フィード
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub feeds: Vec<String>,
+}
+
+impl Output {
+    pub fn new(foreign: Vec<String>, feeds: Vec<String>) -> Self {
+        Output { schema_version: SCHEMA_VERSION, foreign, feeds }
+    }
+
+    pub fn check_version(self) -> Result<Self, String> {
+        check_schema_version(self.schema_version)?;
+        Ok(self)
+    }
+}
+
+/// 読み込んだ出力がこの版で解釈できるか。新しい版で書かれたものは黙って読み違えないよう拒否する
+pub fn check_schema_version(version: u32) -> Result<(), String> {
+    if version > SCHEMA_VERSION {
+        return Err(format!("スキーマの版 {} には対応していません (対応: {} 以下)。ipcheck を更新してください", version, SCHEMA_VERSION));
+    }
+    Ok(())
+}
+
+pub struct CountryStat {
+    pub code: String,
+    pub networks: usize,
+    pub addresses: u64,
+}
+
+/// レポート系の出力で共通に使う集計値
+pub struct Summary {
+    pub total_networks: usize,
+    pub domestic_networks: usize,
+    pub foreign_networks: usize,
+    pub foreign_cidrs: usize,
+    pub foreign_addresses: u64,
+    pub build_epoch: u64,
+    pub prefix_histogram: BTreeMap<u8, usize>,
+    /// 海外判定された国ごとの集計 (アドレス数の降順)
+    pub countries: Vec<CountryStat>,
+}
+
+impl Summary {
+    /// networks はデータベースの全ネットワークと位置コード、foreign_blocks は集約済みの海外ネットワーク
+    pub fn new(networks: &[(NetworkBlock, Option<String>)], rules: &Rules, foreign_blocks: &[NetworkBlock], build_epoch: u64) -> Self {
+        let mut countries: HashMap<&str, CountryStat> = HashMap::new();
+        let mut domestic_networks = 0;
+        for (block, iso_code) in networks {
+            if !rules.is_foreign(iso_code.as_deref()) {
+                domestic_networks += 1;
+                continue;
+            }
+            let code = iso_code.as_deref().unwrap_or("--");
+            let stat = countries.entry(code).or_insert_with(|| CountryStat {
+                code: code.to_string(),
+                networks: 0,
+                addresses: 0,
+            });
+            stat.networks += 1;
+            stat.addresses += 1u64 << (32 - block.prefix_len);
+        }
+        let mut countries: Vec<CountryStat> = countries.into_values().collect();
+        countries.sort_by(|a, b| b.addresses.cmp(&a.addresses).then(a.code.cmp(&b.code)));
+
+        let mut prefix_histogram = BTreeMap::new();
+        for block in foreign_blocks {
+            *prefix_histogram.entry(block.prefix_len).or_insert(0) += 1;
+        }
+
+        Summary {
+            total_networks: networks.len(),
+            domestic_networks,
+            foreign_networks: networks.len() - domestic_networks,
+            foreign_cidrs: foreign_blocks.len(),
+            foreign_addresses: foreign_blocks.iter().map(|b| 1u64 << (32 - b.prefix_len)).sum(),
+            build_epoch,
+            prefix_histogram,
+            countries,
+        }
+    }
+}
+
+/// UNIX 時刻を (年, 月, 日, その日の経過秒) に分解する
+pub fn civil_from_epoch(epoch: u64) -> (i64, i64, i64, u64) {
+    let days = (epoch / 86400) as i64;
+    let secs = epoch % 86400;
+
+    // Howard Hinnant の civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day, secs)
+}
+
+/// (年, 月, 日) の 0 時 UTC の UNIX 時刻。1970 年より前なら None
+pub fn epoch_from_civil(year: i64, month: i64, day: i64) -> Option<u64> {
+    // Howard Hinnant の days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    u64::try_from(days).ok().map(|d| d * 86400)
+}
+
+/// 海外リストの補集合 (データベースにないアドレスも含めて国内扱いになる範囲)
+pub fn complement(blocks: &[NetworkBlock]) -> Vec<NetworkBlock> {
+    subtract(&[(0, u32::MAX)], &ranges(blocks)).into_iter().flat_map(|(start, end)| range_to_blocks(start, end)).collect()
+}
+
+/// UNIX 時刻を "YYYY-MM-DD HH:MM:SS UTC" 形式に整形する
+pub fn format_epoch(epoch: u64) -> String {
+    let (year, month, day, secs) = civil_from_epoch(epoch);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
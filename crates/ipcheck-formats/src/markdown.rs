@@ -0,0 +1,70 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use super::{Summary, format_epoch};
+use crate::NetworkBlock;
+
+const TOP_COUNTRIES: usize = 10;
+
+fn address_count(blocks: &[NetworkBlock]) -> u64 {
+    blocks.iter().map(|b| 1u64 << (32 - b.prefix_len)).sum()
+}
+
+fn signed(delta: i64) -> String {
+    if delta > 0 { format!("+{}", delta) } else { delta.to_string() }
+}
+
+/// チャットやチケットに貼り付ける Markdown 形式のサマリーを生成する
+pub fn render(summary: &Summary, current: &[NetworkBlock], previous: Option<&[NetworkBlock]>) -> String {
+    let mut md = String::new();
+
+    md.push_str("# 海外IP CIDR 生成サマリー\n\n");
+    let _ = writeln!(md, "- データベース作成日時: {}", format_epoch(summary.build_epoch));
+    let _ = writeln!(md, "- 総ネットワーク数: {}", summary.total_networks);
+    let _ = writeln!(md, "- 日本のネットワーク: {}", summary.domestic_networks);
+    let _ = writeln!(md, "- 海外のネットワーク: {}", summary.foreign_networks);
+    let _ = writeln!(md, "- CIDR数: {}", summary.foreign_cidrs);
+    let _ = writeln!(md, "- 海外アドレス数: {}", summary.foreign_addresses);
+
+    if let Some(previous) = previous {
+        let old: HashSet<&NetworkBlock> = previous.iter().collect();
+        let new: HashSet<&NetworkBlock> = current.iter().collect();
+        let added = new.difference(&old).count();
+        let removed = old.difference(&new).count();
+        let old_addresses = address_count(previous);
+
+        md.push_str("\n## 前回からの変化\n\n");
+        md.push_str("| 項目 | 前回 | 今回 | 差分 |\n|---|---:|---:|---:|\n");
+        let _ = writeln!(
+            md,
+            "| CIDR数 | {} | {} | {} |",
+            previous.len(),
+            current.len(),
+            signed(current.len() as i64 - previous.len() as i64)
+        );
+        let _ = writeln!(
+            md,
+            "| アドレス数 | {} | {} | {} |",
+            old_addresses,
+            summary.foreign_addresses,
+            signed(summary.foreign_addresses as i64 - old_addresses as i64)
+        );
+        let _ = writeln!(md, "\n追加されたエントリ: {} / 削除されたエントリ: {}", added, removed);
+    }
+
+    md.push_str("\n## 上位の国\n\n");
+    md.push_str("| 順位 | 国コード | ネットワーク数 | アドレス数 | 割合 |\n|---:|---|---:|---:|---:|\n");
+    for (i, stat) in summary.countries.iter().take(TOP_COUNTRIES).enumerate() {
+        let _ = writeln!(
+            md,
+            "| {} | {} | {} | {} | {:.2}% |",
+            i + 1,
+            stat.code,
+            stat.networks,
+            stat.addresses,
+            stat.addresses as f64 * 100.0 / summary.foreign_addresses.max(1) as f64
+        );
+    }
+
+    md
+}
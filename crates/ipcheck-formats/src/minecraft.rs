@@ -0,0 +1,53 @@
+use std::fmt::Write;
+
+use super::{complement, format_epoch};
+use crate::NetworkBlock;
+
+/// allow なら国内の範囲 (海外リストの補集合) を whitelist に、そうでなければ海外の範囲を blacklist にする
+fn list(allow: bool, blocks: &[NetworkBlock]) -> (&'static str, Vec<NetworkBlock>) {
+    if allow { ("whitelist", complement(blocks)) } else { ("blacklist", blocks.to_vec()) }
+}
+
+/// BungeeCord / Velocity で CIDR の一覧をファイルから読む IP フィルター向けの YAML。
+/// `mode` (whitelist / blacklist) と、同じ名前のキーに CIDR の配列を書く
+pub fn render(allow: bool, blocks: &[NetworkBlock], build_epoch: u64) -> String {
+    let (mode, list) = list(allow, blocks);
+    let mut out = String::new();
+    let _ = writeln!(out, "# ipcheck で生成した{}", if allow { "国内 IP アドレスの許可リスト" } else { "海外 IP アドレスの拒否リスト" });
+    if build_epoch != 0 {
+        let _ = writeln!(out, "# データベース作成日時: {}", format_epoch(build_epoch));
+    }
+    let _ = writeln!(out, "mode: {}", mode);
+    if list.is_empty() {
+        let _ = writeln!(out, "{}: []", mode);
+    } else {
+        let _ = writeln!(out, "{}:", mode);
+        for block in &list {
+            let _ = writeln!(out, "  - \"{}\"", block.to_string());
+        }
+    }
+    out
+}
+
+/// render と同じ構造の JSON。コメントを書けないので、データベース作成日時は build_epoch に入れる
+pub fn render_json(allow: bool, blocks: &[NetworkBlock], build_epoch: u64) -> String {
+    let (mode, list) = list(allow, blocks);
+    let mut value = serde_json::json!({ "mode": mode, mode: list.iter().map(|b| b.to_string()).collect::<Vec<_>>() });
+    if build_epoch != 0 {
+        value["build_epoch"] = build_epoch.into();
+    }
+    let mut out = serde_json::to_string_pretty(&value).unwrap_or_default();
+    out.push('\n');
+    out
+}
+
+#[test]
+fn test_minecraft_render() {
+    let blocks: Vec<NetworkBlock> = vec!["128.0.0.0/1".parse().unwrap()];
+    assert_eq!(render(false, &blocks, 0), "# ipcheck で生成した海外 IP アドレスの拒否リスト\nmode: blacklist\nblacklist:\n  - \"128.0.0.0/1\"\n");
+    assert!(render(true, &blocks, 0).ends_with("mode: whitelist\nwhitelist:\n  - \"0.0.0.0/1\"\n"));
+    assert!(render(true, &["0.0.0.0/0".parse().unwrap()], 0).ends_with("whitelist: []\n"));
+
+    let json: serde_json::Value = serde_json::from_str(&render_json(true, &blocks, 1700000000)).unwrap();
+    assert_eq!(json, serde_json::json!({ "mode": "whitelist", "whitelist": ["0.0.0.0/1"], "build_epoch": 1700000000 }));
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use ipcheck_core::rules::{self, Rules};
+
+use crate::NetworkBlock;
+
+const DATA_SECTION_SEPARATOR: [u8; 16] = [0; 16];
+const METADATA_START_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+
+/// MaxMind DB のデータセクションに書き込む値
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    String(String),
+    Uint16(u16),
+    Uint32(u32),
+    Uint64(u64),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Record {
+    Empty,
+    Node(usize),
+    Data(usize),
+}
+
+/// IPv4 専用の最小限な MaxMind DB ライター
+pub struct MmdbWriter {
+    database_type: String,
+    description: String,
+    build_epoch: u64,
+    nodes: Vec<[Record; 2]>,
+    data: Vec<u8>,
+    data_offsets: HashMap<Vec<u8>, usize>,
+}
+
+impl MmdbWriter {
+    pub fn new(database_type: &str, description: &str, build_epoch: u64) -> Self {
+        MmdbWriter {
+            database_type: database_type.to_string(),
+            description: description.to_string(),
+            build_epoch,
+            nodes: vec![[Record::Empty, Record::Empty]],
+            data: Vec::new(),
+            data_offsets: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, block: &NetworkBlock, value: &Value) {
+        let mut encoded = Vec::new();
+        encode_value(&mut encoded, value);
+        let offset = match self.data_offsets.get(&encoded) {
+            Some(&offset) => offset,
+            None => {
+                let offset = self.data.len();
+                self.data.extend_from_slice(&encoded);
+                self.data_offsets.insert(encoded, offset);
+                offset
+            }
+        };
+
+        if block.prefix_len == 0 {
+            self.nodes[0] = [Record::Data(offset), Record::Data(offset)];
+            return;
+        }
+
+        let mut node = 0;
+        for depth in 0..block.prefix_len {
+            let bit = ((block.network >> (31 - depth)) & 1) as usize;
+            if depth + 1 == block.prefix_len {
+                self.nodes[node][bit] = Record::Data(offset);
+                break;
+            }
+            node = match self.nodes[node][bit] {
+                Record::Node(next) => next,
+                inherited => {
+                    // 上位ネットワークのデータを子ノードへ引き継いでから分岐させる
+                    let next = self.nodes.len();
+                    self.nodes.push([inherited, inherited]);
+                    self.nodes[node][bit] = Record::Node(next);
+                    next
+                }
+            };
+        }
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let node_count = self.nodes.len();
+        let max_record = node_count + DATA_SECTION_SEPARATOR.len() + self.data.len();
+        let record_size: u16 = if max_record < (1 << 24) { 24 } else { 32 };
+
+        let resolve = |record: Record| -> u32 {
+            match record {
+                Record::Empty => node_count as u32,
+                Record::Node(index) => index as u32,
+                Record::Data(offset) => (node_count + DATA_SECTION_SEPARATOR.len() + offset) as u32,
+            }
+        };
+
+        let mut tree = Vec::with_capacity(node_count * record_size as usize / 4);
+        for [left, right] in &self.nodes {
+            let (left, right) = (resolve(*left), resolve(*right));
+            if record_size == 24 {
+                tree.extend_from_slice(&left.to_be_bytes()[1..]);
+                tree.extend_from_slice(&right.to_be_bytes()[1..]);
+            } else {
+                tree.extend_from_slice(&left.to_be_bytes());
+                tree.extend_from_slice(&right.to_be_bytes());
+            }
+        }
+
+        let metadata = Value::Map(vec![
+            ("binary_format_major_version".to_string(), Value::Uint16(2)),
+            ("binary_format_minor_version".to_string(), Value::Uint16(0)),
+            ("build_epoch".to_string(), Value::Uint64(self.build_epoch)),
+            ("database_type".to_string(), Value::String(self.database_type.clone())),
+            (
+                "description".to_string(),
+                Value::Map(vec![("en".to_string(), Value::String(self.description.clone()))]),
+            ),
+            ("ip_version".to_string(), Value::Uint16(4)),
+            ("languages".to_string(), Value::Array(vec![Value::String("en".to_string())])),
+            ("node_count".to_string(), Value::Uint32(node_count as u32)),
+            ("record_size".to_string(), Value::Uint16(record_size)),
+        ]);
+        let mut metadata_bytes = Vec::new();
+        encode_value(&mut metadata_bytes, &metadata);
+
+        out.write_all(&tree)?;
+        out.write_all(&DATA_SECTION_SEPARATOR)?;
+        out.write_all(&self.data)?;
+        out.write_all(METADATA_START_MARKER)?;
+        out.write_all(&metadata_bytes)?;
+        Ok(())
+    }
+}
+
+/// 海外ブロックに {"foreign": true} を割り当てた MaxMind DB を生成する
+pub fn encode_foreign(blocks: &[NetworkBlock], build_epoch: u64) -> io::Result<Vec<u8>> {
+    let mut writer = MmdbWriter::new("ipcheck-Foreign", "ipcheck foreign network classification", build_epoch);
+    let value = Value::Map(vec![("foreign".to_string(), Value::Bool(true))]);
+    for block in blocks {
+        writer.insert(block, &value);
+    }
+    let mut buf = Vec::new();
+    writer.write(&mut buf)?;
+    Ok(buf)
+}
+
+/// 全ネットワークに国コード (地域付きなら subdivisions も) と foreign フラグを書き込んだ MaxMind DB を生成する
+pub fn encode_country(networks: &[(NetworkBlock, Option<String>)], rules: &Rules, build_epoch: u64) -> io::Result<Vec<u8>> {
+    let mut writer = MmdbWriter::new("ipcheck-Foreign", "ipcheck foreign network classification", build_epoch);
+    for (block, iso_code) in networks {
+        let mut entries = Vec::new();
+        if let Some(code) = iso_code {
+            let (country, subdivision) = rules::split_location(code);
+            entries.push((
+                "country".to_string(),
+                Value::Map(vec![("iso_code".to_string(), Value::String(country.to_string()))]),
+            ));
+            if let Some((_, subdivision)) = subdivision.and_then(|s| s.split_once('-')) {
+                entries.push((
+                    "subdivisions".to_string(),
+                    Value::Array(vec![Value::Map(vec![("iso_code".to_string(), Value::String(subdivision.to_string()))])]),
+                ));
+            }
+        }
+        entries.push(("foreign".to_string(), Value::Bool(rules.is_foreign(iso_code.as_deref()))));
+        writer.insert(block, &Value::Map(entries));
+    }
+    let mut buf = Vec::new();
+    writer.write(&mut buf)?;
+    Ok(buf)
+}
+
+fn encode_control(out: &mut Vec<u8>, type_num: u8, size: usize) {
+    let (size_bits, extra): (u8, Vec<u8>) = if size < 29 {
+        (size as u8, vec![])
+    } else if size < 285 {
+        (29, vec![(size - 29) as u8])
+    } else if size < 65821 {
+        (30, ((size - 285) as u16).to_be_bytes().to_vec())
+    } else {
+        (31, ((size - 65821) as u32).to_be_bytes()[1..].to_vec())
+    };
+
+    if type_num <= 7 {
+        out.push((type_num << 5) | size_bits);
+    } else {
+        out.push(size_bits);
+        out.push(type_num - 7);
+    }
+    out.extend_from_slice(&extra);
+}
+
+fn encode_uint(out: &mut Vec<u8>, type_num: u8, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    encode_control(out, type_num, bytes.len() - skip);
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+fn encode_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Bool(b) => encode_control(out, 14, *b as usize),
+        Value::String(s) => {
+            encode_control(out, 2, s.len());
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Uint16(n) => encode_uint(out, 5, *n as u64),
+        Value::Uint32(n) => encode_uint(out, 6, *n as u64),
+        Value::Uint64(n) => encode_uint(out, 9, *n),
+        Value::Array(items) => {
+            encode_control(out, 11, items.len());
+            for item in items {
+                encode_value(out, item);
+            }
+        }
+        Value::Map(entries) => {
+            encode_control(out, 7, entries.len());
+            for (key, item) in entries {
+                encode_value(out, &Value::String(key.clone()));
+                encode_value(out, item);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_mmdb_roundtrip() {
+    use maxminddb::Reader;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[derive(serde::Deserialize)]
+    struct Foreign {
+        foreign: bool,
+    }
+
+    let mut writer = MmdbWriter::new("ipcheck-test", "test", 0);
+    let foreign = Value::Map(vec![("foreign".to_string(), Value::Bool(true))]);
+    writer.insert(&NetworkBlock::new(u32::from(Ipv4Addr::new(1, 0, 0, 0)), 8), &foreign);
+    let mut buf = Vec::new();
+    writer.write(&mut buf).unwrap();
+
+    let reader = Reader::from_source(buf).unwrap();
+    let hit: Foreign = reader.lookup(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))).unwrap();
+    assert!(hit.foreign);
+    assert!(reader.lookup::<Foreign>(IpAddr::V4(Ipv4Addr::new(2, 0, 0, 1))).is_err());
+}
@@ -0,0 +1,30 @@
+use std::fmt::Write;
+
+use crate::NetworkBlock;
+
+const ELEMENTS_PER_LINE: usize = 1000;
+
+fn elements(out: &mut String, verb: &str, table: &str, set_name: &str, blocks: &[NetworkBlock]) {
+    for chunk in blocks.chunks(ELEMENTS_PER_LINE) {
+        let list: Vec<String> = chunk.iter().map(|b| b.to_string()).collect();
+        let _ = writeln!(out, "{} element {} {} {{ {} }}", verb, table, set_name, list.join(", "));
+    }
+}
+
+/// `nft -f` 用の差分。1 ファイルが 1 トランザクションとして適用されるので削除を先に行う
+pub fn delta(table: &str, set_name: &str, added: &[NetworkBlock], removed: &[NetworkBlock]) -> String {
+    let mut out = String::new();
+    elements(&mut out, "delete", table, set_name, removed);
+    elements(&mut out, "add", table, set_name, added);
+    out
+}
+
+/// セットを作り直す `nft -f` 用スクリプト。flush と追加が 1 トランザクションで適用される
+pub fn full(table: &str, set_name: &str, blocks: &[NetworkBlock]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "add table {}", table);
+    let _ = writeln!(out, "add set {} {} {{ type ipv4_addr; flags interval; auto-merge; }}", table, set_name);
+    let _ = writeln!(out, "flush set {} {}", table, set_name);
+    elements(&mut out, "add", table, set_name, blocks);
+    out
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use ::parquet::data_type::{BoolType, ByteArray, ByteArrayType, Int32Type, Int64Type};
+use ::parquet::file::properties::WriterProperties;
+use ::parquet::file::writer::SerializedFileWriter;
+use ::parquet::schema::parser::parse_message_type;
+
+use ipcheck_core::rules::Rules;
+
+use crate::NetworkBlock;
+
+const SCHEMA: &str = "
+message network_classification {
+    REQUIRED BYTE_ARRAY network (UTF8);
+    REQUIRED INT32 prefix_len;
+    OPTIONAL BYTE_ARRAY country (UTF8);
+    REQUIRED BOOLEAN is_foreign;
+    REQUIRED INT64 address_count;
+}
+";
+
+/// ネットワーク単位の分類結果を Parquet 形式で書き出す
+pub fn write<W: std::io::Write + Send>(networks: &[(NetworkBlock, Option<String>)], rules: &Rules, out: W) -> Result<(), Box<dyn std::error::Error>> {
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let props = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(out, schema, props)?;
+
+    let network: Vec<ByteArray> = networks.iter().map(|(block, _)| ByteArray::from(block.to_string().as_str())).collect();
+    let prefix_len: Vec<i32> = networks.iter().map(|(block, _)| block.prefix_len as i32).collect();
+    let country: Vec<ByteArray> = networks.iter().filter_map(|(_, c)| c.as_deref().map(ByteArray::from)).collect();
+    let country_def: Vec<i16> = networks.iter().map(|(_, c)| c.is_some() as i16).collect();
+    let foreign: Vec<bool> = networks.iter().map(|(_, c)| rules.is_foreign(c.as_deref())).collect();
+    let address_count: Vec<i64> = networks.iter().map(|(block, _)| 1i64 << (32 - block.prefix_len)).collect();
+
+    let mut row_group = writer.next_row_group()?;
+    let mut index = 0;
+    while let Some(mut column) = row_group.next_column()? {
+        match index {
+            0 => column.typed::<ByteArrayType>().write_batch(&network, None, None)?,
+            1 => column.typed::<Int32Type>().write_batch(&prefix_len, None, None)?,
+            2 => column.typed::<ByteArrayType>().write_batch(&country, Some(&country_def), None)?,
+            3 => column.typed::<BoolType>().write_batch(&foreign, None, None)?,
+            _ => column.typed::<Int64Type>().write_batch(&address_count, None, None)?,
+        };
+        column.close()?;
+        index += 1;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
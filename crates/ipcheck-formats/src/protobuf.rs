@@ -0,0 +1,57 @@
+use prost::Message;
+
+use crate::NetworkBlock;
+
+/// proto/ipcheck.proto の内容 (`ipcheck proto-schema` で出力できる)
+pub const SCHEMA: &str = include_str!("../proto/ipcheck.proto");
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Network {
+    #[prost(fixed32, tag = "1")]
+    pub network: u32,
+    #[prost(uint32, tag = "2")]
+    pub prefix_len: u32,
+    #[prost(string, tag = "3")]
+    pub cidr: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Metadata {
+    #[prost(uint64, tag = "1")]
+    pub database_build_epoch: u64,
+    #[prost(uint64, tag = "2")]
+    pub generated_at: u64,
+    #[prost(string, tag = "3")]
+    pub generator: String,
+    #[prost(uint32, tag = "4")]
+    pub schema_version: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ForeignNetworks {
+    #[prost(message, optional, tag = "1")]
+    pub metadata: Option<Metadata>,
+    #[prost(message, repeated, tag = "2")]
+    pub networks: Vec<Network>,
+}
+
+/// generated_at が 0 なら生成日時のフィールドは書かれない
+pub fn encode(blocks: &[NetworkBlock], build_epoch: u64, generated_at: u64) -> Vec<u8> {
+    let message = ForeignNetworks {
+        metadata: Some(Metadata {
+            database_build_epoch: build_epoch,
+            generated_at,
+            generator: format!("ipcheck {}", env!("CARGO_PKG_VERSION")),
+            schema_version: crate::SCHEMA_VERSION,
+        }),
+        networks: blocks
+            .iter()
+            .map(|block| Network {
+                network: block.network,
+                prefix_len: block.prefix_len as u32,
+                cidr: block.to_string(),
+            })
+            .collect(),
+    };
+    message.encode_to_vec()
+}
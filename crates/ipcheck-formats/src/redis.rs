@@ -0,0 +1,114 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+
+use crate::NetworkBlock;
+
+const BATCH_SIZE: usize = 1000;
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum RedisMode {
+    /// SADD で CIDR 文字列のセットを作る
+    Set,
+    /// ZADD で開始アドレスをスコアにしたソート済みセットを作る
+    Zset,
+}
+
+impl std::str::FromStr for RedisMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "set" => Ok(RedisMode::Set),
+            "zset" => Ok(RedisMode::Zset),
+            s => Err(format!("redis-mode は set か zset です: {}", s)),
+        }
+    }
+}
+
+/// 一時キーへ投入してから RENAME するコマンド列を組み立てる
+pub fn commands(blocks: &[NetworkBlock], key: &str, mode: RedisMode) -> Vec<Vec<String>> {
+    let tmp_key = format!("{}:tmp", key);
+    let mut commands = vec![vec!["DEL".to_string(), tmp_key.clone()]];
+
+    for chunk in blocks.chunks(BATCH_SIZE) {
+        let mut command = match mode {
+            RedisMode::Set => vec!["SADD".to_string(), tmp_key.clone()],
+            RedisMode::Zset => vec!["ZADD".to_string(), tmp_key.clone()],
+        };
+        for block in chunk {
+            if let RedisMode::Zset = mode {
+                command.push(block.network.to_string());
+            }
+            command.push(block.to_string());
+        }
+        commands.push(command);
+    }
+
+    if blocks.is_empty() {
+        commands.push(vec!["DEL".to_string(), key.to_string()]);
+    } else {
+        commands.push(vec!["RENAME".to_string(), tmp_key, key.to_string()]);
+    }
+    commands
+}
+
+/// `redis-cli --pipe` にそのまま渡せる RESP 形式へエンコードする
+pub fn encode(commands: &[Vec<String>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for command in commands {
+        out.extend_from_slice(format!("*{}\r\n", command.len()).as_bytes());
+        for arg in command {
+            out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            out.extend_from_slice(arg.as_bytes());
+            out.extend_from_slice(b"\r\n");
+        }
+    }
+    out
+}
+
+/// redis://[:password@]host[:port][/db] へ直接コマンドを送信する
+pub fn push(url: &str, commands: &[Vec<String>]) -> Result<(), Box<dyn std::error::Error>> {
+    let rest = url.strip_prefix("redis://").ok_or("Redis URL は redis:// で始まる必要があります")?;
+    let (auth, rest) = match rest.rsplit_once('@') {
+        Some((auth, rest)) => (Some(auth), rest),
+        None => (None, rest),
+    };
+    let (addr, db) = match rest.split_once('/') {
+        Some((addr, db)) if !db.is_empty() => (addr, Some(db)),
+        Some((addr, _)) => (addr, None),
+        None => (rest, None),
+    };
+    let addr = if addr.contains(':') { addr.to_string() } else { format!("{}:6379", addr) };
+
+    let mut preamble = Vec::new();
+    if let Some(auth) = auth {
+        let mut command = vec!["AUTH".to_string()];
+        match auth.split_once(':') {
+            Some((user, password)) if !user.is_empty() => {
+                command.push(user.to_string());
+                command.push(password.to_string());
+            }
+            Some((_, password)) => command.push(password.to_string()),
+            None => command.push(auth.to_string()),
+        }
+        preamble.push(command);
+    }
+    if let Some(db) = db {
+        preamble.push(vec!["SELECT".to_string(), db.to_string()]);
+    }
+
+    let stream = TcpStream::connect(&addr)?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    for command in preamble.iter().chain(commands) {
+        writer.write_all(&encode(std::slice::from_ref(command)))?;
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if let Some(message) = line.strip_prefix('-') {
+            return Err(format!("Redis エラー ({}): {}", command[0], message.trim_end()).into());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::fmt::Write;
+
+use crate::NetworkBlock;
+
+pub const TTL: u32 = 300;
+pub const REFRESH: u32 = 3600;
+pub const RETRY: u32 = 600;
+pub const EXPIRE: u32 = 604800;
+
+/// SOA シリアル。DB の build_epoch から決めるので同じ DB からは同じ値になり、更新されると増える。
+/// 既存のリストから変換したときは生成日時を使う
+pub fn serial(build_epoch: u64, generated_at: u64) -> u32 {
+    match (build_epoch, generated_at) {
+        (0, 0) => 1,
+        (0, generated_at) => generated_at as u32,
+        (build_epoch, _) => build_epoch as u32,
+    }
+}
+
+/// rpz-ip トリガーの相対オーナー名 ("1.0.0.0/22" -> "22.0.0.0.1.rpz-ip")
+pub fn owner(block: &NetworkBlock) -> String {
+    let [a, b, c, d] = block.network.to_be_bytes();
+    format!("{}.{}.{}.{}.{}.rpz-ip", block.prefix_len, d, c, b, a)
+}
+
+/// rpz-ip のオーナー名を CIDR に戻す。ゾーン名の部分は無視する
+pub fn parse_owner(name: &str) -> Option<NetworkBlock> {
+    let labels: Vec<&str> = name.split('.').collect();
+    let rpz_ip = labels.iter().position(|l| l.eq_ignore_ascii_case("rpz-ip"))?;
+    let [prefix, d, c, b, a] = labels.get(..rpz_ip)? else { return None };
+    format!("{}.{}.{}.{}/{}", a, b, c, d, prefix).parse().ok()
+}
+
+/// 海外ブロックに NXDOMAIN (CNAME .) を割り当てた RPZ ゾーンファイル
+pub fn render(zone: &str, serial: u32, blocks: &[NetworkBlock]) -> String {
+    let zone = zone.trim_end_matches('.');
+    let mut out = String::new();
+    let _ = writeln!(out, "$ORIGIN {}.", zone);
+    let _ = writeln!(out, "$TTL {}", TTL);
+    let _ = writeln!(out, "@ SOA localhost. hostmaster.localhost. {} {} {} {} {}", serial, REFRESH, RETRY, EXPIRE, TTL);
+    let _ = writeln!(out, "@ NS localhost.");
+    for block in blocks {
+        let _ = writeln!(out, "{} CNAME .", owner(block));
+    }
+    out
+}
+
+#[test]
+fn test_rpz_owner_roundtrip() {
+    let block: NetworkBlock = "1.0.0.0/22".parse().unwrap();
+    assert_eq!(owner(&block), "22.0.0.0.1.rpz-ip");
+    assert!(parse_owner("22.0.0.0.1.rpz-ip.foreign.rpz.") == Some(block));
+    assert!(parse_owner("example.com").is_none());
+}
@@ -0,0 +1,82 @@
+use std::net::Ipv4Addr;
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use ipcheck_core::rules::Rules;
+
+use crate::NetworkBlock;
+
+pub struct QueryHit {
+    pub block: NetworkBlock,
+    pub country: Option<String>,
+    pub foreign: bool,
+}
+
+/// (start, end, country) の範囲テーブルを持つ SQLite データベースを書き出す
+pub fn write(networks: &[(NetworkBlock, Option<String>)], rules: &Rules, path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    if std::path::Path::new(path).exists() {
+        std::fs::remove_file(path)?;
+    }
+
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(
+        "CREATE TABLE networks (
+            start INTEGER NOT NULL,
+            end INTEGER NOT NULL,
+            prefix_len INTEGER NOT NULL,
+            country TEXT,
+            is_foreign INTEGER NOT NULL
+        );",
+    )?;
+
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO networks (start, end, prefix_len, country, is_foreign) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for (block, country) in networks {
+            stmt.execute(params![
+                block.network,
+                block.last(),
+                block.prefix_len,
+                country,
+                rules.is_foreign(country.as_deref()),
+            ])?;
+        }
+    }
+    tx.commit()?;
+
+    conn.execute_batch(
+        "CREATE UNIQUE INDEX networks_start ON networks (start);
+         CREATE INDEX networks_end ON networks (end);
+         CREATE INDEX networks_country ON networks (country);",
+    )?;
+    drop(conn);
+
+    Ok(std::fs::metadata(path)?.len() as usize)
+}
+
+pub fn query(path: &str, ip: Ipv4Addr) -> Result<Option<QueryHit>, Box<dyn std::error::Error>> {
+    let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let ip = u32::from(ip);
+    let hit = conn
+        .query_row(
+            "SELECT start, end, prefix_len, country, is_foreign FROM networks
+             WHERE start <= ?1 ORDER BY start DESC LIMIT 1",
+            params![ip],
+            |row| {
+                let end: u32 = row.get(1)?;
+                Ok((
+                    end,
+                    QueryHit {
+                        block: NetworkBlock::new(row.get(0)?, row.get(2)?),
+                        country: row.get(3)?,
+                        foreign: row.get(4)?,
+                    },
+                ))
+            },
+        )
+        .optional()?;
+
+    Ok(hit.filter(|(end, _)| *end >= ip).map(|(_, hit)| hit))
+}
@@ -0,0 +1,29 @@
+use std::fmt::Write;
+
+use super::{complement, format_epoch};
+use crate::NetworkBlock;
+
+/// sshd_config に Include する Match ブロック。海外以外の範囲を除いた全アドレス (IPv6 を含む) で DenyUsers * にする。
+/// 後に続く設定が Match の中に入らないよう、最後に Match all で全体の設定に戻す
+pub fn render(blocks: &[NetworkBlock], build_epoch: u64) -> String {
+    let allowed = complement(blocks);
+    let mut out = String::new();
+    let _ = writeln!(out, "# ipcheck で生成した sshd_config 用の設定 (国内 {} エントリ以外からの SSH ログインを拒否する)", allowed.len());
+    if build_epoch != 0 {
+        let _ = writeln!(out, "# データベース作成日時: {}", format_epoch(build_epoch));
+    }
+    let mut patterns = vec!["*".to_string()];
+    patterns.extend(allowed.iter().map(|block| format!("!{}", block.to_string())));
+    let _ = writeln!(out, "Match Address {}", patterns.join(","));
+    let _ = writeln!(out, "\tDenyUsers *");
+    let _ = writeln!(out, "Match all");
+    out
+}
+
+#[test]
+fn test_sshd_render() {
+    let blocks: Vec<NetworkBlock> = vec!["0.0.0.0/1".parse().unwrap(), "192.0.0.0/2".parse().unwrap()];
+    let config = render(&blocks, 0);
+    assert!(config.ends_with("Match Address *,!128.0.0.0/2\n\tDenyUsers *\nMatch all\n"));
+    assert!(config.starts_with("# ipcheck で生成した sshd_config 用の設定 (国内 1 エントリ"));
+}
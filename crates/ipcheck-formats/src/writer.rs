@@ -0,0 +1,862 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::RwLock;
+
+use ipcheck_core::rules::Rules;
+use ipcheck_core::{CidrSet, NetworkBlock};
+
+use crate::redis::RedisMode;
+use crate::{Output, Summary, bpf, geofeed, html, ipset, markdown, minecraft, mmdb, nft, redis, rpz, sshd};
+
+type WriteResult = Result<(), Box<dyn std::error::Error>>;
+
+/// 出力に添える情報と、形式ごとの設定
+#[derive(Default)]
+pub struct Metadata<'a> {
+    /// 元データベースのビルド日時 (UNIX 時刻)。既存のリストから変換するときは 0
+    pub build_epoch: u64,
+    /// 出力に記録する生成日時 (UNIX 時刻)。0 なら記録しない
+    pub generated_at: u64,
+    /// 国の判定に加えてマージした第三者フィードの名前 (メタデータに記録する)
+    pub feeds: Vec<&'a str>,
+    /// データベースの全ネットワークと位置コード。既存のリストから変換するときは空
+    pub networks: &'a [(NetworkBlock, Option<String>)],
+    /// networks の海外・国内の判定に使う規則。リストを作ったときと同じものを渡す
+    pub rules: Rules,
+    /// 形式ごとのオプション (名前 → 値)。受け付ける名前と既定値は各形式の OutputWriter::options が示す
+    pub options: BTreeMap<String, String>,
+    /// markdown で差分を示す前回のリスト
+    pub previous: Option<&'a [NetworkBlock]>,
+}
+
+impl Metadata<'_> {
+    /// writer のオプション name の値。指定がなければ writer が示す既定値 (示していなければ空)
+    pub fn option(&self, writer: &dyn OutputWriter, name: &str) -> &str {
+        match self.options.get(name) {
+            Some(value) => value,
+            None => writer.options().iter().find(|option| option.name == name).map_or("", |option| option.default),
+        }
+    }
+}
+
+/// 形式が受け付けるオプション。CLI では --format-option NAME=VALUE や --also の ,NAME=VALUE で指定する
+pub struct FormatOption {
+    pub name: &'static str,
+    pub default: &'static str,
+    /// --help に表示する説明
+    pub help: &'static str,
+}
+
+const SET_NAME: FormatOption = FormatOption { name: "set-name", default: "foreign", help: "ipset / nft のセット名" };
+const NFT_TABLE: FormatOption = FormatOption { name: "nft-table", default: "inet filter", help: "nft のテーブル (ファミリー + テーブル名)" };
+const BPF_MAP: FormatOption = FormatOption { name: "bpf-map", default: "/sys/fs/bpf/ipcheck/foreign", help: "書き込む、ピン留めされた LPM trie マップ" };
+const MMDB_RECORD: FormatOption = FormatOption { name: "mmdb-record", default: "foreign", help: "foreign なら海外のみ {\"foreign\": true}、country なら全ネットワークに国コードを書く" };
+const REDIS_KEY: FormatOption = FormatOption { name: "redis-key", default: "ipcheck:foreign", help: "キー名" };
+const REDIS_MODE: FormatOption = FormatOption { name: "redis-mode", default: "set", help: "データ構造 (set / zset)" };
+const RPZ_ZONE: FormatOption = FormatOption { name: "rpz-zone", default: "foreign.rpz", help: "ゾーン名" };
+#[cfg(feature = "png")]
+const HILBERT_ORDER: FormatOption = FormatOption { name: "hilbert-order", default: "12", help: "Hilbert 曲線の次数 (1〜13)" };
+const MINECRAFT_MODE: FormatOption = FormatOption { name: "minecraft-mode", default: "deny", help: "deny なら海外の拒否リスト、allow なら国内の許可リスト" };
+
+fn minecraft_allow(mode: &str) -> Result<bool, String> {
+    match mode {
+        "deny" => Ok(false),
+        "allow" => Ok(true),
+        mode => Err(format!("minecraft-mode は deny か allow です: {}", mode)),
+    }
+}
+
+/// 出力形式。実装して register すると --format / --to / serve で使えるようになる
+pub trait OutputWriter: Send + Sync {
+    /// --format や --to で指定する名前
+    fn name(&self) -> &'static str;
+
+    /// -o を省略したときの出力ファイルの拡張子
+    fn extension(&self) -> &'static str;
+
+    /// 進捗表示に使う名前
+    fn label(&self) -> &'static str {
+        self.name()
+    }
+
+    /// --help に表示する説明
+    fn description(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// serve で配信するときの Content-Type
+    fn content_type(&self) -> &'static str {
+        "application/octet-stream"
+    }
+
+    /// 受け付けるオプション (Metadata::option で読む)
+    fn options(&self) -> &'static [FormatOption] {
+        &[]
+    }
+
+    /// 海外リストだけでなく全ネットワークの国コード (Metadata::networks) を使うか。
+    /// 使う形式は既存のリストからの変換や serve の配信には使えない
+    fn needs_networks(&self, _metadata: &Metadata) -> bool {
+        false
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult;
+}
+
+/// 書き出した内容をバイト列で返す
+pub fn render(writer: &dyn OutputWriter, set: &CidrSet, metadata: &Metadata) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut bytes = Vec::new();
+    writer.write(set, metadata, &mut bytes)?;
+    Ok(bytes)
+}
+
+static REGISTERED: RwLock<Vec<&'static dyn OutputWriter>> = RwLock::new(Vec::new());
+
+/// 組み込み以外の形式を追加する。CLI の引数を解釈する前に呼ぶこと。同じ名前の組み込み形式は置き換える
+pub fn register(writer: Box<dyn OutputWriter>) {
+    REGISTERED.write().unwrap().push(Box::leak(writer));
+}
+
+/// 使える形式の一覧 (組み込み、登録順)
+pub fn writers() -> Vec<&'static dyn OutputWriter> {
+    with_registered(builtin(), &REGISTERED.read().unwrap())
+}
+
+/// 組み込みの形式のうち同じ名前が登録されているものを除き、登録された形式を後ろに足す
+fn with_registered(builtin: Vec<&'static dyn OutputWriter>, registered: &[&'static dyn OutputWriter]) -> Vec<&'static dyn OutputWriter> {
+    let mut writers: Vec<&'static dyn OutputWriter> = builtin.into_iter().filter(|b| !registered.iter().any(|r| r.name() == b.name())).collect();
+    writers.extend(registered.iter().copied());
+    writers
+}
+
+pub fn find(name: &str) -> Option<&'static dyn OutputWriter> {
+    writers().into_iter().find(|w| w.name() == name)
+}
+
+fn builtin() -> Vec<&'static dyn OutputWriter> {
+    vec![
+        &Json,
+        &Text,
+        &Nft,
+        &Ipset,
+        &Mmdb,
+        &Bpf,
+        #[cfg(feature = "sqlite")]
+        &Sqlite,
+        #[cfg(feature = "msgpack")]
+        &Msgpack,
+        #[cfg(feature = "cbor")]
+        &Cbor,
+        #[cfg(feature = "protobuf")]
+        &Protobuf,
+        &Redis,
+        &Rpz,
+        &Html,
+        #[cfg(feature = "png")]
+        &Png,
+        &Geofeed,
+        &Markdown,
+        &Minecraft,
+        &MinecraftJson,
+        &Sshd,
+        #[cfg(feature = "xlsx")]
+        &Xlsx,
+        #[cfg(feature = "parquet")]
+        &Parquet,
+        #[cfg(feature = "arrow")]
+        &Arrow,
+    ]
+}
+
+fn output(set: &CidrSet, metadata: &Metadata) -> Output {
+    Output::new(set.blocks().iter().map(|b| b.to_string()).collect(), metadata.feeds.iter().map(|f| f.to_string()).collect())
+}
+
+struct Json;
+
+impl OutputWriter for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn label(&self) -> &'static str {
+        "JSON"
+    }
+
+    fn content_type(&self) -> &'static str {
+        "application/json; charset=utf-8"
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        out.write_all(serde_json::to_string_pretty(&output(set, metadata))?.as_bytes())?;
+        Ok(())
+    }
+}
+
+struct Text;
+
+impl OutputWriter for Text {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn label(&self) -> &'static str {
+        "テキスト"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some("1 行 1 CIDR のテキスト")
+    }
+
+    fn content_type(&self) -> &'static str {
+        "text/plain; charset=utf-8"
+    }
+
+    fn write(&self, set: &CidrSet, _metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        for block in set.blocks() {
+            writeln!(out, "{}", block.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+struct Nft;
+
+impl OutputWriter for Nft {
+    fn name(&self) -> &'static str {
+        "nft"
+    }
+
+    fn extension(&self) -> &'static str {
+        "nft"
+    }
+
+    fn label(&self) -> &'static str {
+        "nftables"
+    }
+
+    fn content_type(&self) -> &'static str {
+        "text/plain; charset=utf-8"
+    }
+
+    fn options(&self) -> &'static [FormatOption] {
+        &[SET_NAME, NFT_TABLE]
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        out.write_all(nft::full(metadata.option(self, "nft-table"), metadata.option(self, "set-name"), set.blocks()).as_bytes())?;
+        Ok(())
+    }
+}
+
+struct Ipset;
+
+impl OutputWriter for Ipset {
+    fn name(&self) -> &'static str {
+        "ipset"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ipset"
+    }
+
+    fn content_type(&self) -> &'static str {
+        "text/plain; charset=utf-8"
+    }
+
+    fn options(&self) -> &'static [FormatOption] {
+        &[SET_NAME]
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        out.write_all(ipset::swap(metadata.option(self, "set-name"), set.blocks())?.as_bytes())?;
+        Ok(())
+    }
+}
+
+struct Bpf;
+
+impl OutputWriter for Bpf {
+    fn name(&self) -> &'static str {
+        "bpf"
+    }
+
+    fn extension(&self) -> &'static str {
+        "bpftool"
+    }
+
+    fn label(&self) -> &'static str {
+        "eBPFマップ"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some("XDP 用 LPM trie マップ (--bpf-map) へ書き込む bpftool batch file 用のコマンド")
+    }
+
+    fn content_type(&self) -> &'static str {
+        "text/plain; charset=utf-8"
+    }
+
+    fn options(&self) -> &'static [FormatOption] {
+        &[BPF_MAP]
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        out.write_all(bpf::full(metadata.option(self, "bpf-map"), set.blocks()).as_bytes())?;
+        Ok(())
+    }
+}
+
+struct Mmdb;
+
+impl OutputWriter for Mmdb {
+    fn name(&self) -> &'static str {
+        "mmdb"
+    }
+
+    fn extension(&self) -> &'static str {
+        "mmdb"
+    }
+
+    fn label(&self) -> &'static str {
+        "MMDB"
+    }
+
+    fn options(&self) -> &'static [FormatOption] {
+        &[MMDB_RECORD]
+    }
+
+    fn needs_networks(&self, metadata: &Metadata) -> bool {
+        metadata.option(self, "mmdb-record") == "country"
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        let country = match metadata.option(self, "mmdb-record") {
+            "foreign" => false,
+            "country" => true,
+            record => return Err(format!("mmdb-record は foreign か country です: {}", record).into()),
+        };
+        let bytes = if country {
+            mmdb::encode_country(metadata.networks, &metadata.rules, metadata.build_epoch)?
+        } else {
+            mmdb::encode_foreign(set.blocks(), metadata.build_epoch)?
+        };
+        out.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+struct Sqlite;
+
+#[cfg(feature = "sqlite")]
+impl OutputWriter for Sqlite {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn extension(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn label(&self) -> &'static str {
+        "SQLite"
+    }
+
+    fn needs_networks(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn write(&self, _set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        // SQLite はファイルにしか書けないので、一時ファイルを経由する
+        let path = std::env::temp_dir().join(format!("ipcheck-{}.sqlite", std::process::id()));
+        let result = crate::sqlite::write(metadata.networks, &metadata.rules, &path.to_string_lossy())
+            .and_then(|_| Ok(std::io::copy(&mut std::fs::File::open(&path)?, out)?));
+        let _ = std::fs::remove_file(&path);
+        result.map(|_| ())
+    }
+}
+
+#[cfg(feature = "msgpack")]
+struct Msgpack;
+
+#[cfg(feature = "msgpack")]
+impl OutputWriter for Msgpack {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn extension(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn label(&self) -> &'static str {
+        "MessagePack"
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        out.write_all(&rmp_serde::to_vec_named(&output(set, metadata))?)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cbor")]
+struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl OutputWriter for Cbor {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn extension(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn label(&self) -> &'static str {
+        "CBOR"
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        ciborium::into_writer(&output(set, metadata), out)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "protobuf")]
+struct Protobuf;
+
+#[cfg(feature = "protobuf")]
+impl OutputWriter for Protobuf {
+    fn name(&self) -> &'static str {
+        "protobuf"
+    }
+
+    fn extension(&self) -> &'static str {
+        "pb"
+    }
+
+    fn label(&self) -> &'static str {
+        "Protobuf"
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        out.write_all(&crate::protobuf::encode(set.blocks(), metadata.build_epoch, metadata.generated_at))?;
+        Ok(())
+    }
+}
+
+struct Redis;
+
+impl OutputWriter for Redis {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn extension(&self) -> &'static str {
+        "redis"
+    }
+
+    fn label(&self) -> &'static str {
+        "Redisパイプ"
+    }
+
+    fn content_type(&self) -> &'static str {
+        "text/plain; charset=utf-8"
+    }
+
+    fn options(&self) -> &'static [FormatOption] {
+        &[REDIS_KEY, REDIS_MODE]
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        let mode: RedisMode = metadata.option(self, "redis-mode").parse()?;
+        out.write_all(&redis::encode(&redis::commands(set.blocks(), metadata.option(self, "redis-key"), mode)))?;
+        Ok(())
+    }
+}
+
+struct Rpz;
+
+impl OutputWriter for Rpz {
+    fn name(&self) -> &'static str {
+        "rpz"
+    }
+
+    fn extension(&self) -> &'static str {
+        "rpz"
+    }
+
+    fn label(&self) -> &'static str {
+        "RPZゾーン"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some("DNS の Response Policy Zone")
+    }
+
+    fn options(&self) -> &'static [FormatOption] {
+        &[RPZ_ZONE]
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        out.write_all(rpz::render(metadata.option(self, "rpz-zone"), rpz::serial(metadata.build_epoch, metadata.generated_at), set.blocks()).as_bytes())?;
+        Ok(())
+    }
+}
+
+struct Html;
+
+impl OutputWriter for Html {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn extension(&self) -> &'static str {
+        "html"
+    }
+
+    fn label(&self) -> &'static str {
+        "HTMLレポート"
+    }
+
+    fn needs_networks(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        let summary = Summary::new(metadata.networks, &metadata.rules, set.blocks(), metadata.build_epoch);
+        out.write_all(html::render(&summary, set.blocks()).as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "png")]
+struct Png;
+
+#[cfg(feature = "png")]
+impl OutputWriter for Png {
+    fn name(&self) -> &'static str {
+        "png"
+    }
+
+    fn extension(&self) -> &'static str {
+        "png"
+    }
+
+    fn label(&self) -> &'static str {
+        "Hilbert曲線画像"
+    }
+
+    fn needs_networks(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn options(&self) -> &'static [FormatOption] {
+        &[HILBERT_ORDER]
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        let order = metadata.option(self, "hilbert-order").parse().map_err(|e| format!("hilbert-order: {}", e))?;
+        out.write_all(&crate::hilbert::render(metadata.networks, &metadata.rules, set.blocks(), order)?)?;
+        Ok(())
+    }
+}
+
+struct Geofeed;
+
+impl OutputWriter for Geofeed {
+    fn name(&self) -> &'static str {
+        "geofeed"
+    }
+
+    fn extension(&self) -> &'static str {
+        "csv"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some("国内ネットワークの RFC 8805 geofeed")
+    }
+
+    fn needs_networks(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn write(&self, _set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        out.write_all(geofeed::render(metadata.networks, &metadata.rules, metadata.build_epoch).as_bytes())?;
+        Ok(())
+    }
+}
+
+struct Markdown;
+
+impl OutputWriter for Markdown {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn extension(&self) -> &'static str {
+        "md"
+    }
+
+    fn label(&self) -> &'static str {
+        "Markdownサマリー"
+    }
+
+    fn needs_networks(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        let summary = Summary::new(metadata.networks, &metadata.rules, set.blocks(), metadata.build_epoch);
+        out.write_all(markdown::render(&summary, set.blocks(), metadata.previous).as_bytes())?;
+        Ok(())
+    }
+}
+
+struct Minecraft;
+
+impl OutputWriter for Minecraft {
+    fn name(&self) -> &'static str {
+        "minecraft"
+    }
+
+    fn extension(&self) -> &'static str {
+        "yml"
+    }
+
+    fn label(&self) -> &'static str {
+        "Minecraftプロキシ用リスト"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some("BungeeCord / Velocity の IP フィルター用 YAML。mode と CIDR の配列を書く (--minecraft-mode で許可リストか拒否リストかを選ぶ)")
+    }
+
+    fn content_type(&self) -> &'static str {
+        "application/yaml; charset=utf-8"
+    }
+
+    fn options(&self) -> &'static [FormatOption] {
+        &[MINECRAFT_MODE]
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        out.write_all(minecraft::render(minecraft_allow(metadata.option(self, "minecraft-mode"))?, set.blocks(), metadata.build_epoch).as_bytes())?;
+        Ok(())
+    }
+}
+
+struct MinecraftJson;
+
+impl OutputWriter for MinecraftJson {
+    fn name(&self) -> &'static str {
+        "minecraft-json"
+    }
+
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn label(&self) -> &'static str {
+        "Minecraftプロキシ用リスト (JSON)"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some("minecraft と同じ構造の JSON (設定を JSON で読むプラグイン向け)")
+    }
+
+    fn content_type(&self) -> &'static str {
+        "application/json; charset=utf-8"
+    }
+
+    fn options(&self) -> &'static [FormatOption] {
+        &[MINECRAFT_MODE]
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        out.write_all(minecraft::render_json(minecraft_allow(metadata.option(self, "minecraft-mode"))?, set.blocks(), metadata.build_epoch).as_bytes())?;
+        Ok(())
+    }
+}
+
+struct Sshd;
+
+impl OutputWriter for Sshd {
+    fn name(&self) -> &'static str {
+        "sshd"
+    }
+
+    fn extension(&self) -> &'static str {
+        "conf"
+    }
+
+    fn label(&self) -> &'static str {
+        "sshd_config"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some("sshd_config に Include する Match Address ブロック (国内以外からの SSH ログインだけを拒否する)")
+    }
+
+    fn content_type(&self) -> &'static str {
+        "text/plain; charset=utf-8"
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        out.write_all(sshd::render(set.blocks(), metadata.build_epoch).as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "xlsx")]
+struct Xlsx;
+
+#[cfg(feature = "xlsx")]
+impl OutputWriter for Xlsx {
+    fn name(&self) -> &'static str {
+        "xlsx"
+    }
+
+    fn extension(&self) -> &'static str {
+        "xlsx"
+    }
+
+    fn label(&self) -> &'static str {
+        "Excel"
+    }
+
+    fn needs_networks(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        let summary = Summary::new(metadata.networks, &metadata.rules, set.blocks(), metadata.build_epoch);
+        out.write_all(&crate::xlsx::render(&summary, set.blocks(), metadata.generated_at)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+struct Parquet;
+
+#[cfg(feature = "parquet")]
+impl OutputWriter for Parquet {
+    fn name(&self) -> &'static str {
+        "parquet"
+    }
+
+    fn extension(&self) -> &'static str {
+        "parquet"
+    }
+
+    fn label(&self) -> &'static str {
+        "Parquet"
+    }
+
+    fn needs_networks(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn write(&self, _set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        let mut bytes = Vec::new();
+        crate::parquet::write(metadata.networks, &metadata.rules, &mut bytes)?;
+        out.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "arrow")]
+struct Arrow;
+
+#[cfg(feature = "arrow")]
+impl OutputWriter for Arrow {
+    fn name(&self) -> &'static str {
+        "arrow"
+    }
+
+    fn extension(&self) -> &'static str {
+        "arrow"
+    }
+
+    fn label(&self) -> &'static str {
+        "Arrow IPC"
+    }
+
+    fn needs_networks(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn write(&self, _set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+        crate::arrow::write(metadata.networks, &metadata.rules, out)
+    }
+}
+
+#[test]
+fn test_register_overrides_builtin() {
+    struct Upper {
+        name: &'static str,
+    }
+    impl OutputWriter for Upper {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn extension(&self) -> &'static str {
+            "TXT"
+        }
+        fn options(&self) -> &'static [FormatOption] {
+            &[FormatOption { name: "label", default: "CIDR", help: "行頭に付ける文字列" }]
+        }
+        fn write(&self, set: &CidrSet, metadata: &Metadata, out: &mut dyn Write) -> WriteResult {
+            for block in set.blocks() {
+                writeln!(out, "{} {}", metadata.option(self, "label"), block.to_string())?;
+            }
+            Ok(())
+        }
+    }
+
+    // グローバルな登録は同じプロセスのほかのテストにも見えるので、組み込みと重ならない名前にする
+    register(Box::new(Upper { name: "test-upper" }));
+    let metadata = Metadata { options: [("set-name".to_string(), "blocked".to_string())].into(), ..Metadata::default() };
+    let set = CidrSet::from_blocks(vec!["1.0.0.0/24".parse().unwrap(), "1.0.1.0/24".parse().unwrap()]);
+    let upper = find("test-upper").unwrap();
+    assert_eq!(upper.extension(), "TXT");
+    assert_eq!(render(upper, &set, &metadata).unwrap(), b"CIDR 1.0.0.0/23\n");
+    let labeled = Metadata { options: [("label".to_string(), "deny".to_string())].into(), ..Metadata::default() };
+    assert_eq!(render(upper, &set, &labeled).unwrap(), b"deny 1.0.0.0/23\n");
+    assert_eq!(find("text").unwrap().extension(), "txt");
+    assert!(find("json").is_some());
+
+    // 組み込みと同じ名前で登録すると置き換わる
+    static TEXT: Upper = Upper { name: "text" };
+    let writers = with_registered(builtin(), &[&TEXT]);
+    let texts: Vec<_> = writers.iter().filter(|w| w.name() == "text").collect();
+    assert_eq!(texts.len(), 1);
+    assert_eq!(texts[0].extension(), "TXT");
+    // 指定のないオプションは形式ごとの既定値になる
+    let nft = find("nft").unwrap();
+    assert_eq!((metadata.option(nft, "set-name"), metadata.option(nft, "nft-table")), ("blocked", "inet filter"));
+}
+
+#[test]
+fn test_metadata_rules() {
+    // 同じプロセスの中でも、Metadata ごとに別の規則で判定する
+    let networks = vec![("1.0.0.0/24".parse().unwrap(), Some("KR".to_string())), ("2.0.0.0/24".parse().unwrap(), Some("JP".to_string()))];
+    let set = CidrSet::from_aggregated(Vec::new());
+    let domestic = |country: &str| {
+        let rules = Rules { domestic_countries: vec![country.to_string()], ..Rules::default() };
+        let metadata = Metadata { networks: &networks, rules, ..Metadata::default() };
+        let feed = String::from_utf8(render(find("geofeed").unwrap(), &set, &metadata).unwrap()).unwrap();
+        feed.lines().filter(|line| !line.starts_with('#')).map(str::to_string).collect::<Vec<_>>()
+    };
+    assert_eq!(domestic("KR"), ["1.0.0.0/24,KR,,,"]);
+    assert_eq!(domestic("JP"), ["2.0.0.0/24,JP,,,"]);
+}
@@ -0,0 +1,78 @@
+use std::net::Ipv4Addr;
+
+use rust_xlsxwriter::{DocProperties, ExcelDateTime, Format, Workbook, XlsxError};
+
+use super::{Summary, format_epoch};
+use crate::NetworkBlock;
+
+/// CIDR 一覧シートと統計シートを持つ xlsx ブックを生成する。作成日時は generated_at にする
+pub fn render(summary: &Summary, blocks: &[NetworkBlock], generated_at: u64) -> Result<Vec<u8>, XlsxError> {
+    let mut workbook = Workbook::new();
+    workbook.set_properties(&DocProperties::new().set_creation_datetime(&ExcelDateTime::from_timestamp(generated_at as i64)?));
+    let bold = Format::new().set_bold();
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("CIDR")?;
+    for (col, header) in ["CIDR", "ネットワーク", "先頭アドレス", "末尾アドレス", "プレフィックス長", "アドレス数"]
+        .iter()
+        .enumerate()
+    {
+        sheet.write_string_with_format(0, col as u16, *header, &bold)?;
+    }
+    for (i, block) in blocks.iter().enumerate() {
+        let row = i as u32 + 1;
+        sheet.write_string(row, 0, block.to_string())?;
+        sheet.write_string(row, 1, Ipv4Addr::from(block.network).to_string())?;
+        sheet.write_number(row, 2, block.network)?;
+        sheet.write_number(row, 3, block.last())?;
+        sheet.write_number(row, 4, block.prefix_len)?;
+        sheet.write_number(row, 5, (1u64 << (32 - block.prefix_len)) as f64)?;
+    }
+    sheet.set_column_width(0, 20)?;
+    sheet.set_column_width(1, 16)?;
+    sheet.set_column_width(2, 14)?;
+    sheet.set_column_width(3, 14)?;
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("統計")?;
+    sheet.set_column_width(0, 24)?;
+    let mut row = 0;
+    let rows: [(&str, String); 6] = [
+        ("データベース作成日時", format_epoch(summary.build_epoch)),
+        ("総ネットワーク数", summary.total_networks.to_string()),
+        ("日本のネットワーク", summary.domestic_networks.to_string()),
+        ("海外のネットワーク", summary.foreign_networks.to_string()),
+        ("CIDR数", summary.foreign_cidrs.to_string()),
+        ("海外アドレス数", summary.foreign_addresses.to_string()),
+    ];
+    for (label, value) in rows {
+        sheet.write_string_with_format(row, 0, label, &bold)?;
+        match value.parse::<f64>() {
+            Ok(number) => sheet.write_number(row, 1, number)?,
+            Err(_) => sheet.write_string(row, 1, value)?,
+        };
+        row += 1;
+    }
+
+    row += 1;
+    sheet.write_string_with_format(row, 0, "プレフィックス長", &bold)?;
+    sheet.write_string_with_format(row, 1, "ブロック数", &bold)?;
+    for (prefix, count) in &summary.prefix_histogram {
+        row += 1;
+        sheet.write_string(row, 0, format!("/{}", prefix))?;
+        sheet.write_number(row, 1, *count as f64)?;
+    }
+
+    row += 2;
+    for (col, header) in ["国コード", "ネットワーク数", "アドレス数"].iter().enumerate() {
+        sheet.write_string_with_format(row, col as u16, *header, &bold)?;
+    }
+    for stat in &summary.countries {
+        row += 1;
+        sheet.write_string(row, 0, &stat.code)?;
+        sheet.write_number(row, 1, stat.networks as f64)?;
+        sheet.write_number(row, 2, stat.addresses as f64)?;
+    }
+
+    workbook.save_to_buffer()
+}
@@ -0,0 +1,71 @@
+//! ipcheck の Node.js アドオン。
+//!
+//! ```js
+//! const ipcheck = require("ipcheck");
+//! ipcheck.load("/var/lib/ipcheck/foreign.bin");
+//! ipcheck.isForeign("8.8.8.8"); // true
+//! ```
+//!
+//! リストを更新したら load を呼び直せば、判定中のリクエストを止めずに差し替わる
+
+use std::net::Ipv4Addr;
+use std::sync::{Arc, RwLock};
+
+use ipcheck_core::CidrSet;
+use napi::{Error, Result, Status};
+use napi_derive::napi;
+
+static CURRENT: RwLock<Option<Arc<CidrSet>>> = RwLock::new(None);
+
+fn parse_ip(ip: &str) -> Result<Ipv4Addr> {
+    ip.trim().parse().map_err(|_| Error::new(Status::InvalidArg, format!("IPv4 アドレスではありません: {}", ip)))
+}
+
+fn read(path: &str) -> Result<CidrSet> {
+    CidrSet::from_file(path).map_err(|e| Error::new(Status::GenericFailure, e))
+}
+
+/// isForeign が参照するリスト (--binary-sidecar の .bin またはテキスト) を読み込む。戻り値は集約後の CIDR の数
+#[napi]
+pub fn load(path: String) -> Result<u32> {
+    let set = read(&path)?;
+    let len = set.len() as u32;
+    *CURRENT.write().unwrap() = Some(Arc::new(set));
+    Ok(len)
+}
+
+/// ip が load したリストに含まれるか
+#[napi]
+pub fn is_foreign(ip: String) -> Result<bool> {
+    let ip = parse_ip(&ip)?;
+    let set = CURRENT.read().unwrap().clone();
+    match set {
+        Some(set) => Ok(set.contains(ip)),
+        None => Err(Error::new(Status::GenericFailure, "先に load でリストを読み込んでください".to_string())),
+    }
+}
+
+/// 複数のリストを使い分けるときのハンドル
+#[napi]
+pub struct ForeignList {
+    set: CidrSet,
+}
+
+#[napi]
+impl ForeignList {
+    #[napi(constructor)]
+    pub fn new(path: String) -> Result<Self> {
+        Ok(ForeignList { set: read(&path)? })
+    }
+
+    #[napi]
+    pub fn is_foreign(&self, ip: String) -> Result<bool> {
+        Ok(self.set.contains(parse_ip(&ip)?))
+    }
+
+    /// 集約後の CIDR の数
+    #[napi(getter)]
+    pub fn size(&self) -> u32 {
+        self.set.len() as u32
+    }
+}
@@ -0,0 +1,91 @@
+//! ipcheck の Python モジュール。
+//!
+//! ```python
+//! import ipcheck
+//! foreign = ipcheck.classify("GeoLite2-Country.mmdb")
+//! "8.8.8.8" in foreign          # True
+//! foreign.cidrs()               # ["1.0.0.0/24", ...]
+//! ipcheck.CidrSet.from_file("foreign.bin").contains("133.0.0.1")
+//! ```
+
+use std::net::Ipv4Addr;
+
+use ipcheck_core::rules::{self, Rules};
+use ipcheck_core::{CidrSet, NetworkBlock};
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+
+/// 集約済みの海外ネットワークの集合
+#[pyclass(name = "CidrSet", module = "ipcheck", frozen)]
+struct PyCidrSet(CidrSet);
+
+fn parse_ip(ip: &str) -> PyResult<Ipv4Addr> {
+    ip.trim().parse().map_err(|_| PyValueError::new_err(format!("IPv4 アドレスではありません: {}", ip)))
+}
+
+#[pymethods]
+impl PyCidrSet {
+    /// CIDR 文字列のリストから作る (集約される)
+    #[new]
+    fn new(cidrs: Vec<String>) -> PyResult<Self> {
+        let blocks = cidrs.iter().map(|c| c.parse::<NetworkBlock>()).collect::<Result<Vec<_>, _>>().map_err(PyValueError::new_err)?;
+        Ok(PyCidrSet(CidrSet::from_blocks(blocks)))
+    }
+
+    /// ipcheck が生成したバイナリ形式 (.bin) またはテキストのリストを読み込む
+    #[staticmethod]
+    fn from_file(path: &str) -> PyResult<Self> {
+        CidrSet::from_file(path).map(PyCidrSet).map_err(PyOSError::new_err)
+    }
+
+    fn contains(&self, ip: &str) -> PyResult<bool> {
+        Ok(self.0.contains(parse_ip(ip)?))
+    }
+
+    fn __contains__(&self, ip: &str) -> PyResult<bool> {
+        self.contains(ip)
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.len()
+    }
+
+    /// 集約済みの CIDR (アドレス順)
+    fn cidrs(&self) -> Vec<String> {
+        self.0.blocks().iter().map(|b| b.to_string()).collect()
+    }
+
+    /// binary 形式 (.bin) のバイト列
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<ipcheck.CidrSet {} cidrs>", self.0.len())
+    }
+}
+
+/// データベース (.mmdb) を走査して海外ネットワークの集合を作る。
+/// domestic_subdivisions を指定すると、国内のうちその地域 (例: "13" や "JP-13") だけを国内とする (City データベースが必要)
+#[pyfunction]
+#[pyo3(signature = (db, domestic_subdivisions = Vec::new()))]
+fn classify(py: Python<'_>, db: &str, domestic_subdivisions: Vec<String>) -> PyResult<PyCidrSet> {
+    let domestic_countries = Rules::default().domestic_countries;
+    let domestic_subdivisions = domestic_subdivisions
+        .iter()
+        .map(|s| rules::normalize_subdivision(s, &domestic_countries))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(PyValueError::new_err)?;
+    let rules = Rules { domestic_countries, domestic_subdivisions, ..Rules::default() };
+    let reader = maxminddb::Reader::open_readfile(db).map_err(|e| PyOSError::new_err(format!("{}: {}", db, e)))?;
+    // 走査は時間がかかるので GIL を離す
+    let set = py.allow_threads(|| ipcheck_core::classify(&reader, &rules)).map_err(|e| PyOSError::new_err(format!("{}: {}", db, e)))?;
+    Ok(PyCidrSet(set))
+}
+
+#[pymodule]
+fn ipcheck(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyCidrSet>()?;
+    m.add_function(wrap_pyfunction!(classify, m)?)?;
+    Ok(())
+}